@@ -1,9 +1,4 @@
-use std::{
-    io,
-    sync::mpsc::{self, Sender},
-    thread,
-    time::Duration,
-};
+use std::{io, sync::mpsc, thread, time::Duration};
 
 use crossterm::{
     event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
@@ -22,6 +17,7 @@ use ratatui::{
 use ratatui_image::{
     picker::Picker,
     protocol::{StatefulBlock, StatefulProtocol},
+    thread::ResizeWorker,
     Resize,
 };
 
@@ -31,11 +27,11 @@ struct App {
 
 enum AppEvent {
     KeyEvent(KeyEvent),
-    Redraw(StatefulBlock),
+    Redraw(Box<StatefulBlock>),
 }
 
 /// A widget that uses a custom ThreadProtocol as state to offload resizing and encoding to a
-/// background thread.
+/// [ResizeWorker].
 pub struct ThreadImage {
     resize: Resize,
 }
@@ -53,6 +49,12 @@ impl ThreadImage {
     }
 }
 
+impl Default for ThreadImage {
+    fn default() -> Self {
+        ThreadImage::new()
+    }
+}
+
 impl StatefulWidget for ThreadImage {
     type State = ThreadProtocol;
 
@@ -62,8 +64,10 @@ impl StatefulWidget for ThreadImage {
             Some(mut protocol) => {
                 // If it needs resizing (grow or shrink) then send it away instead of rendering.
                 if let Some(rect) = protocol.needs_resize(&self.resize, area) {
-                    state.tx.send((protocol, self.resize, rect)).unwrap();
-                    None
+                    state
+                        .worker
+                        .request(protocol, self.resize, None, rect)
+                        .err()
                 } else {
                     protocol.render(area, buf);
                     Some(protocol)
@@ -77,18 +81,17 @@ impl StatefulWidget for ThreadImage {
 
 /// The state of a ThreadImage.
 ///
-/// Has `inner` [ResizeProtocol] that is sent off to the `tx` mspc channel to do the
-/// `resize_encode()` work.
+/// Has `inner` [StatefulBlock] that is sent off to the `worker` to do the `resize_encode()` work.
 pub struct ThreadProtocol {
     inner: Option<StatefulBlock>,
-    tx: Sender<(StatefulBlock, Resize, Rect)>,
+    worker: ResizeWorker<StatefulBlock>,
 }
 
 impl ThreadProtocol {
-    pub fn new(tx: Sender<(StatefulBlock, Resize, Rect)>, inner: StatefulBlock) -> ThreadProtocol {
+    pub fn new(worker: ResizeWorker<StatefulBlock>, inner: StatefulBlock) -> ThreadProtocol {
         ThreadProtocol {
             inner: Some(inner),
-            tx,
+            worker,
         }
     }
 }
@@ -107,19 +110,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     picker.background_color = Some(Rgb::<u8>([255, 0, 255]));
     let dyn_img = image::io::Reader::open("./assets/Ada.png")?.decode()?;
 
-    // Send a [ResizeProtocol] to resize and encode it in a separate thread.
-    let (tx_worker, rec_worker) = mpsc::channel::<(StatefulBlock, Resize, Rect)>();
-
-    // Send UI-events and the [ResizeProtocol] result back to main thread.
+    // Send UI-events and the resized [StatefulBlock] back to main thread.
     let (tx_main, rec_main) = mpsc::channel();
 
     // Resize and encode in background thread.
     let tx_main_render = tx_main.clone();
-    thread::spawn(move || loop {
-        if let Ok((mut protocol, resize, area)) = rec_worker.recv() {
-            protocol.resize_encode(&resize, None, area);
-            tx_main_render.send(AppEvent::Redraw(protocol)).unwrap();
-        }
+    let worker = ResizeWorker::new(move |protocol| {
+        let _ = tx_main_render.send(AppEvent::Redraw(Box::new(protocol)));
     });
 
     // Poll events in background thread to demonstrate polling terminal events and redraw events
@@ -138,7 +135,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     let mut app = App {
-        async_state: ThreadProtocol::new(tx_worker, picker.new_resize_protocol(dyn_img)),
+        async_state: ThreadProtocol::new(worker, picker.new_resize_protocol(dyn_img)),
     };
 
     loop {
@@ -147,14 +144,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         if let Ok(ev) = rec_main.try_recv() {
             match ev {
                 AppEvent::KeyEvent(key) => {
-                    if key.kind == KeyEventKind::Press {
-                        if key.code == KeyCode::Esc {
-                            break;
-                        }
+                    if key.kind == KeyEventKind::Press && key.code == KeyCode::Esc {
+                        break;
                     }
                 }
                 AppEvent::Redraw(protocol) => {
-                    app.async_state.inner = Some(protocol);
+                    app.async_state.inner = Some(*protocol);
                 }
             }
         }
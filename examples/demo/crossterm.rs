@@ -32,10 +32,9 @@ pub fn run() -> Result<(), Box<dyn Error>> {
     let mut terminal = Terminal::new(backend)?;
 
     // TODO: set app.picker window size when PR is merged.
-    let app = App::new("Crossterm demo", &mut terminal);
-
     // run app
-    let res = run_app(&mut terminal, app);
+    let res =
+        App::new("Crossterm demo", &mut terminal).and_then(|app| Ok(run_app(&mut terminal, app)?));
 
     // restore terminal
     disable_raw_mode()?;
@@ -26,7 +26,7 @@ use ratatui::{
 };
 use ratatui_image::{
     picker::Picker,
-    protocol::{FixedBlock, ImageSource, Protocol, StatefulBlock, StatefulProtocol},
+    protocol::{FixedBlock, ImageSource, StatefulBlock},
     Image, Resize, StatefulImage,
 };
 
@@ -70,16 +70,15 @@ fn size() -> Rect {
 }
 
 impl<'a> App<'a> {
-    pub fn new<B: Backend>(title: &'a str, _: &mut Terminal<B>) -> App<'a> {
+    pub fn new<B: Backend>(title: &'a str, _: &mut Terminal<B>) -> Result<App<'a>, Box<dyn Error>> {
         let ada = "./assets/Ada.png";
-        let dyn_img = image::io::Reader::open(ada).unwrap().decode().unwrap();
+        let dyn_img = image::io::Reader::open(ada)?.decode()?;
 
-        let mut picker = Picker::from_termios().unwrap();
+        // Fall back to a guess, e.g. if the terminal does not report its size in pixels.
+        let mut picker = Picker::from_termios().unwrap_or_else(|_| Picker::new((8, 16)));
         picker.guess_protocol();
 
-        let image_static = picker
-            .new_protocol(dyn_img.clone(), size(), Resize::Fit(None))
-            .unwrap();
+        let image_static = picker.new_protocol(dyn_img.clone(), size(), Resize::Fit(None))?;
 
         let image_source = ImageSource::new(dyn_img.clone(), picker.font_size);
         let image_fit_state = picker.new_resize_protocol(dyn_img.clone());
@@ -103,7 +102,7 @@ impl<'a> App<'a> {
             background.push(c);
         }
 
-        App {
+        Ok(App {
             title,
             should_quit: false,
             tick_rate: Duration::from_millis(1000),
@@ -119,7 +118,7 @@ impl<'a> App<'a> {
             image_crop_state,
 
             image_static_offset: (0, 0),
-        }
+        })
     }
     pub fn on_key(&mut self, c: char) {
         match c {
@@ -142,33 +141,27 @@ impl<'a> App<'a> {
                     Some("./assets/Ada.png") => "./assets/Jenkins.jpg",
                     _ => "./assets/Ada.png",
                 };
-                let dyn_img = image::io::Reader::open(path).unwrap().decode().unwrap();
+                let Ok(dyn_img) = image::open(path) else {
+                    return;
+                };
                 self.image_source = ImageSource::new(dyn_img.clone(), self.picker.font_size);
                 self.image_source_path = path.into();
                 self.reset_images();
             }
-            'H' => {
-                if self.split_percent >= 10 {
-                    self.split_percent -= 10;
-                }
+            'H' if self.split_percent >= 10 => {
+                self.split_percent -= 10;
             }
-            'L' => {
-                if self.split_percent <= 90 {
-                    self.split_percent += 10;
-                }
+            'L' if self.split_percent <= 90 => {
+                self.split_percent += 10;
             }
-            'h' => {
-                if self.image_static_offset.0 > 0 {
-                    self.image_static_offset.0 -= 1;
-                }
+            'h' if self.image_static_offset.0 > 0 => {
+                self.image_static_offset.0 -= 1;
             }
             'j' => {
                 self.image_static_offset.1 += 1;
             }
-            'k' => {
-                if self.image_static_offset.1 > 0 {
-                    self.image_static_offset.1 -= 1;
-                }
+            'k' if self.image_static_offset.1 > 0 => {
+                self.image_static_offset.1 -= 1;
             }
             'l' => {
                 self.image_static_offset.0 += 1;
@@ -178,10 +171,13 @@ impl<'a> App<'a> {
     }
 
     fn reset_images(&mut self) {
-        self.image_static = self
-            .picker
-            .new_protocol(self.image_source.image.clone(), size(), Resize::Fit(None))
-            .unwrap();
+        // Keep the previous image if the protocol cannot encode this one.
+        if let Ok(image_static) =
+            self.picker
+                .new_protocol(self.image_source.image.clone(), size(), Resize::Fit(None))
+        {
+            self.image_static = image_static;
+        }
 
         self.image_fit_state = self
             .picker
@@ -247,7 +243,7 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
     match app.show_images {
         ShowImages::Fixed => {}
         _ => {
-            let image = StatefulImage::new(None).resize(Resize::Crop);
+            let image = StatefulImage::new(None).resize(Resize::Crop(None, None));
             f.render_stateful_widget(
                 image,
                 block_left_bottom.inner(chunks_left_bottom[0]),
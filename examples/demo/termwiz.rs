@@ -15,8 +15,8 @@ pub fn run() -> Result<(), Box<dyn Error>> {
     terminal.hide_cursor()?;
 
     // create app and run it
-    let app = App::new("Termwiz Demo", &mut terminal);
-    let res = run_app(&mut terminal, app);
+    let res =
+        App::new("Termwiz Demo", &mut terminal).and_then(|app| Ok(run_app(&mut terminal, app)?));
 
     terminal.show_cursor()?;
     terminal.flush()?;
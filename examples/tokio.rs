@@ -0,0 +1,117 @@
+use std::{io, thread, time::Duration};
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::Rect,
+    prelude::Backend,
+    terminal::Frame,
+    widgets::{Block, Borders, Paragraph},
+    Terminal,
+};
+use ratatui_image::{
+    picker::Picker,
+    protocol::{StatefulBlock, StatefulProtocol},
+    thread::resize_encode_async,
+    Resize, StatefulImage,
+};
+use tokio::sync::mpsc;
+
+/// Resize and encode with [resize_encode_async] in a tokio event loop, awaiting the result
+/// alongside the terminal events with `tokio::select!`.
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // setup terminal
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen,)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    let mut picker = Picker::from_termios()?;
+    picker.guess_protocol();
+    let dyn_img = image::io::Reader::open("./assets/Ada.png")?.decode()?;
+
+    // crossterm's events are blocking, so poll them on a thread and send them to the event loop.
+    let (tx_events, mut events) = mpsc::unbounded_channel();
+    thread::spawn(move || -> Result<(), std::io::Error> {
+        loop {
+            if event::poll(Duration::from_millis(1000))? && tx_events.send(event::read()?).is_err()
+            {
+                return Ok(());
+            }
+        }
+    });
+
+    // The protocol is moved into the future while it is resized and encoded.
+    let mut protocol = Some(picker.new_resize_protocol(dyn_img));
+    let mut pending = None;
+
+    loop {
+        let mut request = None;
+        terminal.draw(|f| request = ui(f, &mut protocol))?;
+        if let Some((state, rect)) = request {
+            pending = Some(resize_encode_async(state, Resize::Fit(None), None, rect));
+        }
+
+        tokio::select! {
+            Some(event) = events.recv() => {
+                if let Event::Key(key) = event {
+                    if key.kind == KeyEventKind::Press && key.code == KeyCode::Esc {
+                        break;
+                    }
+                }
+            }
+            state = async { pending.as_mut().unwrap().await }, if pending.is_some() => {
+                protocol = Some(state);
+                pending = None;
+            }
+        }
+    }
+
+    // restore terminal
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen,)?;
+    terminal.show_cursor()?;
+
+    Ok(())
+}
+
+/// Render the image if it is encoded for the area, or return it with the rect that it needs to be
+/// resized and encoded for.
+fn ui<B: Backend>(
+    f: &mut Frame<B>,
+    protocol: &mut Option<StatefulBlock>,
+) -> Option<(StatefulBlock, Rect)> {
+    let area = f.size();
+    let block = Block::default().borders(Borders::ALL).title("tokio");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let resize = Resize::Fit(None);
+    match protocol.take() {
+        Some(mut state) => match state.needs_resize(&resize, inner) {
+            Some(rect) => {
+                f.render_widget(Paragraph::new("Resizing…"), inner);
+                Some((state, rect))
+            }
+            None => {
+                f.render_stateful_widget(
+                    StatefulImage::new(None).resize(resize),
+                    inner,
+                    &mut state,
+                );
+                *protocol = Some(state);
+                None
+            }
+        },
+        None => {
+            f.render_widget(Paragraph::new("Resizing…"), inner);
+            None
+        }
+    }
+}
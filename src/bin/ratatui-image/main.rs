@@ -19,7 +19,7 @@ use ratatui::{
 };
 use ratatui_image::{
     picker::Picker,
-    protocol::{ImageSource, StatefulBlock, StatefulProtocol},
+    protocol::{ImageSource, StatefulBlock},
     Resize, StatefulImage,
 };
 
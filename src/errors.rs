@@ -1,14 +1,56 @@
+/// The errors of this crate.
+///
+/// Terminal queries like [crate::picker::Picker::from_termios], and creating protocols like
+/// [crate::picker::Picker::new_protocol], return these instead of panicking, so that apps can fall
+/// back e.g. to a default font size or to halfblocks on unpredictable terminals.
 #[derive(Debug, thiserror::Error)]
 pub enum Errors {
+    /// Reading or writing failed, e.g. an image file or the terminal.
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
+    /// Decoding an image failed, e.g. an unknown format or corrupt data, or encoding it as PNG or
+    /// JPEG for iTerm2 failed.
     #[error("Image error: {0}")]
     ImageError(#[from] image::error::ImageError),
+    /// A terminal query failed, e.g. the `TIOCGWINSZ` ioctl.
+    #[cfg(all(feature = "rustix", unix))]
     #[error("Rustix error: {0}")]
     RustixError(#[from] rustix::io::Errno),
+    /// Stdout is not a terminal, e.g. when piped or running under a test harness.
+    #[error("Not a terminal")]
+    NoTty,
+    /// The terminal did not report its size in pixels, so the font size is unknown.
+    #[error("Font size unknown")]
+    FontSizeUnknown,
+    /// A font size with a zero width or height was given.
+    #[error("Invalid font size: {0:?}")]
+    InvalidFontSize(crate::FontSize),
+    /// The area to encode the image for has no width or no height.
+    #[error("Zero-size area")]
+    ZeroSizeArea,
+    /// The image, after resizing, exceeds the size that the protocol can transmit.
+    ///
+    /// Only returned by the fixed [crate::protocol::kitty::Kitty] protocol, since kitty rejects
+    /// images wider or higher than 10000 pixels. Sixel images are shrunk to
+    /// [crate::protocol::sixel::SixelOptions::max_size] instead, and iTerm2 has no such limit.
+    /// Resize protocols cannot fail, so a [crate::protocol::kitty::StatefulKitty] that is larger
+    /// is transmitted anyway, and not shown by kitty.
+    #[error("Image too large for protocol: {width}x{height}")]
+    ImageTooLarge { width: u32, height: u32 },
+    /// The protocol's encoder failed, e.g. the sixel encoder.
+    #[error("Encoder failed: {0}")]
+    EncoderFailed(String),
+    /// A string that does not name a [crate::picker::ProtocolType], e.g. from a config file.
+    #[error(
+        "Unknown protocol {0:?}, expected one of halfblocks, sixel, kitty, iterm2, quadrants, \
+         sextants, braille, blank or text"
+    )]
+    UnknownProtocol(String),
+    /// Any other failure, e.g. a terminal that does not reply to a query.
     #[error("{0}")]
     Str(&'static str),
-    #[error("Sixel error: {0}")]
+    /// Any other failure with a message, e.g. from another library.
+    #[error("{0}")]
     CustomError(String),
 }
 
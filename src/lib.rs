@@ -4,28 +4,31 @@
 //! ratatui-image tackles 3 general problems when rendering images with an immediate-mode TUI:
 //!
 //! 1. **Query the terminal for available graphics protocols**.
-//! Some terminals may implement one or more graphics protocols, such as Sixels, or the iTerm2 or
-//! Kitty graphics protocols. Guess by env vars. If that fails, query the terminal with some
-//! control sequences.
-//! Fallback to "halfblocks" which uses some unicode half-block characters with fore- and
-//! background colors.
+//!    Some terminals may implement one or more graphics protocols, such as Sixels, or the iTerm2 or
+//!    Kitty graphics protocols. Guess by env vars. If that fails, query the terminal with some
+//!    control sequences.
+//!    Fallback to "halfblocks" which uses some unicode half-block characters with fore- and
+//!    background colors.
+//!    Terminals whose fonts have the quadrant or sextant block characters can opt in to a higher
+//!    resolution with [picker::ProtocolType::Quadrants] or [picker::ProtocolType::Sextants].
+//!    Terminals without colors can use the monochrome [picker::ProtocolType::Braille].
 //!
 //! 2. **Query the terminal for the font-size in pixels.**
-//! If there is an actual graphics protocol available, it is necessary to know the font-size to
-//! be able to map the image pixels to character cell area. The image can be resized, fit, or
-//! cropped to an area. Query the terminal for the window and columns/rows sizes, and derive the
-//! font-size.
+//!    If there is an actual graphics protocol available, it is necessary to know the font-size to
+//!    be able to map the image pixels to character cell area. The image can be resized, fit, or
+//!    cropped to an area. Query the terminal for the window and columns/rows sizes, and derive the
+//!    font-size.
 //!
 //! 3. **Render the image by the means of the guessed protocol.**
-//! Some protocols, like Sixels, are essentially "immediate-mode", but we still need to avoid the
-//! TUI from overwriting the image area, even with blank characters.
-//! Other protocols, like Kitty, are essentially stateful, but at least provide a way to re-render
-//! an image that has been loaded, at a different or same position.
+//!    Some protocols, like Sixels, are essentially "immediate-mode", but we still need to avoid the
+//!    TUI from overwriting the image area, even with blank characters.
+//!    Other protocols, like Kitty, are essentially stateful, but at least provide a way to re-render
+//!    an image that has been loaded, at a different or same position.
 //!
 //! # Quick start
 //! ```rust
 //! use ratatui::{backend::{Backend, TestBackend}, Terminal, terminal::Frame};
-//! use ratatui_image::{picker::Picker, StatefulImage, protocol::StatefulProtocol};
+//! use ratatui_image::{picker::Picker, StatefulImage, protocol::StatefulBlock};
 //!
 //! struct App {
 //!     // We need to hold the render state.
@@ -70,34 +73,55 @@
 //!
 //! # Widget choice
 //! * The [Image] widget does not adapt to rendering area (except not drawing at all if space
-//! is insufficient), may be a bit more bug prone (overdrawing or artifacts), and is not friendly
-//! with some of the protocols (e.g. the Kitty graphics protocol, which is stateful). Its big
-//! upside is that it is _stateless_ (in terms of ratatui, i.e. immediate-mode), and thus can never
-//! block the rendering thread/task. A lot of ratatui apps only use stateless widgets.
+//!   is insufficient), may be a bit more bug prone (overdrawing or artifacts), and is not friendly
+//!   with some of the protocols (e.g. the Kitty graphics protocol, which is stateful). Its big
+//!   upside is that it is _stateless_ (in terms of ratatui, i.e. immediate-mode), and thus can never
+//!   block the rendering thread/task. A lot of ratatui apps only use stateless widgets.
 //! * The [StatefulImage] widget adapts to its render area, is more robust against overdraw bugs and
-//! artifacts, and plays nicer with some of the graphics protocols.
-//! The resizing and encoding is blocking by default, but it is possible to offload this to another
-//! thread or async task (see `examples/async.rs`). It must be rendered with
-//! [`render_stateful_widget`] (i.e. with some mutable state).
+//!   artifacts, and plays nicer with some of the graphics protocols.
+//!   The resizing and encoding is blocking by default, but it is possible to offload this to another
+//!   thread or async task (see [thread::ResizeWorker] and `examples/async.rs`). It must be
+//!   rendered with [`render_stateful_widget`] (i.e. with some mutable state).
 //!
 //! # Examples
 //!
 //! * `examples/demo.rs` is a fully fledged demo.
-//! * `examples/async.rs` shows how to offload resize and encoding to another thread, to avoid
-//! blocking the UI thread.
+//! * `examples/async.rs` shows how to offload resize and encoding to a [thread::ResizeWorker], to
+//!   avoid blocking the UI thread.
 //!
 //! The lib also includes a binary that renders an image file, but it is focused on testing.
 //!
+//! # tmux
+//!
+//! Inside tmux, which [picker::Picker::from_termios] and [picker::Picker::guess_protocol] detect
+//! from `$TMUX`, `$TERM` or `$TERM_PROGRAM`, the sixel, kitty and iTerm2 escape sequences are
+//! wrapped in tmux's passthrough (`\ePtmux;...\e\\`, with the escapes inside doubled).
+//!
+//! tmux only passes them on with `allow-passthrough` enabled. The picker runs
+//! `tmux set -p allow-passthrough on` when it detects tmux, but if that fails, e.g. with an older
+//! tmux, add `set -g allow-passthrough on` to your `tmux.conf`.
+//!
+//! Set [picker::Picker::is_tmux] before creating protocols to force the wrapping on or off, e.g.
+//! when the detection fails in nested sessions. GNU screen has a different passthrough, which is
+//! not supported.
+//!
 //! # Features
 //! * `rustix` (default) enables much better guessing of graphics protocols with `rustix::termios::tcgetattr`.
 //! * `crossterm` or `termion` should match your ratatui backend. `termwiz` is available, but not
-//! working correctly with ratati-image.
+//!   working correctly with ratati-image.
+//! * `async` adds [thread::resize_encode_async], to await the resizing and encoding from any async
+//!   runtime.
 //! * `serde` for `#[derive]`s on [picker::ProtocolType] for convenience, because it might be
-//! useful to save it in some user configuration.
+//!   useful to save it in some user configuration.
 //! * `image-defaults` (default) just enables `image/defaults` (`image` has `default-features =
-//! false`). To only support a selection of image formats and cut down dependencies, disable this
-//! feature, add `image` to your crate, and enable its features/formats as desired. See
-//! https://doc.rust-lang.org/cargo/reference/features.html#feature-unification.
+//!   false`). To only support a selection of image formats and cut down dependencies, disable this
+//!   feature, add `image` to your crate, and enable its features/formats as desired. See
+//!   https://doc.rust-lang.org/cargo/reference/features.html#feature-unification.
+//!   JPEG and PNG are always supported, since the iTerm2 protocol encodes images in these formats.
+//! * `webp` enables `image/webp`, to decode WebP without `image-defaults`, e.g. with
+//!   [picker::Picker::new_protocol_from_bytes].
+//! * `avif` enables `image/avif-decoder`, to decode AVIF. It links to the `dav1d` system library,
+//!   which must be installed.
 //!
 //! [ratatui]: https://github.com/ratatui-org/ratatui
 //! [sixel]: https://en.wikipedia.org/wiki/Sixel
@@ -108,13 +132,14 @@ use image::{imageops, DynamicImage, ImageBuffer, Rgb};
 use protocol::{ImageSource, Protocol, StatefulProtocol};
 use ratatui::{
     buffer::Buffer,
-    layout::Rect,
+    layout::{Alignment, Rect},
     widgets::{StatefulWidget, Widget},
 };
 
 pub mod errors;
 pub mod picker;
 pub mod protocol;
+pub mod thread;
 pub use image::imageops::FilterType;
 
 type Result<T> = std::result::Result<T, errors::Errors>;
@@ -122,11 +147,45 @@ type Result<T> = std::result::Result<T, errors::Errors>;
 /// The terminal's font size in `(width, height)`
 pub type FontSize = (u16, u16);
 
+/// Convert a size in pixels to the number of cells that cover it, rounding up.
+///
+/// This is the same rounding that [protocol::ImageSource] uses for the size of an image.
+///
+/// ```rust
+/// assert_eq!((3, 2), ratatui_image::pixels_to_cells((10, 20), 21, 40));
+/// ```
+pub fn pixels_to_cells((char_width, char_height): FontSize, width: u32, height: u32) -> (u16, u16) {
+    let cells = |pixels: u32, char_size: u16| {
+        let char_size = max(char_size, 1) as u32;
+        let cells = pixels / char_size + (pixels % char_size != 0) as u32;
+        min(cells, u16::MAX as u32) as u16
+    };
+    (cells(width, char_width), cells(height, char_height))
+}
+
+/// Convert a size in cells to pixels.
+///
+/// ```rust
+/// assert_eq!((30, 40), ratatui_image::cells_to_pixels((10, 20), 3, 2));
+/// ```
+pub fn cells_to_pixels((char_width, char_height): FontSize, width: u16, height: u16) -> (u32, u32) {
+    (
+        width as u32 * char_width as u32,
+        height as u32 * char_height as u32,
+    )
+}
+
 /// Fixed size image widget that uses [Protocol].
 ///
 /// The widget does **not** react to area resizes, and is not even guaranteed to **not** overdraw.
 /// Its advantage lies in that the [Protocol] needs only one initial resize.
 ///
+/// Sixel and iTerm2 images are not drawn at all while they are only partially inside the buffer,
+/// e.g. in a scrolled viewport, since their escape sequences can only be drawn whole.
+///
+/// Since the image is encoded up front, its background color is set when creating the protocol,
+/// see [picker::Picker::background_color].
+///
 /// ```rust
 /// # use ratatui::{backend::Backend, terminal::Frame};
 /// # use ratatui_image::{Resize, Image, protocol::Protocol};
@@ -140,11 +199,22 @@ pub type FontSize = (u16, u16);
 /// ```
 pub struct Image<'a> {
     image: &'a dyn Protocol,
+    alignment: (Alignment, VerticalAlignment),
 }
 
 impl<'a> Image<'a> {
     pub fn new(image: &'a dyn Protocol) -> Image<'a> {
-        Image { image }
+        Image {
+            image,
+            alignment: (Alignment::Left, VerticalAlignment::Top),
+        }
+    }
+    /// Align the image within the area, if it is smaller than the area.
+    ///
+    /// Defaults to the top left.
+    pub fn alignment(mut self, horizontal: Alignment, vertical: VerticalAlignment) -> Image<'a> {
+        self.alignment = (horizontal, vertical);
+        self
     }
 }
 
@@ -154,6 +224,7 @@ impl<'a> Widget for Image<'a> {
             return;
         }
 
+        let area = align(self.image.rect(), area, self.alignment);
         self.image.render(area, buf);
     }
 }
@@ -162,14 +233,23 @@ impl<'a> Widget for Image<'a> {
 ///
 /// This stateful widget reacts to area resizes and resizes its image data accordingly.
 ///
+/// # Overlapping images
+///
+/// Sixel and iTerm2 images are pixels on top of the cells, that ratatui does not know about.
+/// Where the image is transparent, or where the terminal does not draw it, an image that was
+/// drawn there before stays visible. With [StatefulImage::overwrite], the cells of the image are
+/// erased right before it is drawn, so that e.g. a modal dialog's image is not ghosted over a
+/// background image. Kitty images and the text-based protocols are cells themselves, so they
+/// always overwrite what was there before.
+///
 /// ```rust
 /// # use ratatui::{backend::Backend, terminal::Frame};
-/// # use ratatui_image::{Resize, StatefulImage, protocol::{StatefulProtocol}};
+/// # use ratatui_image::{Resize, StatefulImage, protocol::StatefulBlock};
 /// struct App {
 ///     image_state: StatefulBlock,
 /// }
 /// fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
-///     let image = StatefulImage::new(None).resize(Resize::Crop);
+///     let image = StatefulImage::new(None).resize(Resize::Crop(None, None));
 ///     f.render_stateful_widget(
 ///         image,
 ///         f.size(),
@@ -180,6 +260,8 @@ impl<'a> Widget for Image<'a> {
 pub struct StatefulImage {
     resize: Resize,
     background_color: Option<Rgb<u8>>,
+    alignment: (Alignment, VerticalAlignment),
+    overwrite: bool,
 }
 
 impl StatefulImage {
@@ -187,12 +269,136 @@ impl StatefulImage {
         StatefulImage {
             resize: Resize::Fit(None),
             background_color,
+            alignment: (Alignment::Left, VerticalAlignment::Top),
+            overwrite: false,
         }
     }
+    /// Configure the widget step by step, see [StatefulImageBuilder].
+    ///
+    /// # Example
+    /// ```rust
+    /// use image::Rgb;
+    /// use ratatui::layout::Alignment;
+    /// use ratatui_image::{FilterType, Resize, StatefulImage, VerticalAlignment};
+    ///
+    /// let image = StatefulImage::builder()
+    ///     .resize(Resize::Fit(None))
+    ///     .background(Rgb([0, 0, 0]))
+    ///     .filter(FilterType::Lanczos3)
+    ///     .alignment(Alignment::Center, VerticalAlignment::Center)
+    ///     .build();
+    /// ```
+    pub fn builder() -> StatefulImageBuilder {
+        StatefulImageBuilder::default()
+    }
     pub fn resize(mut self, resize: Resize) -> StatefulImage {
         self.resize = resize;
         self
     }
+    /// Set the background color that transparent images are flattened against, and that pads the
+    /// image to the cell size.
+    ///
+    /// Most protocols, like sixel or halfblocks, cannot show transparency, so the image would
+    /// otherwise be composited against black.
+    pub fn background_color(mut self, color: Rgb<u8>) -> StatefulImage {
+        self.background_color = Some(color);
+        self
+    }
+    /// Align the resized image within the area, e.g. when [Resize::Fit] leaves empty space.
+    ///
+    /// Defaults to the top left.
+    pub fn alignment(
+        mut self,
+        horizontal: Alignment,
+        vertical: VerticalAlignment,
+    ) -> StatefulImage {
+        self.alignment = (horizontal, vertical);
+        self
+    }
+    /// Erase the cells of the image in the terminal before drawing a sixel or iTerm2 image, see
+    /// [overlapping images](StatefulImage#overlapping-images). Defaults to `false`.
+    ///
+    /// The erase is sent along with the image, i.e. whenever the image is drawn again.
+    pub fn overwrite(mut self, overwrite: bool) -> StatefulImage {
+        self.overwrite = overwrite;
+        self
+    }
+    /// Whether `state` is already resized and encoded for `area` with this widget's [Resize], so
+    /// that rendering does not block on resizing and encoding.
+    ///
+    /// For example, render a "loading…" placeholder instead of the widget until this is `true`,
+    /// while the resize and encode happens on a [thread::ResizeWorker].
+    pub fn is_current(&self, state: &mut impl StatefulProtocol, area: Rect) -> bool {
+        area.width == 0 || area.height == 0 || state.is_current(&self.resize, area)
+    }
+}
+
+/// Builder for [StatefulImage], see [StatefulImage::builder].
+///
+/// Unset options have the defaults of [StatefulImage::new] with no background color.
+#[derive(Clone, Copy, Debug)]
+pub struct StatefulImageBuilder {
+    resize: Resize,
+    background_color: Option<Rgb<u8>>,
+    filter: Option<FilterType>,
+    alignment: (Alignment, VerticalAlignment),
+    overwrite: bool,
+}
+
+impl Default for StatefulImageBuilder {
+    fn default() -> Self {
+        StatefulImageBuilder {
+            resize: Resize::Fit(None),
+            background_color: None,
+            filter: None,
+            alignment: (Alignment::Left, VerticalAlignment::Top),
+            overwrite: false,
+        }
+    }
+}
+
+impl StatefulImageBuilder {
+    /// See [StatefulImage::resize].
+    pub fn resize(mut self, resize: Resize) -> StatefulImageBuilder {
+        self.resize = resize;
+        self
+    }
+    /// See [StatefulImage::background_color].
+    pub fn background(mut self, color: Rgb<u8>) -> StatefulImageBuilder {
+        self.background_color = Some(color);
+        self
+    }
+    /// Set the [FilterType] of the [Resize], regardless of the order in which both are set, see
+    /// [Resize::filter].
+    pub fn filter(mut self, filter_type: FilterType) -> StatefulImageBuilder {
+        self.filter = Some(filter_type);
+        self
+    }
+    /// See [StatefulImage::alignment].
+    pub fn alignment(
+        mut self,
+        horizontal: Alignment,
+        vertical: VerticalAlignment,
+    ) -> StatefulImageBuilder {
+        self.alignment = (horizontal, vertical);
+        self
+    }
+    /// See [StatefulImage::overwrite].
+    pub fn overwrite(mut self, overwrite: bool) -> StatefulImageBuilder {
+        self.overwrite = overwrite;
+        self
+    }
+    pub fn build(self) -> StatefulImage {
+        StatefulImage {
+            resize: match self.filter {
+                Some(filter_type) => self.resize.filter(filter_type),
+                None => self.resize,
+            },
+            background_color: self.background_color,
+            alignment: self.alignment,
+            overwrite: self.overwrite,
+        }
+    }
 }
 
 impl StatefulWidget for StatefulImage {
@@ -202,11 +408,53 @@ impl StatefulWidget for StatefulImage {
             return;
         }
 
-        state.resize_encode_render(&self.resize, self.background_color, area, buf)
+        if let Some(rect) = state.needs_resize(&self.resize, area) {
+            state.resize_encode(&self.resize, self.background_color, rect);
+        }
+        let area = align(state.rect(), area, self.alignment);
+        state.render(area, buf);
+        if self.overwrite
+            && matches!(
+                state,
+                protocol::StatefulBlock::Sixel(_) | protocol::StatefulBlock::Iterm2(_)
+            )
+        {
+            protocol::erase(state.rect(), area, buf);
+        }
     }
 }
 
-#[derive(Debug)]
+/// Vertical alignment of an image within its area, the counterpart of ratatui's [Alignment], see
+/// [StatefulImage::alignment].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum VerticalAlignment {
+    #[default]
+    Top,
+    Center,
+    Bottom,
+}
+
+/// Shrink `area` from the top and left, so that the image's `rect` inside of it is aligned.
+///
+/// Graphics protocols position the image at the top left of the area, so the area itself has to
+/// move, rather than blank cells being drawn around the image.
+fn align(rect: Rect, area: Rect, (horizontal, vertical): (Alignment, VerticalAlignment)) -> Rect {
+    let free_width = area.width.saturating_sub(rect.x + rect.width);
+    let x = match horizontal {
+        Alignment::Left => 0,
+        Alignment::Center => free_width / 2,
+        Alignment::Right => free_width,
+    };
+    let free_height = area.height.saturating_sub(rect.y + rect.height);
+    let y = match vertical {
+        VerticalAlignment::Top => 0,
+        VerticalAlignment::Center => free_height / 2,
+        VerticalAlignment::Bottom => free_height,
+    };
+    Rect::new(area.x + x, area.y + y, area.width - x, area.height - y)
+}
+
+#[derive(Clone, Copy, Debug)]
 /// Resize method
 pub enum Resize {
     /// Fit to area.
@@ -214,7 +462,10 @@ pub enum Resize {
     /// If the width or height is smaller than the area, the image will be resized maintaining
     /// proportions.
     ///
-    /// The [FilterType] (re-exported from the [image] crate) defaults to [FilterType::Nearest].
+    /// The parameter is the [FilterType] (re-exported from the [image] crate) used for scaling,
+    /// and defaults to [FilterType::Nearest]. The cells around the image are left untouched, see
+    /// [Resize::Pad] to fill them with a color instead. Images are never upscaled, see
+    /// [Resize::Upscale] for that.
     Fit(Option<FilterType>),
     /// Crop to area.
     ///
@@ -223,10 +474,129 @@ pub enum Resize {
     /// but some terminals might misbehave when overdrawing characters over graphics.
     /// For example, the sixel branch of Alacritty never draws text over a cell that is currently
     /// being rendered by some sixel sequence, not necessarily originating from the same cell.
-    Crop,
+    ///
+    /// The [FilterType] is only used by protocols that need to further downsample the image, and
+    /// for images with a scale, see [ImageSource::with_scale]. It defaults to
+    /// [FilterType::Nearest]. The [CropAnchor] determines which part of the image is kept, and
+    /// defaults to [CropAnchor::TopLeft].
+    Crop(Option<FilterType>, Option<CropAnchor>),
+    /// Stretch to area.
+    ///
+    /// The image is resized to exactly fill the area, ignoring the aspect ratio.
+    ///
+    /// The [FilterType] defaults to [FilterType::Nearest].
+    Stretch(Option<FilterType>),
+    /// Center in area.
+    ///
+    /// If the image is smaller than the area, it is rendered at its native size and centered.
+    /// Otherwise, it is resized like [Resize::Fit] and then centered. The cells around the image
+    /// are left untouched.
+    ///
+    /// The centering offset is stored in the `x` and `y` fields of the encoded [Rect], relative
+    /// to the render area.
+    ///
+    /// The [FilterType] defaults to [FilterType::Nearest].
+    Center(Option<FilterType>),
+    /// Fill the area.
+    ///
+    /// The image is scaled maintaining proportions so that it covers the whole area, like CSS
+    /// `object-fit: cover`, and the overflow is cropped evenly from both sides to keep the image
+    /// centered.
+    ///
+    /// The [FilterType] defaults to [FilterType::Nearest].
+    Fill(Option<FilterType>),
+    /// Fit to the width of the area.
+    ///
+    /// The image is scaled maintaining proportions so that its width is exactly the area's width.
+    /// The height follows from the aspect ratio, and may overflow the area, e.g. for images in a
+    /// vertically scrolling list. The resulting size is available from the protocol's `rect()`.
+    ///
+    /// The image is clipped to the area when rendering, except by protocols that cannot clip an
+    /// image, like sixel and iTerm2, which draw it whole.
+    ///
+    /// The [FilterType] defaults to [FilterType::Nearest].
+    FitWidth(Option<FilterType>),
+    /// Fit to the height of the area.
+    ///
+    /// Like [Resize::FitWidth], but the height is the area's height and the width may overflow.
+    ///
+    /// The [FilterType] defaults to [FilterType::Nearest].
+    FitHeight(Option<FilterType>),
+    /// Fit to area, and fill the rest of the area with a color.
+    ///
+    /// The image is resized like [Resize::Fit] and centered, and the bars around it, including any
+    /// leftover pixels from rounding to the cell size, are filled with the color, e.g. for
+    /// panels with a brand color. Since the image is encoded with the bars, this works with every
+    /// protocol.
+    ///
+    /// The [FilterType] defaults to [FilterType::Nearest].
+    Pad(Option<FilterType>, Rgb<u8>),
+    /// Fit to area, upscaling by at most a factor.
+    ///
+    /// Like [Resize::Center], but images smaller than the area are scaled up to at most `factor`
+    /// times their native size, e.g. `2` for icons that should never be blown up beyond 2x and
+    /// become blurry. A factor of `0` or `1` never upscales. The image is centered, and the cells
+    /// around it are left untouched.
+    ///
+    /// The [FilterType] defaults to [FilterType::Nearest], which keeps integer upscaled pixel art
+    /// crisp.
+    Upscale(Option<FilterType>, u16),
+    /// Pan over the image at its native size.
+    ///
+    /// Shows the part of the image that starts at the `x` and `y` offset in cells and fits the
+    /// area, e.g. to scroll around a map or a large screenshot in a small viewport. The offset is
+    /// clamped so that at least one cell of the image is shown.
+    ///
+    /// Changing the offset re-crops the image on the next render, without scaling it. For the
+    /// fixed [Image] widget, create the protocol again with the new offset.
+    Pan(u16, u16),
 }
 
 impl Resize {
+    /// Set the [FilterType] used to scale the image.
+    ///
+    /// The filter is also used by protocols that need to further downsample the image, like
+    /// halfblocks. [Resize::Crop] only scales images with a scale, see [ImageSource::with_scale].
+    /// [Resize::Pan] never scales the image, so this has no effect on it.
+    ///
+    /// ```rust
+    /// use ratatui_image::{FilterType, Resize};
+    /// let resize = Resize::Fit(None).filter(FilterType::Lanczos3);
+    /// assert_eq!(Some(FilterType::Lanczos3), resize.filter_type());
+    /// let resize = Resize::Crop(None, None).filter(FilterType::Nearest);
+    /// assert_eq!(Some(FilterType::Nearest), resize.filter_type());
+    /// ```
+    pub fn filter(self, filter_type: FilterType) -> Resize {
+        match self {
+            Self::Fit(_) => Self::Fit(Some(filter_type)),
+            Self::Crop(_, anchor) => Self::Crop(Some(filter_type), anchor),
+            Self::Stretch(_) => Self::Stretch(Some(filter_type)),
+            Self::Center(_) => Self::Center(Some(filter_type)),
+            Self::Fill(_) => Self::Fill(Some(filter_type)),
+            Self::FitWidth(_) => Self::FitWidth(Some(filter_type)),
+            Self::FitHeight(_) => Self::FitHeight(Some(filter_type)),
+            Self::Pad(_, color) => Self::Pad(Some(filter_type), color),
+            Self::Upscale(_, factor) => Self::Upscale(Some(filter_type), factor),
+            Self::Pan(x, y) => Self::Pan(x, y),
+        }
+    }
+
+    /// The [FilterType] that has been set, if any.
+    pub fn filter_type(&self) -> Option<FilterType> {
+        match self {
+            Self::Fit(filter_type)
+            | Self::Stretch(filter_type)
+            | Self::Center(filter_type)
+            | Self::Fill(filter_type)
+            | Self::FitWidth(filter_type)
+            | Self::FitHeight(filter_type)
+            | Self::Pad(filter_type, _)
+            | Self::Upscale(filter_type, _)
+            | Self::Crop(filter_type, _) => *filter_type,
+            Self::Pan(_, _) => None,
+        }
+    }
+
     /// Resize if [`ImageSource`]'s "desired" doesn't fit into `area`, or is different than `current`
     fn resize(
         &self,
@@ -237,22 +607,59 @@ impl Resize {
         force: bool,
     ) -> Option<(DynamicImage, Rect)> {
         self.needs_resize(source, current, area, force).map(|rect| {
-            let width = (rect.width * source.font_size.0) as u32;
-            let height = (rect.height * source.font_size.1) as u32;
-            // Resize/Crop/etc. but not necessarily fitting cell size
-            let mut image = self.resize_image(source, width, height);
-            // Pad to cell size
-            if image.width() != width || image.height() != height {
-                static DEFAULT_BACKGROUND: Rgb<u8> = Rgb([0, 0, 0]);
-                let color = background_color.unwrap_or(DEFAULT_BACKGROUND);
-                let mut bg: DynamicImage = ImageBuffer::from_pixel(width, height, color).into();
-                imageops::overlay(&mut bg, &image, 0, 0);
-                image = bg;
-            }
+            let image = self.resize_to_rect(
+                &source.image,
+                source.font_size,
+                source.scale,
+                rect,
+                background_color,
+            );
             (image, rect)
         })
     }
 
+    /// Resize an image, e.g. a frame of an animation, to a rect returned by [Resize::needs_resize].
+    ///
+    /// `scale` is the [ImageSource::with_scale] of the image.
+    fn resize_to_rect(
+        &self,
+        image: &DynamicImage,
+        font_size: FontSize,
+        scale: f32,
+        rect: Rect,
+        background_color: Option<Rgb<u8>>,
+    ) -> DynamicImage {
+        let (width, height) = cells_to_pixels(font_size, rect.width, rect.height);
+        // Resize/Crop/etc. but not necessarily fitting cell size
+        let mut image = self.resize_image(image, font_size, scale, width, height);
+        // Pad to cell size, and flatten transparency if there is a background color
+        let pad = image.width() != width || image.height() != height;
+        let has_alpha = image.color().has_alpha();
+        let (background_color, x, y) = match self {
+            Self::Pad(_, color) => (
+                Some(*color),
+                (width - image.width()) / 2,
+                (height - image.height()) / 2,
+            ),
+            _ => (background_color, 0, 0),
+        };
+        let flatten = background_color.is_some() && has_alpha;
+        if pad || flatten {
+            let mut bg: DynamicImage = match background_color {
+                // Keep transparent images transparent for protocols that support it.
+                None if has_alpha => DynamicImage::new_rgba8(width, height),
+                color => {
+                    static DEFAULT_BACKGROUND: Rgb<u8> = Rgb([0, 0, 0]);
+                    let color = color.unwrap_or(DEFAULT_BACKGROUND);
+                    ImageBuffer::from_pixel(width, height, color).into()
+                }
+            };
+            imageops::overlay(&mut bg, &image, x.into(), y.into());
+            image = bg;
+        }
+        image
+    }
+
     /// Check if [`ImageSource`]'s "desired" fits into `area` and is different than `current`.
     pub fn needs_resize(
         &self,
@@ -263,9 +670,12 @@ impl Resize {
     ) -> Option<Rect> {
         let desired = image.desired;
         // Check if resize is needed at all.
-        if desired.width <= area.width && desired.height <= area.height && desired == current {
-            let width = (desired.width * image.font_size.0) as u32;
-            let height = (desired.height * image.font_size.1) as u32;
+        if matches!(self, Self::Fit(_) | Self::Crop(_, _))
+            && desired.width <= area.width
+            && desired.height <= area.height
+            && desired == current
+        {
+            let (width, height) = cells_to_pixels(image.font_size, desired.width, desired.height);
             if !force && (image.image.width() == width || image.image.height() == height) {
                 return None;
             }
@@ -278,19 +688,61 @@ impl Resize {
         None
     }
 
-    fn resize_image(&self, source: &ImageSource, width: u32, height: u32) -> DynamicImage {
+    fn resize_image(
+        &self,
+        image: &DynamicImage,
+        font_size: FontSize,
+        scale: f32,
+        width: u32,
+        height: u32,
+    ) -> DynamicImage {
         static DEFAULT_FILTER_TYPE: FilterType = FilterType::Nearest;
+        let filter_type = self.filter_type().unwrap_or(DEFAULT_FILTER_TYPE);
+        // Pixels of the scaled image, in pixels of the image.
+        let unscaled = |pixels: u32| (pixels as f32 * scale).round() as u32;
+        // Pixels of the image, in pixels of the scaled image.
+        let scaled = |pixels: u32| (pixels as f32 / scale).round() as u32;
         match self {
-            Self::Fit(filter_type) => {
-                source
-                    .image
-                    .resize(width, height, filter_type.unwrap_or(DEFAULT_FILTER_TYPE))
+            Self::Fit(_)
+            | Self::Center(_)
+            | Self::FitWidth(_)
+            | Self::FitHeight(_)
+            | Self::Upscale(_, _) => image.resize(width, height, filter_type),
+            // Never upscale beyond the scaled image, like Fit, since the width and height are the
+            // whole area.
+            Self::Pad(_, _) => image.resize(
+                min(width, scaled(image.width())),
+                min(height, scaled(image.height())),
+                filter_type,
+            ),
+            Self::Crop(_, anchor) => {
+                let (width, height) = (unscaled(width), unscaled(height));
+                let (x, y) = anchor.unwrap_or_default().offset(
+                    image.width().saturating_sub(width),
+                    image.height().saturating_sub(height),
+                );
+                crop_scaled(image, (x, y, width, height), scale, filter_type)
+            }
+            Self::Stretch(_) => image.resize_exact(width, height, filter_type),
+            Self::Fill(_) => image.resize_to_fill(width, height, filter_type),
+            Self::Pan(x, y) => {
+                // Clamp the offset to the scaled image, like Resize::needs_resize_rect.
+                let desired = ImageSource::round_pixel_size_to_cells(
+                    image.width(),
+                    image.height(),
+                    font_size,
+                    scale,
+                );
+                let (x, y) = pan_offset((*x, *y), desired.width, desired.height);
+                let (x, y) = cells_to_pixels(font_size, x, y);
+                let (x, y) = (unscaled(x), unscaled(y));
+                let crop = (x, y, unscaled(width), unscaled(height));
+                crop_scaled(image, crop, scale, filter_type)
             }
-            Self::Crop => source.image.crop_imm(0, 0, width, height),
         }
     }
 
-    fn needs_resize_rect(&self, desired: Rect, area: Rect) -> Rect {
+    pub(crate) fn needs_resize_rect(&self, desired: Rect, area: Rect) -> Rect {
         match self {
             Self::Fit(_) => {
                 let (width, height) = resize_pixels(
@@ -301,16 +753,126 @@ impl Resize {
                 );
                 Rect::new(0, 0, width, height)
             }
-            Self::Crop => Rect::new(
+            Self::Crop(_, _) => Rect::new(
                 0,
                 0,
                 min(desired.width, area.width),
                 min(desired.height, area.height),
             ),
+            Self::Stretch(_) | Self::Fill(_) | Self::Pad(_, _) => {
+                Rect::new(0, 0, area.width, area.height)
+            }
+            Self::FitWidth(_) => {
+                let (width, height) =
+                    resize_pixels(desired.width, desired.height, area.width, u16::MAX);
+                Rect::new(0, 0, width, height)
+            }
+            Self::FitHeight(_) => {
+                let (width, height) =
+                    resize_pixels(desired.width, desired.height, u16::MAX, area.height);
+                Rect::new(0, 0, width, height)
+            }
+            Self::Center(_) => {
+                let (width, height) = resize_pixels(
+                    desired.width,
+                    desired.height,
+                    min(area.width, desired.width),
+                    min(area.height, desired.height),
+                );
+                Rect::new(
+                    (area.width - width) / 2,
+                    (area.height - height) / 2,
+                    width,
+                    height,
+                )
+            }
+            Self::Pan(x, y) => {
+                let (x, y) = pan_offset((*x, *y), desired.width, desired.height);
+                Rect::new(
+                    0,
+                    0,
+                    min(desired.width - x, area.width),
+                    min(desired.height - y, area.height),
+                )
+            }
+            Self::Upscale(_, factor) => {
+                let factor = max(*factor, 1);
+                let (width, height) = resize_pixels(
+                    desired.width,
+                    desired.height,
+                    min(area.width, desired.width.saturating_mul(factor)),
+                    min(area.height, desired.height.saturating_mul(factor)),
+                );
+                Rect::new(
+                    (area.width - width) / 2,
+                    (area.height - height) / 2,
+                    width,
+                    height,
+                )
+            }
         }
     }
 }
 
+/// Crop `(x, y, width, height)` out of the image, and scale the cropped part by `1 / scale`, see
+/// [ImageSource::with_scale].
+fn crop_scaled(
+    image: &DynamicImage,
+    (x, y, width, height): (u32, u32, u32, u32),
+    scale: f32,
+    filter_type: FilterType,
+) -> DynamicImage {
+    let image = image.crop_imm(x, y, width, height);
+    if scale == 1.0 || image.width() == 0 || image.height() == 0 {
+        return image;
+    }
+    let scaled = |pixels: u32| max((pixels as f32 / scale).round() as u32, 1);
+    image.resize_exact(scaled(image.width()), scaled(image.height()), filter_type)
+}
+
+/// The offset of [Resize::Pan] in cells, clamped to the last cell of an image of `cols` and `rows`.
+fn pan_offset((x, y): (u16, u16), cols: u16, rows: u16) -> (u16, u16) {
+    (
+        min(x, cols.saturating_sub(1)),
+        min(y, rows.saturating_sub(1)),
+    )
+}
+
+/// The part of the image that is kept by [Resize::Crop].
+///
+/// For example, [CropAnchor::Top] keeps the top of portrait images, e.g. for avatar thumbnails.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CropAnchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl CropAnchor {
+    /// The offset of the kept part, given how many pixels are cropped horizontally and vertically.
+    fn offset(self, free_x: u32, free_y: u32) -> (u32, u32) {
+        let (x, y) = match self {
+            Self::TopLeft => (0, 0),
+            Self::Top => (1, 0),
+            Self::TopRight => (2, 0),
+            Self::Left => (0, 1),
+            Self::Center => (1, 1),
+            Self::Right => (2, 1),
+            Self::BottomLeft => (0, 2),
+            Self::Bottom => (1, 2),
+            Self::BottomRight => (2, 2),
+        };
+        (free_x * x / 2, free_y * y / 2)
+    }
+}
+
 /// Ripped from https://github.com/image-rs/image/blob/master/src/math/utils.rs#L12
 /// Calculates the width and height an image should be resized to.
 /// This preserves aspect ratio, and based on the `fill` parameter
@@ -341,7 +903,7 @@ fn resize_pixels(width: u16, height: u16, nwidth: u16, nheight: u16) -> (u16, u1
 
 #[cfg(test)]
 mod tests {
-    use image::{ImageBuffer, Rgb};
+    use image::{ImageBuffer, Rgb, Rgba};
 
     use super::*;
 
@@ -391,7 +953,7 @@ mod tests {
 
     #[test]
     fn needs_resize_crop() {
-        let resize = Resize::Crop;
+        let resize = Resize::Crop(None, None);
 
         let to = resize.needs_resize(&s(100, 100), r(10, 10), r(10, 10), false);
         assert_eq!(None, to);
@@ -405,4 +967,461 @@ mod tests {
         let to = resize.needs_resize(&s(100, 100), r(10, 10), r(10, 8), false);
         assert_eq!(Some(r(10, 8)), to);
     }
+
+    #[test]
+    fn crop_anchor() {
+        // Left half red, right half blue, 2x1 cells of 10x10 pixels.
+        let image: DynamicImage = ImageBuffer::from_fn(20, 10, |x, _| {
+            if x < 10 {
+                Rgb([255u8, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        })
+        .into();
+        let source = ImageSource::new(image, FONT_SIZE);
+
+        let (image, rect) = Resize::Crop(None, None)
+            .resize(&source, Rect::default(), r(1, 1), None, false)
+            .expect("resized");
+        assert_eq!(r(1, 1), rect);
+        assert_eq!(&Rgb([255, 0, 0]), image.to_rgb8().get_pixel(9, 5));
+
+        let (image, _) = Resize::Crop(None, Some(CropAnchor::Right))
+            .resize(&source, Rect::default(), r(1, 1), None, false)
+            .expect("resized");
+        assert_eq!(&Rgb([0, 0, 255]), image.to_rgb8().get_pixel(0, 5));
+
+        assert_eq!((5, 0), CropAnchor::Top.offset(10, 20));
+        assert_eq!((10, 10), CropAnchor::Center.offset(20, 20));
+        assert_eq!((10, 20), CropAnchor::BottomRight.offset(10, 20));
+    }
+
+    #[test]
+    fn needs_resize_stretch() {
+        let resize = Resize::Stretch(None);
+
+        let to = resize.needs_resize(&s(100, 100), r(10, 10), r(10, 10), false);
+        assert_eq!(None, to);
+
+        let to = resize.needs_resize(&s(100, 100), r(10, 10), r(20, 10), false);
+        assert_eq!(Some(r(20, 10)), to);
+
+        let to = resize.needs_resize(&s(100, 100), r(20, 10), r(20, 5), false);
+        assert_eq!(Some(r(20, 5)), to);
+
+        let to = resize.needs_resize(&s(50, 100), r(20, 5), r(20, 5), false);
+        assert_eq!(None, to);
+    }
+
+    #[test]
+    fn needs_resize_center() {
+        let resize = Resize::Center(None);
+
+        let to = resize.needs_resize(&s(100, 100), r(10, 10), r(10, 10), false);
+        assert_eq!(None, to);
+
+        let to = resize.needs_resize(&s(40, 20), r(4, 2), r(10, 10), false);
+        assert_eq!(Some(Rect::new(3, 4, 4, 2)), to);
+
+        let to = resize.needs_resize(&s(40, 20), Rect::new(3, 4, 4, 2), r(10, 10), false);
+        assert_eq!(None, to);
+
+        let to = resize.needs_resize(&s(100, 100), r(10, 10), r(20, 5), false);
+        assert_eq!(Some(Rect::new(7, 0, 5, 5)), to);
+    }
+
+    #[test]
+    fn pad() {
+        let resize = Resize::Pad(None, Rgb([0, 0, 255]));
+
+        let to = resize.needs_resize(&s(40, 20), r(4, 2), r(10, 10), false);
+        assert_eq!(Some(r(10, 10)), to);
+
+        let to = resize.needs_resize(&s(40, 20), r(10, 10), r(10, 10), false);
+        assert_eq!(None, to);
+
+        // 4x2 cells image in a 5x4 cells area: the bars and the leftover column are filled.
+        let (image, rect) = resize
+            .resize(&s(40, 20), Rect::default(), r(5, 4), None, false)
+            .unwrap();
+        assert_eq!(r(5, 4), rect);
+        assert_eq!((50, 40), (image.width(), image.height()));
+        let image = image.to_rgb8();
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(0, 0));
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(4, 20));
+        assert_eq!(&Rgb([255, 0, 0]), image.get_pixel(5, 10));
+        assert_eq!(&Rgb([255, 0, 0]), image.get_pixel(44, 29));
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(45, 20));
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(25, 30));
+    }
+
+    #[test]
+    fn pad_scaled() {
+        let resize = Resize::Pad(None, Rgb([0, 0, 255]));
+        let pad = |scale| {
+            let source = s(40, 20).with_scale(scale);
+            let (image, rect) = resize
+                .resize(&source, Rect::default(), r(10, 10), None, false)
+                .unwrap();
+            assert_eq!(r(10, 10), rect);
+            image.to_rgb8()
+        };
+
+        // Twice the size: 8x4 cells, centered in the 10x10 cells.
+        let image = pad(0.5);
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(9, 50));
+        assert_eq!(&Rgb([255, 0, 0]), image.get_pixel(10, 30));
+        assert_eq!(&Rgb([255, 0, 0]), image.get_pixel(89, 69));
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(90, 50));
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(50, 70));
+
+        // Half the size: 2x1 cells.
+        let image = pad(2.0);
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(39, 50));
+        assert_eq!(&Rgb([255, 0, 0]), image.get_pixel(40, 45));
+        assert_eq!(&Rgb([255, 0, 0]), image.get_pixel(59, 54));
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(60, 50));
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(50, 55));
+    }
+
+    #[test]
+    fn needs_resize_upscale() {
+        let resize = Resize::Upscale(None, 2);
+
+        // Capped at twice the native size, and centered.
+        let to = resize.needs_resize(&s(20, 10), r(0, 0), r(10, 10), false);
+        assert_eq!(Some(Rect::new(3, 4, 4, 2)), to);
+
+        let to = resize.needs_resize(&s(20, 10), Rect::new(3, 4, 4, 2), r(10, 10), false);
+        assert_eq!(None, to);
+
+        // Smaller areas are fitted like Resize::Fit.
+        let to = resize.needs_resize(&s(20, 10), Rect::new(3, 4, 4, 2), r(3, 3), false);
+        assert_eq!(Some(Rect::new(0, 0, 3, 2)), to);
+
+        let to = Resize::Upscale(None, 0).needs_resize(&s(20, 10), r(0, 0), r(10, 10), false);
+        assert_eq!(Some(Rect::new(4, 4, 2, 1)), to);
+
+        let (image, rect) = resize
+            .resize(&s(20, 10), Rect::default(), r(10, 10), None, false)
+            .unwrap();
+        assert_eq!(Rect::new(3, 4, 4, 2), rect);
+        assert_eq!((40, 20), (image.width(), image.height()));
+    }
+
+    #[test]
+    fn needs_resize_pan() {
+        let resize = Resize::Pan(3, 1);
+
+        // Native size from the offset, clipped to the area.
+        let to = resize.needs_resize(&s(100, 50), r(0, 0), r(4, 10), false);
+        assert_eq!(Some(r(4, 4)), to);
+        let to = resize.needs_resize(&s(100, 50), r(4, 4), r(4, 10), false);
+        assert_eq!(None, to);
+
+        // Clamped to the last cell.
+        let to = Resize::Pan(20, 20).needs_resize(&s(100, 50), r(0, 0), r(4, 10), false);
+        assert_eq!(Some(r(1, 1)), to);
+
+        let image: DynamicImage =
+            ImageBuffer::from_fn(100, 50, |x, y| Rgb([x as u8, y as u8, 0])).into();
+        let source = ImageSource::new(image, FONT_SIZE);
+        let (image, rect) = resize
+            .resize(&source, Rect::default(), r(4, 10), None, false)
+            .unwrap();
+        assert_eq!(r(4, 4), rect);
+        assert_eq!((40, 40), (image.width(), image.height()));
+        assert_eq!(Rgb([30, 10, 0]), image.to_rgb8()[(0, 0)]);
+    }
+
+    #[test]
+    fn pan_and_crop_scaled() {
+        let image: DynamicImage =
+            ImageBuffer::from_fn(100, 50, |x, y| Rgb([x as u8, y as u8, 0])).into();
+        // Twice the size: 20x10 cells.
+        let source = ImageSource::new(image, FONT_SIZE).with_scale(0.5);
+
+        // The offset is clamped to the last cell of the scaled image, in both steps.
+        let resize = Resize::Pan(15, 2);
+        let rect = resize.needs_resize(&source, r(0, 0), r(4, 4), false);
+        assert_eq!(Some(r(4, 4)), rect);
+        let (image, rect) = resize
+            .resize(&source, Rect::default(), r(4, 4), None, false)
+            .unwrap();
+        assert_eq!(r(4, 4), rect);
+        assert_eq!((40, 40), (image.width(), image.height()));
+        assert_eq!(Rgb([75, 10, 0]), image.to_rgb8()[(0, 0)]);
+        assert_eq!(Rgb([94, 29, 0]), image.to_rgb8()[(39, 39)]);
+
+        let resize = Resize::Pan(30, 30);
+        let (image, rect) = resize
+            .resize(&source, Rect::default(), r(4, 4), None, false)
+            .unwrap();
+        assert_eq!(r(1, 1), rect);
+        assert_eq!(Rgb([95, 45, 0]), image.to_rgb8()[(0, 0)]);
+
+        let (image, rect) = Resize::Crop(None, None)
+            .resize(&source, Rect::default(), r(4, 4), None, false)
+            .unwrap();
+        assert_eq!(r(4, 4), rect);
+        assert_eq!((40, 40), (image.width(), image.height()));
+        assert_eq!(Rgb([19, 19, 0]), image.to_rgb8()[(39, 39)]);
+
+        // The crop is scaled with the filter.
+        let checkers: DynamicImage =
+            ImageBuffer::from_fn(100, 50, |x, y| Rgb([((x + y) % 2 * 255) as u8, 0, 0])).into();
+        let source = ImageSource::new(checkers, FONT_SIZE).with_scale(0.5);
+        let crop = |filter_type| {
+            let (image, _) = Resize::Crop(Some(filter_type), None)
+                .resize(&source, Rect::default(), r(4, 4), None, false)
+                .unwrap();
+            image.to_rgb8()
+        };
+        assert_ne!(crop(FilterType::Nearest), crop(FilterType::Triangle));
+    }
+
+    #[test]
+    fn needs_resize_fill() {
+        let resize = Resize::Fill(None);
+
+        let to = resize.needs_resize(&s(100, 100), r(10, 10), r(10, 10), false);
+        assert_eq!(None, to);
+
+        let to = resize.needs_resize(&s(40, 20), r(10, 10), r(20, 5), false);
+        assert_eq!(Some(r(20, 5)), to);
+
+        let to = resize.needs_resize(&s(40, 20), r(20, 5), r(20, 5), false);
+        assert_eq!(None, to);
+    }
+
+    #[test]
+    fn needs_resize_fit_width() {
+        let resize = Resize::FitWidth(None);
+
+        let to = resize.needs_resize(&s(100, 50), r(10, 10), r(20, 5), false);
+        assert_eq!(Some(r(20, 10)), to);
+
+        let to = resize.needs_resize(&s(100, 50), r(20, 10), r(20, 3), false);
+        assert_eq!(None, to);
+
+        let to = resize.needs_resize(&s(100, 50), r(20, 10), r(4, 10), false);
+        assert_eq!(Some(r(4, 2)), to);
+    }
+
+    #[test]
+    fn needs_resize_fit_height() {
+        let resize = Resize::FitHeight(None);
+
+        let to = resize.needs_resize(&s(100, 50), r(10, 5), r(5, 10), false);
+        assert_eq!(Some(r(20, 10)), to);
+
+        let to = resize.needs_resize(&s(100, 50), r(20, 10), r(30, 10), false);
+        assert_eq!(None, to);
+    }
+
+    #[test]
+    fn resize_fill_crops_centered() {
+        // Left half red, right half blue.
+        let image: DynamicImage = ImageBuffer::from_fn(40, 20, |x, _| {
+            if x < 20 {
+                Rgb([255u8, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        })
+        .into();
+        let source = ImageSource::new(image, FONT_SIZE);
+
+        let (image, rect) = Resize::Fill(None)
+            .resize(&source, Rect::default(), r(2, 2), None, false)
+            .expect("resized");
+        assert_eq!(r(2, 2), rect);
+        assert_eq!((20, 20), (image.width(), image.height()));
+        let image = image.to_rgb8();
+        assert_eq!(&Rgb([255, 0, 0]), image.get_pixel(0, 10));
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(19, 10));
+    }
+
+    #[test]
+    fn image_alignment() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgb([255u8, 0, 0])).into();
+        let source = ImageSource::new(image, (1, 2));
+        let area = Rect::new(0, 0, 4, 3);
+        let protocol =
+            protocol::halfblocks::Halfblocks::from_source(&source, Resize::Fit(None), None, area)
+                .unwrap();
+        let mut buf = Buffer::empty(area);
+
+        Image::new(&protocol)
+            .alignment(Alignment::Right, VerticalAlignment::Center)
+            .render(area, &mut buf);
+        assert_eq!(" ", buf.get(0, 1).symbol);
+        assert_eq!(" ", buf.get(3, 0).symbol);
+        assert_eq!("▀", buf.get(2, 1).symbol);
+        assert_eq!("▀", buf.get(3, 1).symbol);
+        assert_eq!(" ", buf.get(3, 2).symbol);
+    }
+
+    #[test]
+    fn stateful_image_builder() {
+        let image = StatefulImage::builder()
+            .filter(FilterType::Lanczos3)
+            .resize(Resize::Center(None))
+            .background(Rgb([1, 2, 3]))
+            .alignment(Alignment::Center, VerticalAlignment::Bottom)
+            .build();
+        assert!(matches!(
+            image.resize,
+            Resize::Center(Some(FilterType::Lanczos3))
+        ));
+        assert_eq!(Some(Rgb([1, 2, 3])), image.background_color);
+        assert_eq!(
+            (Alignment::Center, VerticalAlignment::Bottom),
+            image.alignment
+        );
+        assert!(!image.overwrite);
+
+        let image = StatefulImage::builder().build();
+        assert!(matches!(image.resize, Resize::Fit(None)));
+        assert_eq!(None, image.background_color);
+    }
+
+    #[test]
+    fn stateful_image_alignment() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgb([255u8, 0, 0])).into();
+        let mut state = protocol::StatefulBlock::Halfblocks(
+            protocol::halfblocks::StatefulHalfblocks::new(ImageSource::new(image, (1, 2))),
+        );
+        let area = Rect::new(1, 1, 6, 3);
+        let mut buf = Buffer::empty(Rect::new(0, 0, 8, 5));
+
+        StatefulImage::new(None)
+            .alignment(Alignment::Center, VerticalAlignment::Bottom)
+            .render(area, &mut buf, &mut state);
+        assert_eq!(r(2, 1), state.rect());
+        assert_eq!(" ", buf.get(1, 1).symbol);
+        assert_eq!(" ", buf.get(3, 2).symbol);
+        assert_eq!("▀", buf.get(3, 3).symbol);
+        assert_eq!("▀", buf.get(4, 3).symbol);
+        assert_eq!(" ", buf.get(5, 3).symbol);
+    }
+
+    #[test]
+    fn stateful_image_overwrite() {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 4, Rgb([255u8, 0, 0])).into();
+        let source = ImageSource::new(image, (2, 2));
+        let mut state = protocol::StatefulBlock::Sixel(protocol::sixel::StatefulSixel::new(
+            source.clone(),
+            false,
+        ));
+        let area = Rect::new(1, 1, 4, 4);
+        let mut buf = Buffer::empty(Rect::new(0, 0, 6, 6));
+        StatefulImage::new(None).render(area, &mut buf, &mut state);
+        assert!(buf.get(1, 1).symbol.starts_with("\x1bP"));
+
+        let mut buf = Buffer::empty(Rect::new(0, 0, 6, 6));
+        StatefulImage::new(None)
+            .overwrite(true)
+            .render(area, &mut buf, &mut state);
+        let symbol = &buf.get(1, 1).symbol;
+        assert!(symbol.starts_with("\x1b7\x1b[2X\x1b[1B\x1b[2X\x1b8\x1bP"));
+
+        // Halfblocks overwrite their cells anyway.
+        let mut state = protocol::StatefulBlock::Halfblocks(
+            protocol::halfblocks::StatefulHalfblocks::new(source),
+        );
+        StatefulImage::new(None)
+            .overwrite(true)
+            .render(area, &mut buf, &mut state);
+        assert_eq!("▀", buf.get(1, 1).symbol);
+    }
+
+    #[test]
+    fn stateful_image_is_current() {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 4, Rgb([255u8, 0, 0])).into();
+        let mut state =
+            protocol::halfblocks::StatefulHalfblocks::new(ImageSource::new(image, (1, 2)));
+        let widget = StatefulImage::new(None);
+        let area = r(4, 2);
+        assert!(!widget.is_current(&mut state, area));
+        assert!(widget.is_current(&mut state, r(0, 0)));
+
+        state.resize_encode(&Resize::Fit(None), None, area);
+        assert!(widget.is_current(&mut state, area));
+        assert!(!state.is_stale(&Resize::Fit(None), area));
+        assert!(!widget.is_current(&mut state, r(2, 1)));
+        assert!(state.is_stale(&Resize::Fit(None), r(2, 1)));
+        assert!(!StatefulImage::new(None)
+            .resize(Resize::Stretch(None))
+            .is_current(&mut state, r(8, 2)));
+    }
+
+    #[test]
+    fn stateful_image_warm() {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 4, Rgb([255u8, 0, 0])).into();
+        let mut state = protocol::StatefulBlock::Halfblocks(
+            protocol::halfblocks::StatefulHalfblocks::new(ImageSource::new(image, (1, 2))),
+        );
+        let widget = StatefulImage::new(None).resize(Resize::Stretch(None));
+        let area = r(6, 3);
+        state.warm(&Resize::Stretch(None), None, area);
+        assert!(widget.is_current(&mut state, area));
+        assert_eq!(area, state.rect());
+
+        let mut buf = Buffer::empty(area);
+        widget.render(area, &mut buf, &mut state);
+        assert_eq!("▀", buf.get(5, 2).symbol);
+    }
+
+    #[test]
+    fn test_backend() {
+        use ratatui::{backend::TestBackend, Terminal};
+
+        let mut picker = picker::Picker::new((1, 2));
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgb([255u8, 0, 0])).into();
+        let mut state = picker.new_resize_protocol(image);
+
+        let mut terminal = Terminal::new(TestBackend::new(3, 1)).unwrap();
+        terminal
+            .draw(|f| f.render_stateful_widget(StatefulImage::new(None), f.size(), &mut state))
+            .unwrap();
+        let buf = terminal.backend().buffer();
+        assert_eq!("▀", buf.get(1, 0).symbol);
+        assert_eq!(ratatui::style::Color::Rgb(255, 0, 0), buf.get(1, 0).fg);
+        assert_eq!(" ", buf.get(2, 0).symbol);
+    }
+
+    #[test]
+    fn resize_flattens_transparency() {
+        let image: DynamicImage = ImageBuffer::from_pixel(10, 10, Rgba([255u8, 0, 0, 0])).into();
+        let source = ImageSource::new(image, FONT_SIZE);
+        let white = Rgb([255, 255, 255]);
+
+        let (image, _) = Resize::Fit(None)
+            .resize(&source, Rect::default(), r(1, 1), Some(white), false)
+            .expect("resized");
+        assert_eq!(&white, image.to_rgb8().get_pixel(5, 5));
+
+        // Without a background color, the alpha channel is kept for protocols that support it.
+        let (image, _) = Resize::Fit(None)
+            .resize(&source, Rect::default(), r(1, 1), None, false)
+            .expect("resized");
+        assert!(image.color().has_alpha());
+
+        // Also when padding to the cell size, where the padding is transparent too.
+        let image: DynamicImage = ImageBuffer::from_pixel(15, 10, Rgba([255u8, 0, 0, 255])).into();
+        let (image, _) = Resize::Fit(None)
+            .resize(
+                &ImageSource::new(image, FONT_SIZE),
+                r(2, 1),
+                r(2, 1),
+                None,
+                true,
+            )
+            .expect("resized");
+        assert_eq!((20, 10), (image.width(), image.height()));
+        assert_eq!(&Rgba([255, 0, 0, 255]), image.to_rgba8().get_pixel(0, 0));
+        assert_eq!(0, image.to_rgba8().get_pixel(19, 0)[3]);
+    }
 }
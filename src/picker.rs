@@ -1,8 +1,15 @@
 //! Helper module to build a protocol, and swap protocols at runtime
 
-use std::{env, io, time::Instant};
+use std::{
+    env, fmt,
+    fs::File,
+    io::{self, BufReader, Cursor},
+    path::Path,
+    str::FromStr,
+    time::Instant,
+};
 
-use image::{DynamicImage, Rgb};
+use image::{DynamicImage, Frames, Rgb};
 use ratatui::layout::Rect;
 #[cfg(all(feature = "rustix", unix))]
 use rustix::termios::Winsize;
@@ -10,26 +17,103 @@ use rustix::termios::Winsize;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    errors::Errors,
     protocol::{
-        halfblocks::{Halfblocks, StatefulHalfblocks},
-        iterm2::{FixedIterm2, Iterm2State},
-        kitty::{Kitty, StatefulKitty},
-        sixel::{Sixel, StatefulSixel},
-        FixedBlock, Protocol, StatefulBlock, StatefulProtocol,
+        animated::StatefulAnimated,
+        blank::{Blank, BlankOptions, StatefulBlank},
+        braille::{Braille, BrailleOptions, StatefulBraille},
+        halfblocks::{Blocks, Halfblocks, HalfblocksColors, HalfblocksOptions, StatefulHalfblocks},
+        iterm2::{FixedIterm2, Iterm2Options, Iterm2State},
+        kitty::{self, Kitty, KittyOptions, StatefulKitty},
+        sixel::{Sixel, SixelOptions, StatefulSixel},
+        text::{StatefulText, Text, TextOptions},
+        FixedBlock, StatefulBlock,
     },
     FontSize, ImageSource, Resize, Result,
 };
 
 #[derive(Clone, Copy)]
 pub struct Picker {
+    /// The size of a cell in pixels, see [Picker::with_font_size].
     pub font_size: FontSize,
+    /// How [Picker::font_size] was obtained, e.g. to warn when it was not detected.
+    pub font_size_source: FontSizeSource,
+    /// Multiplier of the pixels per cell of [Picker::font_size] for the native size of images in
+    /// cells, see [ImageSource::with_scale]. Defaults to `1.0`.
+    ///
+    /// Tunes the size of images independently of the detected font size, e.g. `0.5` for images
+    /// twice as large on HiDPI screens where they look tiny.
+    pub scale: f32,
+    /// The color that transparent images are composited over. Defaults to `None`.
+    ///
+    /// Without a background color, [Blocks::Halves], kitty and iTerm2 leave transparent pixels to
+    /// the terminal's background. The terminal's background color is unknown though, so
+    /// [Blocks::Quadrants], [Blocks::Sextants] and sixel composite transparent pixels over black,
+    /// which looks wrong on light themes. Set the background color of the UI to avoid that.
     pub background_color: Option<Rgb<u8>>,
+    /// The protocol that all new protocols are created with.
+    ///
+    /// Set by [Picker::from_termios] and [Picker::guess_protocol], and otherwise only changed
+    /// explicitly, e.g. with [Picker::set_protocol_type]. Creating protocols never guesses again.
     pub protocol_type: ProtocolType,
+    /// Whether the app runs inside tmux, detected from `$TMUX`, `$TERM` or `$TERM_PROGRAM`.
+    ///
+    /// The sixel, kitty and iTerm2 escape sequences are then wrapped in tmux's passthrough, so
+    /// that they reach the terminal.
     pub is_tmux: bool,
-    kitty_counter: u8,
+    /// Options for [ProtocolType::Halfblocks], [ProtocolType::Quadrants] and
+    /// [ProtocolType::Sextants], e.g. to dither gradients.
+    pub halfblocks: HalfblocksOptions,
+    /// Options for [ProtocolType::Sixel].
+    pub sixel: SixelOptions,
+    /// Options for [ProtocolType::Kitty], e.g. to compress the image data.
+    pub kitty: KittyOptions,
+    /// Options for [ProtocolType::Iterm2].
+    pub iterm2: Iterm2Options,
+    /// Options for [ProtocolType::Braille].
+    pub braille: BrailleOptions,
+    /// Options for [ProtocolType::Blank], e.g. the placeholder character.
+    pub blank: BlankOptions,
+    /// Options for [ProtocolType::Text], e.g. the style of the box.
+    pub text: TextOptions,
+    /// Bitset of the graphics protocols that the terminal was detected to support, see
+    /// [Picker::available_protocols].
+    supported: u32,
+    /// Bitset of the protocols made available with [Picker::enable_protocol].
+    enabled: u32,
+    /// See [Picker::screen_size_pixels].
+    screen_size: Option<(u16, u16)>,
 }
 
 /// Serde-friendly protocol-type enum for [Picker].
+///
+/// | Protocol   | Resolution       | Colors                 | Transparency |
+/// |------------|------------------|------------------------|--------------|
+/// | Halfblocks | 1x2 per cell     | 2 colors per cell      | on or off    |
+/// | Sixel      | pixels           | palette, up to 256     | no           |
+/// | Kitty      | pixels           | 24-bit                 | yes          |
+/// | Iterm2     | pixels           | 24-bit (JPEG or PNG)   | yes          |
+/// | Quadrants  | 2x2 per cell     | 2 colors per cell      | no           |
+/// | Sextants   | 2x3 per cell     | 2 colors per cell      | no           |
+/// | Braille    | 2x4 per cell     | 1 color per cell       | no           |
+/// | Blank      | none             | none                   | no           |
+/// | Text       | none             | none                   | no           |
+///
+/// Without transparency, the image is flattened against the background color, see
+/// [Picker::background_color].
+///
+/// The lowercase names, e.g. `"kitty"`, round-trip through [FromStr] and [Display](fmt::Display),
+/// like with the `serde` feature:
+///
+/// ```
+/// use ratatui_image::picker::ProtocolType;
+///
+/// let protocol_type: ProtocolType = "kitty".parse()?;
+/// assert_eq!(ProtocolType::Kitty, protocol_type);
+/// assert_eq!("kitty", protocol_type.to_string());
+/// assert!("kitten".parse::<ProtocolType>().is_err());
+/// # Ok::<(), ratatui_image::errors::Errors>(())
+/// ```
 #[derive(PartialEq, Clone, Debug, Copy)]
 #[cfg_attr(
     feature = "serde",
@@ -41,6 +125,67 @@ pub enum ProtocolType {
     Sixel,
     Kitty,
     Iterm2,
+    /// Halfblocks with [Blocks::Quadrants], for 2x2 pixels per cell.
+    Quadrants,
+    /// Halfblocks with [Blocks::Sextants], for 2x3 pixels per cell.
+    Sextants,
+    /// Monochrome braille dots, 2x4 per cell, see [Picker::braille].
+    Braille,
+    /// No image at all, only a placeholder in its cells, see [Picker::blank]. Writes no escape
+    /// sequences, e.g. for output that is redirected to a file, see [Picker::blank_unless_tty].
+    Blank,
+    /// No image at all, but a box labeled with the image's size, e.g. `[img 640x480]`, see
+    /// [Picker::text]. Writes no escape sequences, e.g. for CI or snapshot tests.
+    Text,
+}
+
+impl fmt::Display for ProtocolType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProtocolType::Halfblocks => "halfblocks",
+            ProtocolType::Sixel => "sixel",
+            ProtocolType::Kitty => "kitty",
+            ProtocolType::Iterm2 => "iterm2",
+            ProtocolType::Quadrants => "quadrants",
+            ProtocolType::Sextants => "sextants",
+            ProtocolType::Braille => "braille",
+            ProtocolType::Blank => "blank",
+            ProtocolType::Text => "text",
+        })
+    }
+}
+
+impl FromStr for ProtocolType {
+    type Err = Errors;
+
+    /// Parse the name of a protocol, ignoring case and surrounding whitespace.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "halfblocks" => Ok(ProtocolType::Halfblocks),
+            "sixel" => Ok(ProtocolType::Sixel),
+            "kitty" => Ok(ProtocolType::Kitty),
+            "iterm2" => Ok(ProtocolType::Iterm2),
+            "quadrants" => Ok(ProtocolType::Quadrants),
+            "sextants" => Ok(ProtocolType::Sextants),
+            "braille" => Ok(ProtocolType::Braille),
+            "blank" => Ok(ProtocolType::Blank),
+            "text" => Ok(ProtocolType::Text),
+            _ => Err(Errors::UnknownProtocol(s.to_string())),
+        }
+    }
+}
+
+/// How the [FontSize] of a [Picker] was obtained.
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+pub enum FontSizeSource {
+    /// Given to [Picker::new] or [Picker::set_font_size], which may be a guessed default like
+    /// `(8, 16)`.
+    Manual,
+    /// Computed from the window size in pixels and cells that the terminal reported to `ioctl`.
+    Ioctl,
+    /// Computed from the replies to the `CSI 14 t` (text area in pixels) and `CSI 18 t` (text
+    /// area in cells) queries, because `ioctl` reported no pixel size, e.g. in tmux or screen.
+    Query,
 }
 
 impl ProtocolType {
@@ -49,9 +194,37 @@ impl ProtocolType {
             ProtocolType::Halfblocks => ProtocolType::Sixel,
             ProtocolType::Sixel => ProtocolType::Kitty,
             ProtocolType::Kitty => ProtocolType::Iterm2,
-            ProtocolType::Iterm2 => ProtocolType::Halfblocks,
+            ProtocolType::Iterm2 => ProtocolType::Quadrants,
+            ProtocolType::Quadrants => ProtocolType::Sextants,
+            ProtocolType::Sextants => ProtocolType::Braille,
+            ProtocolType::Braille => ProtocolType::Halfblocks,
+            ProtocolType::Blank => ProtocolType::Halfblocks,
+            ProtocolType::Text => ProtocolType::Halfblocks,
         }
     }
+
+    /// Whether this is an actual graphics protocol (sixel, kitty or iTerm2), as opposed to the
+    /// text-based fallbacks like halfblocks.
+    pub fn is_graphics(&self) -> bool {
+        matches!(
+            self,
+            ProtocolType::Sixel | ProtocolType::Kitty | ProtocolType::Iterm2
+        )
+    }
+
+    /// Whether every pixel of the image is shown in 24-bit color (kitty or iTerm2).
+    ///
+    /// Sixel is quantized to a palette, and the text-based fallbacks only have one or two colors
+    /// per cell.
+    pub fn is_truecolor(&self) -> bool {
+        matches!(self, ProtocolType::Kitty | ProtocolType::Iterm2)
+    }
+
+    /// Whether transparent pixels show what is behind the image (kitty, and iTerm2 with PNG), when
+    /// no background color is set.
+    pub fn supports_transparency(&self) -> bool {
+        matches!(self, ProtocolType::Kitty | ProtocolType::Iterm2)
+    }
 }
 
 /// Helper for building widgets
@@ -60,6 +233,15 @@ impl Picker {
     ///
     /// This writes and reads from stdin momentarily.
     ///
+    /// # Errors
+    /// * [Errors::NoTty] if stdout is not a terminal.
+    /// * [Errors::RustixError] if querying the window size failed.
+    /// * [Errors::FontSizeUnknown] if the terminal reports its size in pixels neither to
+    ///   `ioctl` nor to the `CSI 14 t` and `CSI 18 t` queries.
+    ///
+    /// If `ioctl` reports no size in pixels, which is common in tmux or screen, the terminal is
+    /// queried with escape sequences instead. [Picker::font_size_source] tells which was used.
+    ///
     /// # Example
     /// ```rust
     /// use ratatui_image::picker::Picker;
@@ -67,17 +249,84 @@ impl Picker {
     /// ```
     #[cfg(all(feature = "rustix", unix))]
     pub fn from_termios() -> Result<Picker> {
-        use rustix::{stdio::stdout, termios::tcgetwinsize};
+        use rustix::{
+            stdio::stdout,
+            termios::{isatty, tcgetwinsize},
+        };
 
         let stdout = stdout();
-        let font_size = font_size(tcgetwinsize(stdout)?)?;
-        Ok(Picker::new(font_size))
+        if !isatty(stdout) {
+            return Err(Errors::NoTty);
+        }
+        let winsize = tcgetwinsize(stdout)?;
+        let (font_size, source, screen_size) = match font_size(winsize) {
+            Ok(font_size) => (
+                font_size,
+                FontSizeSource::Ioctl,
+                (winsize.ws_xpixel, winsize.ws_ypixel),
+            ),
+            Err(Errors::FontSizeUnknown) => {
+                let is_tmux = is_tmux();
+                if is_tmux {
+                    enable_tmux_passthrough();
+                }
+                let (font_size, screen_size) =
+                    query_font_size(is_tmux).map_err(|_| Errors::FontSizeUnknown)?;
+                (font_size, FontSizeSource::Query, screen_size)
+            }
+            Err(err) => return Err(err),
+        };
+        let mut picker = Picker::new(font_size);
+        picker.font_size_source = source;
+        picker.screen_size = Some(screen_size);
+        Ok(picker)
+    }
+
+    /// The size of the terminal window in pixels, from the same query as the font size in
+    /// [Picker::from_termios], e.g. to cap the size of images before decoding them.
+    ///
+    /// `None` if the picker was not created by querying the terminal, e.g. with [Picker::new].
+    pub fn screen_size_pixels(&self) -> Option<(u16, u16)> {
+        self.screen_size
+    }
+
+    /// Query the terminal for font-size and for graphics protocol support.
+    ///
+    /// Unlike [Picker::guess_protocol], this does not guess from environment variables, but
+    /// always writes the kitty graphics and sixel (`DA1`) queries to stdout and reads the replies
+    /// from stdin, with a timeout. The terminal state is restored afterwards. Falls back to
+    /// [ProtocolType::Halfblocks] if the terminal does not reply or supports neither protocol.
+    ///
+    /// Since iTerm2 support cannot be queried, [Picker::guess_protocol] should be preferred if
+    /// iTerm2 terminals are relevant.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use ratatui_image::picker::Picker;
+    /// let mut picker = Picker::from_query_stdio();
+    /// ```
+    #[cfg(all(feature = "rustix", unix))]
+    pub fn from_query_stdio() -> Result<Picker> {
+        let mut picker = Picker::from_termios()?;
+        picker.is_tmux = is_tmux();
+        if picker.is_tmux {
+            enable_tmux_passthrough();
+        }
+        let (protocols, graphics) = query_device_attrs(picker.is_tmux)
+            .unwrap_or((vec![ProtocolType::Halfblocks], SixelGraphics::default()));
+        picker.set_supported(&protocols);
+        graphics.apply(&mut picker.sixel);
+        Ok(picker)
     }
 
     /// Create a picker from a given terminal [FontSize] and [ProtocolType].
     /// This is useful to allow overriding the best-guess of [Picker::from_termios], for example
     /// from some user configuration.
     ///
+    /// Like [Picker::from_fontsize], this does not do any terminal I/O, so it can be used to test
+    /// widgets against ratatui's `TestBackend`, with the deterministic
+    /// [ProtocolType::Halfblocks].
+    ///
     /// # Example
     /// ```rust
     /// use ratatui_image::picker::{ProtocolType, Picker};
@@ -89,91 +338,573 @@ impl Picker {
     /// picker.protocol_type = user_protocol;
     /// ```
     pub fn new(font_size: FontSize) -> Picker {
+        Picker::from_fontsize(font_size)
+    }
+
+    /// Create a picker from a given terminal [FontSize], without any terminal I/O.
+    ///
+    /// This works without a controlling terminal, e.g. in CI, over SSH without a PTY, or in tests.
+    /// The protocol defaults to [ProtocolType::Halfblocks], and can be set afterwards. Note that
+    /// [Picker::guess_protocol] may still query the terminal.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ratatui_image::picker::{ProtocolType, Picker};
+    ///
+    /// let picker = Picker::from_fontsize((7, 14));
+    /// assert_eq!(ProtocolType::Halfblocks, picker.protocol_type);
+    /// ```
+    pub fn from_fontsize(font_size: FontSize) -> Picker {
         Picker {
             font_size,
+            font_size_source: FontSizeSource::Manual,
+            scale: 1.0,
             background_color: None,
             protocol_type: ProtocolType::Halfblocks,
             is_tmux: false,
-            kitty_counter: 0,
+            halfblocks: HalfblocksOptions::default(),
+            sixel: SixelOptions::default(),
+            kitty: KittyOptions::default(),
+            iterm2: Iterm2Options::default(),
+            braille: BrailleOptions::default(),
+            blank: BlankOptions::default(),
+            text: TextOptions::default(),
+            supported: 0,
+            enabled: 0,
+            screen_size: None,
+        }
+    }
+
+    /// Override the [FontSize], e.g. with cell dimensions measured by the user, when the size
+    /// reported by the terminal is wrong (which can happen in tmux or over some SSH setups).
+    ///
+    /// All protocols created afterwards map image pixels to cells with this font size.
+    ///
+    /// # Cell grid and pixels
+    ///
+    /// The font size is used for two things: the cell-grid math, i.e. how many cells an image
+    /// covers (see [ImageSource::desired]), and the pixel size that the image is resized to for a
+    /// rect of cells before it is transmitted. Kitty and iTerm2 scale the transmitted image to the
+    /// cells, so only the aspect ratio of the font size matters to them: with a square `(8, 8)`
+    /// instead of the actual `(8, 16)`, images are squashed vertically. Sixel draws pixels as they
+    /// are, so with sixel, a font size that is too small also draws the image smaller than its
+    /// cells, and one that is too large overflows them. Halfblocks and the other text-based
+    /// protocols only use the aspect ratio.
+    ///
+    /// # Errors
+    /// [Errors::InvalidFontSize] if the width or height is zero.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ratatui_image::picker::Picker;
+    ///
+    /// let picker = Picker::from_fontsize((7, 14)).with_font_size((8, 16))?;
+    /// assert_eq!((8, 16), picker.font_size);
+    /// # Ok::<(), ratatui_image::errors::Errors>(())
+    /// ```
+    pub fn with_font_size(mut self, font_size: FontSize) -> Result<Picker> {
+        self.set_font_size(font_size)?;
+        Ok(self)
+    }
+
+    /// Set the [FontSize], see [Picker::with_font_size].
+    pub fn set_font_size(&mut self, font_size: FontSize) -> Result<()> {
+        if font_size.0 == 0 || font_size.1 == 0 {
+            return Err(Errors::InvalidFontSize(font_size));
         }
+        self.font_size = font_size;
+        self.font_size_source = FontSizeSource::Manual;
+        Ok(())
     }
 
     /// Guess the best protocol for the current terminal by issuing some escape sequences to
     /// stdout.
+    ///
+    /// If stdout is not a terminal, the terminal is not queried. To draw no images at all when the
+    /// output is redirected, see [Picker::blank_unless_tty].
+    ///
+    /// If the terminal advertises its number of sixel color registers, the sixel palette size is
+    /// clamped to it, see [SixelOptions::color_registers]. Likewise, sixel images are shrunk to
+    /// the maximum sixel size it advertises, see [SixelOptions::max_size].
+    ///
+    /// Halfblocks use the 256-color palette if `$TERM` ends with `256color` and `$COLORTERM` is
+    /// not `truecolor` or `24bit`, see [HalfblocksOptions::colors]. Set it afterwards to override
+    /// this, e.g. over SSH, which often does not forward `$COLORTERM`.
     pub fn guess_protocol(&mut self) -> ProtocolType {
-        (self.protocol_type, self.is_tmux) = guess_protocol();
+        let (protocols, graphics);
+        (protocols, self.is_tmux, graphics) = guess_protocol();
+        graphics.apply(&mut self.sixel);
+        self.halfblocks.colors = halfblocks_colors(
+            env::var("COLORTERM").ok().as_deref(),
+            env::var("TERM").ok().as_deref(),
+        );
+        self.set_supported(&protocols);
+        self.protocol_type
+    }
+
+    /// Pick [ProtocolType::Blank] if `fd` is not a terminal, e.g. when it is redirected to a file,
+    /// so that no escape sequences end up in the output. Returns the picked protocol.
+    ///
+    /// `fd` should be what the ratatui backend writes to, e.g. [std::io::stdout], or
+    /// [std::io::stderr] or `/dev/tty` for TUIs whose stdout is piped into another program.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ratatui_image::picker::Picker;
+    /// let mut picker = Picker::new((8, 16));
+    /// picker.guess_protocol();
+    /// picker.blank_unless_tty(std::io::stdout());
+    /// ```
+    #[cfg(all(feature = "rustix", unix))]
+    pub fn blank_unless_tty<Fd: rustix::fd::AsFd>(&mut self, fd: Fd) -> ProtocolType {
+        if !rustix::termios::isatty(fd) {
+            self.set_supported(&[ProtocolType::Blank]);
+        }
+        self.protocol_type
+    }
+
+    /// Pick the first, i.e. best, of the detected protocols, and record all of them for
+    /// [Picker::available_protocols].
+    fn set_supported(&mut self, protocols: &[ProtocolType]) {
+        self.protocol_type = protocols
+            .first()
+            .copied()
+            .unwrap_or(ProtocolType::Halfblocks);
+        self.supported = protocols.iter().fold(0, |supported, &protocol_type| {
+            supported | bit(protocol_type)
+        });
+    }
+
+    /// The protocol that was guessed or set.
+    pub fn protocol_type(&self) -> ProtocolType {
         self.protocol_type
     }
 
-    /// Cycle through available protocols.
+    /// Force a protocol, e.g. from a user setting when the terminal misreports its capabilities.
+    ///
+    /// Protocols created afterwards with [Picker::new_protocol] or [Picker::new_resize_protocol]
+    /// use it, even if the terminal does not support it.
+    pub fn set_protocol_type(&mut self, protocol_type: ProtocolType) {
+        self.protocol_type = protocol_type;
+    }
+
+    /// Whether the protocol is an actual graphics protocol, see [ProtocolType::is_graphics].
+    ///
+    /// Useful to decide whether to reserve space for an image at all, or e.g. show a text
+    /// description instead of the halfblocks fallback.
+    pub fn supports_graphics(&self) -> bool {
+        self.protocol_type.is_graphics()
+    }
+
+    /// Cycle through the available protocols, see [Picker::available_protocols].
+    ///
+    /// Protocols that the terminal does not support are skipped, so that e.g. a "cycle image
+    /// mode" key binding never ends up on a protocol that only shows garbage.
     pub fn cycle_protocols(&mut self) -> ProtocolType {
-        self.protocol_type = self.protocol_type.next();
+        let mut protocol_type = self.protocol_type.next();
+        while !self.is_available(protocol_type) {
+            protocol_type = protocol_type.next();
+        }
+        self.protocol_type = protocol_type;
         self.protocol_type
     }
 
+    /// The protocols that the terminal supports, in the order of [Picker::cycle_protocols].
+    ///
+    /// These are [ProtocolType::Halfblocks] and [ProtocolType::Quadrants], whose glyphs are in
+    /// virtually any font, the graphics protocols detected by [Picker::guess_protocol] or
+    /// [Picker::from_query_stdio], if any, and the protocols enabled with
+    /// [Picker::enable_protocol]. Useful to build e.g. a settings menu.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ratatui_image::picker::{Picker, ProtocolType};
+    ///
+    /// let picker = Picker::from_fontsize((7, 14));
+    /// assert!(!picker.available_protocols().contains(&ProtocolType::Kitty));
+    /// ```
+    pub fn available_protocols(&self) -> Vec<ProtocolType> {
+        let mut protocols = vec![ProtocolType::Halfblocks];
+        let mut protocol_type = ProtocolType::Halfblocks.next();
+        while protocol_type != ProtocolType::Halfblocks {
+            if self.is_available(protocol_type) {
+                protocols.push(protocol_type);
+            }
+            protocol_type = protocol_type.next();
+        }
+        protocols
+    }
+
+    /// Make a protocol available, even though it was not detected, see
+    /// [Picker::available_protocols].
+    ///
+    /// [ProtocolType::Sextants] need the Unicode 13 sextant glyphs, and [ProtocolType::Braille] the
+    /// braille glyphs, which not every font has and which cannot be detected. Enable them if the
+    /// font is known to have them, e.g. from a user setting.
+    ///
+    /// # Example
+    /// ```rust
+    /// use ratatui_image::picker::{Picker, ProtocolType};
+    ///
+    /// let mut picker = Picker::from_fontsize((7, 14));
+    /// assert!(!picker.available_protocols().contains(&ProtocolType::Sextants));
+    /// picker.enable_protocol(ProtocolType::Sextants);
+    /// assert!(picker.available_protocols().contains(&ProtocolType::Sextants));
+    /// ```
+    pub fn enable_protocol(&mut self, protocol_type: ProtocolType) {
+        self.enabled |= bit(protocol_type);
+    }
+
+    fn is_available(&self, protocol_type: ProtocolType) -> bool {
+        matches!(
+            protocol_type,
+            ProtocolType::Halfblocks | ProtocolType::Quadrants
+        ) || (self.supported | self.enabled) & bit(protocol_type) != 0
+    }
+
     /// Returns a new protocol for [`crate::Image`] widgets that fits into the given size.
+    ///
+    /// # Errors
+    /// * [Errors::ZeroSizeArea] if `size` has no width or no height.
+    /// * [Errors::ImageTooLarge] if the protocol cannot transmit an image this large, which only
+    ///   [ProtocolType::Kitty] checks.
+    /// * [Errors::EncoderFailed] if the protocol's encoder failed.
+    ///
+    /// In any case, the image can still be shown with another protocol, e.g.
+    /// [ProtocolType::Halfblocks], or replaced with some placeholder.
     pub fn new_protocol(
         &mut self,
         image: DynamicImage,
         size: Rect,
         resize: Resize,
     ) -> Result<FixedBlock> {
-        let source = ImageSource::new(image, self.font_size);
-        match self.protocol_type {
-            ProtocolType::Halfblocks => {
-                Ok(Halfblocks::from_source(&source, resize, self.background_color, size)?.into())
-            }
-            ProtocolType::Sixel => {
-                Ok(
-                    Sixel::from_source(&source, resize, self.background_color, self.is_tmux, size)?
-                        .into(),
-                )
+        let source = ImageSource::new(image, self.font_size).with_scale(self.scale);
+        self.new_protocol_from_source(self.protocol_type, &source, size, resize)
+    }
+
+    /// Like [Picker::new_protocol], but if encoding with the picked protocol fails, the other
+    /// available graphics protocols are tried, see [Picker::available_protocols], and finally
+    /// [ProtocolType::Halfblocks]. Returns the protocol that was actually used.
+    ///
+    /// [Picker::protocol_type] is not changed. Resize protocols only encode when rendering, where
+    /// nothing can fail, so there is no such fallback for [Picker::new_resize_protocol].
+    ///
+    /// # Errors
+    /// * [Errors::ZeroSizeArea] if `size` has no width or no height.
+    /// * The error of the last protocol, if all of them failed.
+    pub fn new_protocol_with_fallback(
+        &mut self,
+        image: DynamicImage,
+        size: Rect,
+        resize: Resize,
+    ) -> Result<(FixedBlock, ProtocolType)> {
+        let source = ImageSource::new(image, self.font_size).with_scale(self.scale);
+        let mut protocols = vec![self.protocol_type];
+        for protocol_type in self.available_protocols() {
+            if protocol_type.is_graphics() && !protocols.contains(&protocol_type) {
+                protocols.push(protocol_type);
             }
-            ProtocolType::Kitty => {
-                self.kitty_counter = self.kitty_counter.saturating_add(1);
-                Ok(Kitty::from_source(
-                    &source,
-                    resize,
-                    self.background_color,
-                    size,
-                    self.kitty_counter,
-                )?
-                .into())
+        }
+        if !protocols.contains(&ProtocolType::Halfblocks) {
+            protocols.push(ProtocolType::Halfblocks);
+        }
+
+        let mut error = Errors::ZeroSizeArea;
+        for protocol_type in protocols {
+            match self.new_protocol_from_source(protocol_type, &source, size, resize) {
+                Ok(protocol) => return Ok((protocol, protocol_type)),
+                Err(Errors::ZeroSizeArea) => return Err(Errors::ZeroSizeArea),
+                Err(err) => error = err,
             }
-            ProtocolType::Iterm2 => Ok(FixedIterm2::from_source(
-                &source,
+        }
+        Err(error)
+    }
+
+    /// Like [Picker::new_protocol], but decodes the image from encoded bytes, e.g. received over
+    /// the network or embedded with `include_bytes!`. The format is guessed from the contents.
+    ///
+    /// # Errors
+    /// * [Errors::ImageError] if the format is unknown or the data cannot be decoded.
+    /// * Any error of [Picker::new_protocol].
+    ///
+    /// # Example
+    /// ```rust
+    /// use ratatui::layout::Rect;
+    /// use ratatui_image::{picker::Picker, Resize};
+    ///
+    /// let mut picker = Picker::new((7, 14));
+    /// let area = Rect::new(0, 0, 10, 5);
+    /// let result = picker.new_protocol_from_bytes(b"not an image", area, Resize::Fit(None));
+    /// assert!(result.is_err());
+    /// ```
+    pub fn new_protocol_from_bytes(
+        &mut self,
+        bytes: &[u8],
+        size: Rect,
+        resize: Resize,
+    ) -> Result<FixedBlock> {
+        let source =
+            ImageSource::from_reader(Cursor::new(bytes), self.font_size)?.with_scale(self.scale);
+        self.new_protocol_from_source(self.protocol_type, &source, size, resize)
+    }
+
+    /// Like [Picker::new_protocol], for each of the image files at `paths`, e.g. for a grid of
+    /// thumbnails that all fit into `size`.
+    ///
+    /// The images are only opened and decoded as the iterator advances, with the font size and
+    /// protocol type of the picker at the time of the call. A file that fails to load does not stop
+    /// the iteration.
+    ///
+    /// # Errors
+    /// Each item is either the protocol, or:
+    /// * [Errors::IoError] if the file cannot be opened.
+    /// * [Errors::ImageError] if the format is unknown or the data cannot be decoded.
+    /// * Any error of [Picker::new_protocol].
+    ///
+    /// # Example
+    /// ```rust
+    /// use ratatui::layout::Rect;
+    /// use ratatui_image::{picker::Picker, Resize};
+    ///
+    /// let picker = Picker::new((7, 14));
+    /// let paths = ["./assets/Ada.png", "./assets/missing.png"];
+    /// let thumbnail = Rect::new(0, 0, 10, 5);
+    /// let thumbnails: Vec<_> = picker
+    ///     .new_protocols_from_paths(&paths, thumbnail, Resize::Fit(None))
+    ///     .collect();
+    /// assert!(thumbnails[0].is_ok());
+    /// assert!(thumbnails[1].is_err());
+    /// ```
+    pub fn new_protocols_from_paths<'a, P: AsRef<Path>>(
+        &self,
+        paths: &'a [P],
+        size: Rect,
+        resize: Resize,
+    ) -> impl Iterator<Item = Result<FixedBlock>> + 'a {
+        let mut picker = *self;
+        paths.iter().map(move |path| {
+            let file = BufReader::new(File::open(path)?);
+            let source = ImageSource::from_reader(file, picker.font_size)?.with_scale(picker.scale);
+            picker.new_protocol_from_source(picker.protocol_type, &source, size, resize)
+        })
+    }
+
+    fn new_protocol_from_source(
+        &mut self,
+        protocol_type: ProtocolType,
+        source: &ImageSource,
+        size: Rect,
+        resize: Resize,
+    ) -> Result<FixedBlock> {
+        if size.width == 0 || size.height == 0 {
+            return Err(Errors::ZeroSizeArea);
+        }
+        match protocol_type {
+            ProtocolType::Halfblocks => Ok(Halfblocks::from_source_with_options(
+                source,
+                resize,
+                self.background_color,
+                size,
+                Blocks::Halves,
+                self.halfblocks,
+            )?
+            .into()),
+            ProtocolType::Sixel => Ok(Sixel::from_source_with_options(
+                source,
+                resize,
+                self.background_color,
+                self.is_tmux,
+                size,
+                self.sixel,
+            )?
+            .into()),
+            ProtocolType::Kitty => Ok(Kitty::from_source_with_options(
+                source,
+                resize,
+                self.background_color,
+                size,
+                kitty::next_id(),
+                self.kitty_options(),
+            )?
+            .into()),
+            ProtocolType::Iterm2 => Ok(FixedIterm2::from_source_with_options(
+                source,
                 resize,
                 self.background_color,
                 self.is_tmux,
                 size,
+                self.iterm2,
+            )?
+            .into()),
+            ProtocolType::Quadrants => Ok(Halfblocks::from_source_with_options(
+                source,
+                resize,
+                self.background_color,
+                size,
+                Blocks::Quadrants,
+                self.halfblocks,
+            )?
+            .into()),
+            ProtocolType::Sextants => Ok(Halfblocks::from_source_with_options(
+                source,
+                resize,
+                self.background_color,
+                size,
+                Blocks::Sextants,
+                self.halfblocks,
+            )?
+            .into()),
+            ProtocolType::Braille => Ok(Braille::from_source(
+                source,
+                resize,
+                self.background_color,
+                size,
+                self.braille,
             )?
             .into()),
+            ProtocolType::Blank => Ok(Blank::from_source(source, resize, size, self.blank).into()),
+            ProtocolType::Text => Ok(Text::from_source(source, resize, size, self.text).into()),
         }
     }
 
+    /// The [Rect] of cells that [Picker::new_protocol] would produce for `image`, fit into `size`
+    /// with `resize`, without resizing or encoding it, e.g. to reserve exactly that space in a
+    /// [ratatui::layout::Layout].
+    ///
+    /// The position is relative to `size`, e.g. [Resize::Center] offsets the image.
+    ///
+    /// ```rust
+    /// use image::DynamicImage;
+    /// use ratatui::layout::Rect;
+    /// use ratatui_image::{picker::Picker, Resize};
+    ///
+    /// let picker = Picker::new((10, 20));
+    /// let image = DynamicImage::new_rgb8(200, 100);
+    /// let rect = picker.protocol_rect(&image, Rect::new(0, 0, 10, 10), &Resize::Fit(None));
+    /// assert_eq!(Rect::new(0, 0, 10, 3), rect);
+    /// ```
+    pub fn protocol_rect(&self, image: &DynamicImage, size: Rect, resize: &Resize) -> Rect {
+        let desired = ImageSource::round_pixel_size_to_cells(
+            image.width(),
+            image.height(),
+            self.font_size,
+            ImageSource::valid_scale(self.scale),
+        );
+        resize.needs_resize_rect(desired, size)
+    }
+
     /// Returns a new *resize* protocol for [`crate::StatefulImage`] widgets.
     pub fn new_resize_protocol(&mut self, image: DynamicImage) -> StatefulBlock {
-        let source = ImageSource::new(image, self.font_size);
+        let source = ImageSource::new(image, self.font_size).with_scale(self.scale);
+        self.new_resize_protocol_from_source(source)
+    }
+
+    /// Like [Picker::new_resize_protocol], but decodes the image from encoded bytes, e.g. received
+    /// over the network or embedded with `include_bytes!`. The format is guessed from the contents.
+    ///
+    /// # Errors
+    /// * [Errors::ImageError] if the format is unknown or the data cannot be decoded.
+    pub fn new_resize_protocol_from_bytes(&mut self, bytes: &[u8]) -> Result<StatefulBlock> {
+        let source =
+            ImageSource::from_reader(Cursor::new(bytes), self.font_size)?.with_scale(self.scale);
+        Ok(self.new_resize_protocol_from_source(source))
+    }
+
+    /// Returns a new animated *resize* protocol for [`crate::StatefulImage`] widgets.
+    ///
+    /// The frames can be obtained from any [image::AnimationDecoder], e.g. GIF or APNG. Use
+    /// [`crate::protocol::StatefulProtocol::advance_frame`] to animate.
+    ///
+    /// With the kitty protocol, all frames are transmitted at once and the terminal plays the
+    /// animation by itself, see [`StatefulKitty::new_animated`]. In that case
+    /// [`crate::protocol::StatefulProtocol::frame_delay`] returns `None`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use image::{codecs::gif::GifDecoder, AnimationDecoder};
+    /// use ratatui_image::{picker::Picker, protocol::StatefulProtocol};
+    ///
+    /// let mut picker = Picker::new((7, 14));
+    /// let decoder = GifDecoder::new(std::fs::File::open("./assets/Recording.gif")?)?;
+    /// let mut protocol = picker.new_animated_resize_protocol(decoder.into_frames())?;
+    /// if let Some(delay) = protocol.frame_delay() {
+    ///     // Schedule a tick after `delay`, then:
+    ///     protocol.advance_frame();
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn new_animated_resize_protocol(&mut self, frames: Frames) -> Result<StatefulBlock> {
+        let source = ImageSource::from_frames(frames, self.font_size)?.with_scale(self.scale);
+        Ok(self.new_animated_resize_protocol_from_source(source))
+    }
+
+    /// Returns a new [`StatefulAnimated`] for the frames of an animated image, which selects the
+    /// current frame from the elapsed time with [`StatefulAnimated::advance`].
+    ///
+    /// Like [`Picker::new_animated_resize_protocol`], but with the same API for every protocol,
+    /// and the encoded frames are cached so that looping does not re-encode them.
+    pub fn new_animated_protocol(&mut self, frames: Frames) -> Result<StatefulAnimated> {
+        let source = ImageSource::from_frames(frames, self.font_size)?.with_scale(self.scale);
+        let frame_count = source.frame_count();
+        let protocol = self.new_animated_resize_protocol_from_source(source);
+        Ok(StatefulAnimated::new(protocol, frame_count))
+    }
+
+    fn new_animated_resize_protocol_from_source(&mut self, source: ImageSource) -> StatefulBlock {
+        if self.protocol_type == ProtocolType::Kitty {
+            let mut kitty = StatefulKitty::new_animated(source, kitty::next_id());
+            kitty.set_options(self.kitty_options());
+            return kitty.into();
+        }
+        self.new_resize_protocol_from_source(source)
+    }
+
+    fn new_resize_protocol_from_source(&mut self, source: ImageSource) -> StatefulBlock {
         match self.protocol_type {
-            ProtocolType::Halfblocks => StatefulHalfblocks::new(source).into(),
-            ProtocolType::Sixel => StatefulSixel::new(source, self.is_tmux).into(),
+            ProtocolType::Halfblocks => self.halfblocks(source, Blocks::Halves).into(),
+            ProtocolType::Sixel => {
+                let mut sixel = StatefulSixel::new(source, self.is_tmux);
+                sixel.set_options(self.sixel);
+                sixel.into()
+            }
             ProtocolType::Kitty => {
-                self.kitty_counter = self.kitty_counter.saturating_add(1);
-                StatefulKitty::new(source, self.kitty_counter).into()
+                let mut kitty = StatefulKitty::new(source, kitty::next_id());
+                kitty.set_options(self.kitty_options());
+                kitty.into()
             }
-            ProtocolType::Iterm2 => Iterm2State::new(source, self.is_tmux).into(),
+            ProtocolType::Iterm2 => {
+                let mut iterm2 = Iterm2State::new(source, self.is_tmux);
+                iterm2.set_options(self.iterm2);
+                iterm2.into()
+            }
+            ProtocolType::Quadrants => self.halfblocks(source, Blocks::Quadrants).into(),
+            ProtocolType::Sextants => self.halfblocks(source, Blocks::Sextants).into(),
+            ProtocolType::Braille => StatefulBraille::new(source, self.braille).into(),
+            ProtocolType::Blank => StatefulBlank::new(source, self.blank).into(),
+            ProtocolType::Text => StatefulText::new(source, self.text).into(),
+        }
+    }
+
+    /// [Picker::kitty], wrapped in tmux's passthrough if [Picker::is_tmux].
+    fn kitty_options(&self) -> KittyOptions {
+        KittyOptions {
+            tmux: self.kitty.tmux || self.is_tmux,
+            ..self.kitty
         }
     }
 
+    fn halfblocks(&self, source: ImageSource, blocks: Blocks) -> StatefulHalfblocks {
+        let mut halfblocks = StatefulHalfblocks::new_with_blocks(source, blocks);
+        halfblocks.set_options(self.halfblocks);
+        halfblocks
+    }
+
     // pub fn nrp(&mut self, image: DynamicImage) -> impl StatefulProtocol {
     //     let source = ImageSource::new(image, self.font_size);
     //     match self.protocol_type {
     //         ProtocolType::Halfblocks => StatefulHalfblocks::new(source),
     //         ProtocolType::Sixel => StatefulSixel::new(source, self.is_tmux),
-    //         ProtocolType::Kitty => {
-    //             self.kitty_counter = self.kitty_counter.saturating_add(1);
-    //             StatefulKitty::new(source, self.kitty_counter)
-    //         }
+    //         ProtocolType::Kitty => StatefulKitty::new(source, kitty::next_id()),
     //         ProtocolType::Iterm2 => Iterm2State::new(source, self.is_tmux),
     //     }
     // }
@@ -188,22 +919,27 @@ pub fn font_size(winsize: Winsize) -> Result<FontSize> {
         ws_row: rows,
     } = winsize;
     if x == 0 || y == 0 || cols == 0 || rows == 0 {
-        return Err("font_size zero value".into());
+        return Err(Errors::FontSizeUnknown);
     }
     Ok((x / cols, y / rows))
 }
 
-// Guess what protocol should be used, first from some program-specific magical env vars, then with
-// the typical $TERM* env vars, and then with termios stdin/out queries.
-fn guess_protocol() -> (ProtocolType, bool) {
+/// Bit of a protocol in [Picker::supported].
+fn bit(protocol_type: ProtocolType) -> u32 {
+    1 << protocol_type as u32
+}
+
+// Guess what protocols are supported, best first, first from some program-specific magical env
+// vars, then with the typical $TERM* env vars, and then with termios stdin/out queries.
+fn guess_protocol() -> (Vec<ProtocolType>, bool, SixelGraphics) {
     // Start with some basic env vars.
     let mut is_tmux = false;
     if let Ok(term) = env::var("TERM") {
         if term == "mlterm" || term == "yaft-256color" {
-            return (ProtocolType::Sixel, is_tmux);
+            return (vec![ProtocolType::Sixel], is_tmux, SixelGraphics::default());
         }
         if term.contains("kitty") {
-            return (ProtocolType::Kitty, is_tmux);
+            return (vec![ProtocolType::Kitty], is_tmux, SixelGraphics::default());
         }
         if term.starts_with("tmux") {
             is_tmux = true;
@@ -211,45 +947,117 @@ fn guess_protocol() -> (ProtocolType, bool) {
     }
     if let Ok(term_program) = env::var("TERM_PROGRAM") {
         if term_program == "MacTerm" {
-            return (ProtocolType::Sixel, is_tmux);
+            return (vec![ProtocolType::Sixel], is_tmux, SixelGraphics::default());
         }
+        // VS Code's terminal is not guessed here, since its images are disabled by default, and
+        // only the query tells whether they are enabled.
         if term_program.contains("iTerm") || term_program.contains("WezTerm") {
-            return (ProtocolType::Iterm2, is_tmux);
+            return (
+                vec![ProtocolType::Iterm2],
+                is_tmux,
+                SixelGraphics::default(),
+            );
         }
         if term_program == "tmux" {
             is_tmux = true;
         }
     }
+    if env_exists("TMUX") {
+        is_tmux = true;
+    }
     if let Ok(lc_term) = env::var("LC_TERMINAL") {
         if lc_term.contains("iTerm") {
-            return (ProtocolType::Iterm2, is_tmux);
+            return (
+                vec![ProtocolType::Iterm2],
+                is_tmux,
+                SixelGraphics::default(),
+            );
         }
     }
 
     if is_tmux {
-        let _ = std::process::Command::new("tmux")
-            .args(["set", "-p", "allow-passthrough", "on"])
-            .stdin(std::process::Stdio::null())
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .spawn()
-            .and_then(|mut child| child.wait()); // wait(), for check_device_attrs.
+        enable_tmux_passthrough();
 
         // Only if we're in tmux, take a risky guess because $TERM has been overwritten.
         // The core issue is that iterm2 support cannot be queried, like kitty or sixel.
         if let Some(proto) = guess_protocol_magic_env_var_exist() {
-            return (proto, is_tmux);
+            return (vec![proto], is_tmux, SixelGraphics::default());
         }
     }
 
-    // No hardcoded stuff worked, try querying the terminal now.
+    // No hardcoded stuff worked, try querying the terminal now, unless the queries would end up in
+    // redirected output.
     #[cfg(all(feature = "rustix", unix))]
-    if let Ok(proto) = query_device_attrs(is_tmux) {
-        return (proto, is_tmux);
+    if rustix::termios::isatty(rustix::stdio::stdout()) {
+        if let Ok((protocols, graphics)) = query_device_attrs(is_tmux) {
+            return (protocols, is_tmux, graphics);
+        }
     }
 
     // Fallback.
-    (ProtocolType::Halfblocks, is_tmux)
+    (
+        vec![ProtocolType::Halfblocks],
+        is_tmux,
+        SixelGraphics::default(),
+    )
+}
+
+/// The sixel attributes that the terminal replied to the `XTSMGRAPHICS` queries with, if any.
+#[derive(Clone, Copy, Default)]
+struct SixelGraphics {
+    color_registers: Option<u16>,
+    max_size: Option<(u32, u32)>,
+}
+
+impl SixelGraphics {
+    /// Parse the `XTSMGRAPHICS` replies, see [parse_color_registers] and [parse_sixel_geometry].
+    #[cfg(all(feature = "rustix", unix))]
+    fn parse(reply: &str) -> SixelGraphics {
+        SixelGraphics {
+            color_registers: parse_color_registers(reply),
+            max_size: parse_sixel_geometry(reply),
+        }
+    }
+
+    /// Set the replied attributes on `options`, keeping the others.
+    fn apply(self, options: &mut SixelOptions) {
+        if self.color_registers.is_some() {
+            options.color_registers = self.color_registers;
+        }
+        if self.max_size.is_some() {
+            options.max_size = self.max_size;
+        }
+    }
+}
+
+/// Truecolor, unless `term` claims 256 colors and `colorterm` does not claim truecolor.
+fn halfblocks_colors(colorterm: Option<&str>, term: Option<&str>) -> HalfblocksColors {
+    let truecolor = matches!(colorterm, Some("truecolor" | "24bit"));
+    let indexed = term.map_or(false, |term| term.ends_with("256color"));
+    if indexed && !truecolor {
+        HalfblocksColors::Indexed
+    } else {
+        HalfblocksColors::Truecolor
+    }
+}
+
+/// Check if we are running inside tmux, based on `$TMUX`, `$TERM` and `$TERM_PROGRAM`.
+#[cfg(all(feature = "rustix", unix))]
+fn is_tmux() -> bool {
+    env_exists("TMUX")
+        || env::var("TERM").map_or(false, |term| term.starts_with("tmux"))
+        || env::var("TERM_PROGRAM").map_or(false, |term_program| term_program == "tmux")
+}
+
+/// Tell tmux to pass through the graphics escape sequences.
+fn enable_tmux_passthrough() {
+    let _ = std::process::Command::new("tmux")
+        .args(["set", "-p", "allow-passthrough", "on"])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .and_then(|mut child| child.wait()); // wait(), for check_device_attrs.
 }
 
 /// Crude guess based on the *existance* of some magic program specific env vars.
@@ -267,7 +1075,7 @@ fn guess_protocol_magic_env_var_exist() -> Option<ProtocolType> {
 
 #[inline]
 pub fn env_exists(name: &str) -> bool {
-    env::var_os(name).is_some_and(|s| !s.is_empty())
+    env::var_os(name).map_or(false, |s| !s.is_empty())
 }
 
 #[cfg(all(feature = "rustix", unix))]
@@ -284,8 +1092,47 @@ pub fn env_exists(name: &str) -> bool {
 /// * wezterm
 /// * foot
 /// * konsole (kitty protocol)
+///
 /// NOTE: "tested" means that it guesses correctly, not necessarily rendering correctly.
-fn query_device_attrs(is_tmux: bool) -> Result<ProtocolType> {
+///
+/// Also queries the number of sixel color registers and the maximum sixel size with
+/// `XTSMGRAPHICS`, if the terminal replies.
+///
+/// Returns all detected protocols, best first.
+fn query_device_attrs(is_tmux: bool) -> Result<(Vec<ProtocolType>, SixelGraphics)> {
+    // Queries first for kitty support with `_Gi=...<ESC>\`, then for the number of sixel color
+    // registers with `<ESC>[?1;1;0S` and the maximum sixel size with `<ESC>[?2;1;0S`, and then
+    // for "graphics attributes" (sixel) with `<ESC>[c`.
+    // See https://sw.kovidgoyal.net/kitty/graphics-protocol/#querying-support-and-available-transmission-mediums
+    let buf = query_stdio(is_tmux, |escape| {
+        format!("{escape}_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA{escape}\\{escape}[?1;1;0S{escape}[?2;1;0S{escape}[c")
+    })?;
+    if buf.is_empty() {
+        return Err("no reply to graphics support query".into());
+    }
+
+    let protocols = parse_device_attrs(&buf);
+    if protocols.is_empty() {
+        return Err("graphics support not detected".into());
+    }
+    Ok((protocols, SixelGraphics::parse(&buf)))
+}
+
+/// Query the font size and the text area size in pixels, from the text area size in pixels, with
+/// `<ESC>[14t`, and in cells, with `<ESC>[18t`. The `DA1` query `<ESC>[c` is last, since every
+/// terminal replies to it.
+#[cfg(all(feature = "rustix", unix))]
+fn query_font_size(is_tmux: bool) -> Result<(FontSize, (u16, u16))> {
+    let buf = query_stdio(is_tmux, |escape| {
+        format!("{escape}[14t{escape}[18t{escape}[c")
+    })?;
+    parse_text_area(&buf).ok_or(Errors::FontSizeUnknown)
+}
+
+/// Write the queries that `query` formats with the escape character to stdout, and read the
+/// replies from stdin, with a timeout. Wraps the queries in tmux passthrough if `is_tmux`.
+#[cfg(all(feature = "rustix", unix))]
+fn query_stdio(is_tmux: bool, query: impl FnOnce(&str) -> String) -> Result<String> {
     use rustix::termios::{LocalModes, OptionalActions};
 
     let stdin = rustix::stdio::stdin();
@@ -305,17 +1152,12 @@ fn query_device_attrs(is_tmux: bool) -> Result<ProtocolType> {
     });
 
     let (start, escape, end) = if is_tmux {
-        ("\x1bPtmux;\x1b\x1b", "\x1b\x1b", "\x1b\\")
+        ("\x1bPtmux;", "\x1b\x1b", "\x1b\\")
     } else {
-        ("\x1b", "\x1b", "")
+        ("", "\x1b", "")
     };
-    rustix::io::write(
-        rustix::stdio::stdout(),
-        // Queries first for kitty support with `_Gi=...<ESC>\` and then for "graphics attributes"
-        // (sixel) with `<ESC>[c`.
-        // See https://sw.kovidgoyal.net/kitty/graphics-protocol/#querying-support-and-available-transmission-mediums
-        format!("{start}_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA{escape}\\{escape}[c{end}").as_bytes(),
-    )?;
+    let query = format!("{start}{}{end}", query(escape));
+    rustix::io::write(rustix::stdio::stdout(), query.as_bytes())?;
 
     let buf = read_stdin(
         1000,
@@ -327,24 +1169,73 @@ fn query_device_attrs(is_tmux: bool) -> Result<ProtocolType> {
             }
         },
         fd_flags_original.is_ok(),
-    )?;
-    if buf.is_empty() {
-        return Err("no reply to graphics support query".into());
-    }
+    );
 
-    // Reset to previous mode and status, and termios attributes.
+    // Reset to previous mode and status, and termios attributes, even if reading failed.
     if let Ok(fd_flags_original) = fd_flags_original {
         rustix::fs::fcntl_setfl(stdin, fd_flags_original)?;
     }
     rustix::termios::tcsetattr(stdin, OptionalActions::Now, &termios_original)?;
 
-    if buf.contains("_Gi=31;OK") {
-        return Ok(ProtocolType::Kitty);
+    Ok(buf?)
+}
+
+/// Parse the graphics protocols from the replies to the kitty and `DA1` queries, best first.
+#[cfg(all(feature = "rustix", unix))]
+fn parse_device_attrs(reply: &str) -> Vec<ProtocolType> {
+    let mut protocols = Vec::new();
+    if reply.contains("_Gi=31;OK") {
+        protocols.push(ProtocolType::Kitty);
+    }
+    if reply.contains(";4;")
+        || reply.contains("?4;")
+        || reply.contains(";4c")
+        || reply.contains("?4c")
+    {
+        protocols.push(ProtocolType::Sixel);
+    }
+    protocols
+}
+
+/// Parse the number of color registers from a `XTSMGRAPHICS` reply, `<ESC>[?1;0;<n>S`.
+#[cfg(all(feature = "rustix", unix))]
+fn parse_color_registers(reply: &str) -> Option<u16> {
+    let (_, rest) = reply.split_once("[?1;0;")?;
+    let (registers, _) = rest.split_once('S')?;
+    registers.parse().ok().filter(|&registers| registers > 0)
+}
+
+/// Parse the maximum sixel size in pixels from a `XTSMGRAPHICS` reply,
+/// `<ESC>[?2;0;<width>;<height>S`.
+#[cfg(all(feature = "rustix", unix))]
+fn parse_sixel_geometry(reply: &str) -> Option<(u32, u32)> {
+    let (_, rest) = reply.split_once("[?2;0;")?;
+    let (geometry, _) = rest.split_once('S')?;
+    let (width, height) = geometry.split_once(';')?;
+    let size = (width.parse().ok()?, height.parse().ok()?);
+    Some(size).filter(|&(width, height)| width > 0 && height > 0)
+}
+
+/// Parse the font size and the text area size in pixels from the replies to the text area
+/// queries, `<ESC>[4;<height>;<width>t` in pixels and `<ESC>[8;<rows>;<cols>t` in cells.
+#[cfg(all(feature = "rustix", unix))]
+fn parse_text_area(reply: &str) -> Option<(FontSize, (u16, u16))> {
+    fn size(reply: &str, prefix: &str) -> Option<(u16, u16)> {
+        let (_, rest) = reply.split_once(prefix)?;
+        let (size, _) = rest.split_once('t')?;
+        let (height, width) = size.split_once(';')?;
+        Some((width.parse().ok()?, height.parse().ok()?))
+    }
+    let (width, height) = size(reply, "[4;")?;
+    let (cols, rows) = size(reply, "[8;")?;
+    if cols == 0 || rows == 0 {
+        return None;
     }
-    if buf.contains(";4;") || buf.contains("?4;") || buf.contains(";4c") || buf.contains("?4c") {
-        return Ok(ProtocolType::Sixel);
+    let font_size = (width / cols, height / rows);
+    if font_size.0 == 0 || font_size.1 == 0 {
+        return None;
     }
-    Err("graphics support not detected".into())
+    Some((font_size, (width, height)))
 }
 
 pub fn read_stdin(
@@ -398,37 +1289,464 @@ pub fn read_stdin(
 mod tests {
     use std::{
         assert_eq,
-        io::{self},
+        io::{self, Cursor},
     };
 
-    use crate::picker::{font_size, read_stdin, Picker, ProtocolType};
+    use image::{DynamicImage, ImageBuffer, ImageOutputFormat, Rgb};
+    use ratatui::layout::Rect;
     use rustix::termios::Winsize;
 
+    use crate::{
+        errors::Errors,
+        picker::{
+            bit, font_size, halfblocks_colors, parse_color_registers, parse_device_attrs,
+            parse_sixel_geometry, parse_text_area, read_stdin, FontSizeSource, Picker,
+            ProtocolType, SixelGraphics,
+        },
+        protocol::{
+            halfblocks::HalfblocksColors, FixedBlock, Protocol, StatefulBlock, StatefulProtocol,
+        },
+        Resize,
+    };
+
     #[test]
     fn test_font_size() {
-        assert!(font_size(Winsize {
-            ws_row: 0,
-            ws_col: 0,
-            ws_xpixel: 10,
-            ws_ypixel: 10
-        })
-        .is_err());
-        assert!(font_size(Winsize {
-            ws_row: 10,
-            ws_col: 10,
-            ws_xpixel: 0,
-            ws_ypixel: 0
-        })
-        .is_err());
+        assert!(matches!(
+            font_size(Winsize {
+                ws_row: 0,
+                ws_col: 0,
+                ws_xpixel: 10,
+                ws_ypixel: 10
+            }),
+            Err(Errors::FontSizeUnknown)
+        ));
+        assert!(matches!(
+            font_size(Winsize {
+                ws_row: 10,
+                ws_col: 10,
+                ws_xpixel: 0,
+                ws_ypixel: 0
+            }),
+            Err(Errors::FontSizeUnknown)
+        ));
+        assert_eq!(
+            (8, 16),
+            font_size(Winsize {
+                ws_row: 10,
+                ws_col: 10,
+                ws_xpixel: 80,
+                ws_ypixel: 160
+            })
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn color_registers() {
+        assert_eq!(
+            Some(256),
+            parse_color_registers("\x1b[?1;0;256S\x1b[?62;4;22c")
+        );
+        assert_eq!(Some(16), parse_color_registers("\x1b[?1;0;16S"));
+        // Error or failure status.
+        assert_eq!(None, parse_color_registers("\x1b[?1;3;0S\x1b[?62;4c"));
+        assert_eq!(None, parse_color_registers("\x1b[?62;4c"));
+    }
+
+    #[test]
+    fn sixel_geometry() {
+        assert_eq!(
+            Some((1000, 1000)),
+            parse_sixel_geometry("\x1b[?1;0;256S\x1b[?2;0;1000;1000S\x1b[?62;4;22c")
+        );
+        // Error or failure status.
+        assert_eq!(None, parse_sixel_geometry("\x1b[?2;3;0S\x1b[?62;4c"));
+        assert_eq!(None, parse_sixel_geometry("\x1b[?1;0;256S"));
+    }
+
+    #[test]
+    fn sixel_graphics_apply() {
+        let mut picker = Picker::new((1, 1));
+        picker.set_protocol_type(ProtocolType::Sixel);
+        SixelGraphics::parse("\x1b[?1;0;16S\x1b[?2;0;100;50S\x1b[?62;4;22c")
+            .apply(&mut picker.sixel);
+        assert_eq!(Some(16), picker.sixel.color_registers);
+        assert_eq!(Some((100, 50)), picker.sixel.max_size);
+
+        // No XTSMGRAPHICS reply keeps the attributes.
+        SixelGraphics::parse("\x1b[?62;4;22c").apply(&mut picker.sixel);
+        assert_eq!(Some((100, 50)), picker.sixel.max_size);
+
+        let image: DynamicImage = ImageBuffer::from_pixel(200, 200, Rgb([255u8, 0, 0])).into();
+        let area = Rect::new(0, 0, 200, 200);
+        match picker.new_protocol(image, area, Resize::Fit(None)).unwrap() {
+            FixedBlock::Sixel(sixel) => {
+                assert!(sixel.clamped);
+                assert_eq!(Rect::new(0, 0, 200, 200), sixel.rect);
+                // The raster attributes of the shrunk image.
+                assert!(sixel.data.contains("\"1;1;50;50"));
+            }
+            _ => panic!("expected sixel"),
+        }
+    }
+
+    #[test]
+    fn text_area() {
+        assert_eq!(
+            Some(((8, 16), (640, 384))),
+            parse_text_area("\x1b[4;384;640t\x1b[8;24;80t\x1b[?62;4c")
+        );
+        // No reply to the pixel size query.
+        assert_eq!(None, parse_text_area("\x1b[8;24;80t\x1b[?62;4c"));
+        assert_eq!(None, parse_text_area("\x1b[4;0;0t\x1b[8;24;80t"));
+        assert_eq!(None, parse_text_area("\x1b[4;384;640t\x1b[8;0;0t"));
+    }
+
+    #[test]
+    fn supports_graphics() {
+        let mut picker = Picker::new((7, 14));
+        assert!(!picker.supports_graphics());
+        picker.protocol_type = ProtocolType::Kitty;
+        assert!(picker.supports_graphics());
+        assert_eq!(ProtocolType::Kitty, picker.protocol_type());
+        assert!(!ProtocolType::Sextants.is_graphics());
+    }
+
+    #[test]
+    fn set_protocol_type() {
+        let mut picker = Picker::new((1, 1));
+        picker.set_protocol_type(ProtocolType::Sixel);
+        assert_eq!(ProtocolType::Sixel, picker.protocol_type());
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgb([255u8, 0, 0])).into();
+        assert!(matches!(
+            picker.new_resize_protocol(image),
+            crate::protocol::StatefulBlock::Sixel(_)
+        ));
+    }
+
+    #[test]
+    fn protocols_from_paths() {
+        let mut picker = Picker::new((8, 16));
+        picker.protocol_type = ProtocolType::Text;
+        let paths = ["./assets/Ada.png", "./assets/missing.png", "./Cargo.toml"];
+        let area = Rect::new(0, 0, 10, 5);
+        let mut protocols = picker.new_protocols_from_paths(&paths, area, Resize::Fit(None));
+        assert!(matches!(protocols.next(), Some(Ok(FixedBlock::Text(_)))));
+        assert!(matches!(protocols.next(), Some(Err(Errors::IoError(_)))));
+        assert!(matches!(protocols.next(), Some(Err(Errors::ImageError(_)))));
+        assert!(protocols.next().is_none());
+    }
+
+    #[test]
+    fn guess_halfblocks_colors() {
+        let colors = |colorterm, term| halfblocks_colors(colorterm, term);
+        assert_eq!(
+            HalfblocksColors::Indexed,
+            colors(None, Some("xterm-256color"))
+        );
+        assert_eq!(
+            HalfblocksColors::Truecolor,
+            colors(Some("truecolor"), Some("xterm-256color"))
+        );
+        assert_eq!(
+            HalfblocksColors::Truecolor,
+            colors(Some("24bit"), Some("tmux-256color"))
+        );
+        assert_eq!(
+            HalfblocksColors::Truecolor,
+            colors(None, Some("xterm-kitty"))
+        );
+        assert_eq!(HalfblocksColors::Truecolor, colors(None, None));
+    }
+
+    #[test]
+    fn tmux_passthrough() {
+        let mut picker = Picker::new((1, 1));
+        picker.is_tmux = true;
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgb([255u8, 0, 0])).into();
+        let area = Rect::new(0, 0, 2, 2);
+        for protocol_type in [
+            ProtocolType::Sixel,
+            ProtocolType::Kitty,
+            ProtocolType::Iterm2,
+        ] {
+            picker.protocol_type = protocol_type;
+            let mut protocol = picker.new_resize_protocol(image.clone());
+            let mut buf = ratatui::buffer::Buffer::empty(area);
+            protocol.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+            let symbol = &buf.get(0, 0).symbol;
+            assert!(symbol.contains("\x1bPtmux;\x1b\x1b"), "{protocol_type}");
+        }
+    }
+
+    #[test]
+    fn capabilities() {
+        assert!(ProtocolType::Iterm2.is_truecolor());
+        assert!(!ProtocolType::Sixel.is_truecolor());
+        assert!(!ProtocolType::Halfblocks.is_truecolor());
+        assert!(ProtocolType::Kitty.supports_transparency());
+        assert!(ProtocolType::Iterm2.supports_transparency());
+        assert!(!ProtocolType::Sixel.supports_transparency());
+    }
+
+    #[test]
+    fn parse_protocol_type() {
+        let mut all = vec![ProtocolType::Blank, ProtocolType::Text];
+        let mut protocol_type = ProtocolType::Halfblocks;
+        loop {
+            all.push(protocol_type);
+            protocol_type = protocol_type.next();
+            if protocol_type == ProtocolType::Halfblocks {
+                break;
+            }
+        }
+        assert_eq!(9, all.len());
+        for protocol_type in all {
+            let parsed: ProtocolType = protocol_type.to_string().parse().unwrap();
+            assert_eq!(protocol_type, parsed);
+        }
+        assert_eq!(ProtocolType::Iterm2, " ITerm2 ".parse().unwrap());
+        let err = "kitten".parse::<ProtocolType>().unwrap_err();
+        assert!(matches!(&err, Errors::UnknownProtocol(s) if s == "kitten"));
+        assert!(err.to_string().contains("expected one of halfblocks"));
+    }
+
+    #[test]
+    fn font_size_override() {
+        let mut picker = Picker::new((7, 14));
+        assert!(matches!(
+            picker.set_font_size((0, 16)),
+            Err(Errors::InvalidFontSize((0, 16)))
+        ));
+        assert_eq!((7, 14), picker.font_size);
+        let picker = picker.with_font_size((8, 16)).unwrap();
+        assert_eq!((8, 16), picker.font_size);
+        assert_eq!(FontSizeSource::Manual, picker.font_size_source);
+        assert_eq!(None, picker.screen_size_pixels());
+
+        // Cells are computed with the overridden font size.
+        let mut picker = Picker::new((8, 8)).with_font_size((8, 16)).unwrap();
+        let image: DynamicImage = ImageBuffer::from_pixel(16, 32, Rgb([255u8, 0, 0])).into();
+        let mut protocol = picker.new_resize_protocol(image);
+        let area = Rect::new(0, 0, 10, 10);
+        assert_eq!(
+            Some(Rect::new(0, 0, 2, 2)),
+            protocol.needs_resize(&Resize::Fit(None), area)
+        );
+    }
+
+    #[test]
+    fn scale() {
+        let mut picker = Picker::new((8, 16));
+        let image: DynamicImage = ImageBuffer::from_pixel(16, 32, Rgb([255u8, 0, 0])).into();
+        let area = Rect::new(0, 0, 10, 10);
+
+        picker.scale = 0.5;
+        let mut protocol = picker.new_resize_protocol(image.clone());
+        protocol.resize_encode(&Resize::Fit(None), None, area);
+        assert_eq!(Rect::new(0, 0, 4, 4), protocol.rect());
+        let fixed = picker
+            .new_protocol(image.clone(), area, Resize::Fit(None))
+            .unwrap();
+        assert_eq!(Rect::new(0, 0, 4, 4), fixed.rect());
+        assert_eq!(
+            fixed.rect(),
+            picker.protocol_rect(&image, area, &Resize::Fit(None))
+        );
+
+        // Fractional scales round up to whole cells.
+        picker.scale = 1.5;
+        let mut protocol = picker.new_resize_protocol(image);
+        protocol.resize_encode(&Resize::Fit(None), None, area);
+        assert_eq!(Rect::new(0, 0, 2, 2), protocol.rect());
+    }
+
+    #[test]
+    fn new_protocol_errors() {
+        let image: DynamicImage = ImageBuffer::from_pixel(1, 1, Rgb([255u8, 0, 0])).into();
+        let mut picker = Picker::new((1, 1));
+        assert!(matches!(
+            picker.new_protocol(image.clone(), Rect::new(0, 0, 0, 1), Resize::Fit(None)),
+            Err(Errors::ZeroSizeArea)
+        ));
+
+        let image: DynamicImage = ImageBuffer::from_pixel(10001, 1, Rgb([255u8, 0, 0])).into();
+        picker.protocol_type = ProtocolType::Kitty;
+        assert!(matches!(
+            picker.new_protocol(image, Rect::new(0, 0, 10001, 1), Resize::Fit(None)),
+            Err(Errors::ImageTooLarge {
+                width: 10001,
+                height: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn new_protocol_with_fallback() {
+        let image: DynamicImage = ImageBuffer::from_pixel(10001, 1, Rgb([255u8, 0, 0])).into();
+        let area = Rect::new(0, 0, 10001, 1);
+        let mut picker = Picker::new((1, 1));
+        picker.set_supported(&[ProtocolType::Kitty, ProtocolType::Sixel]);
+
+        let (protocol, protocol_type) = picker
+            .new_protocol_with_fallback(image.clone(), area, Resize::Fit(None))
+            .unwrap();
+        assert_eq!(ProtocolType::Sixel, protocol_type);
+        assert!(matches!(protocol, FixedBlock::Sixel(_)));
+        assert_eq!(ProtocolType::Kitty, picker.protocol_type);
+
+        picker.set_supported(&[ProtocolType::Kitty]);
+        let (protocol, protocol_type) = picker
+            .new_protocol_with_fallback(image.clone(), area, Resize::Fit(None))
+            .unwrap();
+        assert_eq!(ProtocolType::Halfblocks, protocol_type);
+        assert_eq!(Rect::new(0, 0, 10001, 1), protocol.rect());
+
+        assert!(matches!(
+            picker.new_protocol_with_fallback(image, Rect::default(), Resize::Fit(None)),
+            Err(Errors::ZeroSizeArea)
+        ));
+    }
+
+    #[test]
+    fn from_bytes() {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 4, Rgb([255u8, 0, 0])).into();
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+        let mut picker = Picker::new((2, 2));
+
+        let protocol = picker
+            .new_protocol_from_bytes(&png, Rect::new(0, 0, 10, 10), Resize::Fit(None))
+            .unwrap();
+        assert_eq!(Rect::new(0, 0, 2, 2), protocol.rect());
+        let mut protocol = picker.new_resize_protocol_from_bytes(&png).unwrap();
+        assert_eq!(
+            Some(Rect::new(0, 0, 2, 2)),
+            protocol.needs_resize(&Resize::Fit(None), Rect::new(0, 0, 10, 10))
+        );
+
+        assert!(matches!(
+            picker.new_resize_protocol_from_bytes(&png[..png.len() / 2]),
+            Err(Errors::ImageError(_))
+        ));
+        assert!(matches!(
+            picker.new_protocol_from_bytes(b"garbage", Rect::new(0, 0, 1, 1), Resize::Fit(None)),
+            Err(Errors::ImageError(_))
+        ));
+    }
+
+    #[test]
+    fn text_protocol() {
+        let image: DynamicImage = ImageBuffer::from_pixel(640, 480, Rgb([255u8, 0, 0])).into();
+        let mut picker = Picker::new((8, 16));
+        picker.set_protocol_type(ProtocolType::Text);
+        let protocol = picker
+            .new_protocol(image.clone(), Rect::new(0, 0, 80, 30), Resize::Fit(None))
+            .unwrap();
+        assert!(protocol.encoded().contains("[img 640x480]"));
+        assert!(matches!(
+            picker.new_resize_protocol(image),
+            StatefulBlock::Text(_)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "webp")]
+    fn webp_from_bytes() {
+        use base64::{engine::general_purpose, Engine};
+
+        // Lossless 1x1 WebP.
+        let webp = general_purpose::STANDARD
+            .decode("UklGRhoAAABXRUJQVlA4TA0AAAAvAAAAEAcQERGIiP4HAA==")
+            .unwrap();
+        let mut picker = Picker::new((1, 2));
+        picker.protocol_type = ProtocolType::Halfblocks;
+        let protocol = picker
+            .new_protocol_from_bytes(&webp, Rect::new(0, 0, 4, 4), Resize::Fit(None))
+            .unwrap();
+        assert_eq!(Rect::new(0, 0, 1, 1), protocol.rect());
+        assert!(!protocol.encoded().trim().is_empty());
     }
 
     #[test]
     fn test_cycle_protocol() {
         let mut picker = Picker::new((1, 1));
+        assert_eq!(picker.cycle_protocols(), ProtocolType::Quadrants);
+        assert_eq!(picker.cycle_protocols(), ProtocolType::Halfblocks);
+
+        // Not detected, so only once enabled.
+        picker.enable_protocol(ProtocolType::Sextants);
+        picker.enable_protocol(ProtocolType::Braille);
+        assert_eq!(picker.cycle_protocols(), ProtocolType::Quadrants);
+        assert_eq!(picker.cycle_protocols(), ProtocolType::Sextants);
+        assert_eq!(picker.cycle_protocols(), ProtocolType::Braille);
+        assert_eq!(picker.cycle_protocols(), ProtocolType::Halfblocks);
+
+        // Still enabled after detecting the graphics protocols.
+        picker.set_supported(&[ProtocolType::Kitty, ProtocolType::Sixel]);
+        assert_eq!(ProtocolType::Kitty, picker.protocol_type);
+        assert_eq!(picker.cycle_protocols(), ProtocolType::Quadrants);
+        assert_eq!(picker.cycle_protocols(), ProtocolType::Sextants);
+        assert_eq!(picker.cycle_protocols(), ProtocolType::Braille);
+        assert_eq!(picker.cycle_protocols(), ProtocolType::Halfblocks);
         assert_eq!(picker.cycle_protocols(), ProtocolType::Sixel);
         assert_eq!(picker.cycle_protocols(), ProtocolType::Kitty);
-        assert_eq!(picker.cycle_protocols(), ProtocolType::Iterm2);
+        assert_eq!(
+            vec![
+                ProtocolType::Halfblocks,
+                ProtocolType::Sixel,
+                ProtocolType::Kitty,
+                ProtocolType::Quadrants,
+                ProtocolType::Sextants,
+                ProtocolType::Braille,
+            ],
+            picker.available_protocols()
+        );
+
+        // Not a terminal: only the text-based protocols.
+        picker.set_supported(&[ProtocolType::Blank]);
+        assert_eq!(ProtocolType::Blank, picker.protocol_type);
         assert_eq!(picker.cycle_protocols(), ProtocolType::Halfblocks);
+        assert!(!picker.available_protocols().iter().any(|p| p.is_graphics()));
+    }
+
+    #[test]
+    fn supported_bits() {
+        let mut picker = Picker::new((1, 1));
+        picker.set_supported(&[ProtocolType::Text, ProtocolType::Kitty]);
+        assert_eq!(ProtocolType::Text, picker.protocol_type);
+        assert!(picker.is_available(ProtocolType::Kitty));
+        assert!(!picker.is_available(ProtocolType::Sixel));
+        assert_ne!(bit(ProtocolType::Text), bit(ProtocolType::Halfblocks));
+    }
+
+    #[test]
+    #[cfg(all(feature = "rustix", unix))]
+    fn blank_unless_tty() {
+        let mut picker = Picker::new((1, 1));
+        picker.set_supported(&[ProtocolType::Kitty]);
+        let null = std::fs::File::open("/dev/null").unwrap();
+        assert_eq!(ProtocolType::Blank, picker.blank_unless_tty(&null));
+        assert!(!picker.available_protocols().iter().any(|p| p.is_graphics()));
+    }
+
+    #[test]
+    fn test_parse_device_attrs() {
+        assert_eq!(
+            vec![ProtocolType::Kitty, ProtocolType::Sixel],
+            parse_device_attrs("\x1b_Gi=31;OK\x1b\\\x1b[?1;0;256S\x1b[?62;4;22c")
+        );
+        assert_eq!(
+            vec![ProtocolType::Sixel],
+            parse_device_attrs("\x1b[?1;0;256S\x1b[?62;4;22c")
+        );
+        assert_eq!(
+            vec![ProtocolType::Kitty],
+            parse_device_attrs("\x1b_Gi=31;OK\x1b\\\x1b[?62;22c")
+        );
+        assert!(parse_device_attrs("\x1b[?62;22c").is_empty());
     }
 
     #[derive(Clone)]
@@ -437,7 +1755,7 @@ mod tests {
         data: &'a str,
     }
 
-    fn test_stdin(wouldblock_count: u32, data: &str) -> TestStdin {
+    fn test_stdin(wouldblock_count: u32, data: &str) -> TestStdin<'_> {
         TestStdin {
             wouldblock_count,
             data,
@@ -486,6 +1804,29 @@ mod tests {
         assert_eq!(io::ErrorKind::TimedOut, err.kind());
     }
 
+    #[test]
+    fn protocol_rect() {
+        let image: DynamicImage = ImageBuffer::from_pixel(75, 30, Rgb([255u8, 0, 0])).into();
+        let size = Rect::new(0, 0, 5, 4);
+        let mut picker = Picker::new((10, 10));
+        for protocol_type in [ProtocolType::Halfblocks, ProtocolType::Kitty] {
+            picker.set_protocol_type(protocol_type);
+            for resize in [
+                Resize::Fit(None),
+                Resize::Crop(None, None),
+                Resize::Stretch(None),
+                Resize::Center(None),
+                Resize::Fill(None),
+                Resize::FitWidth(None),
+                Resize::FitHeight(None),
+            ] {
+                let rect = picker.protocol_rect(&image, size, &resize);
+                let protocol = picker.new_protocol(image.clone(), size, resize).unwrap();
+                assert_eq!(protocol.rect(), rect);
+            }
+        }
+    }
+
     #[test]
     fn test_read_stdin_blocking() {
         let mut stdin = test_stdin(10, "abcabc");
@@ -0,0 +1,232 @@
+//! Animation of any protocol, e.g. GIF, APNG, or animated WebP.
+//!
+//! [StatefulAnimated] wraps a [StatefulBlock] and selects the current frame from the elapsed
+//! time, with [StatefulAnimated::advance]. Kitty plays the animation by itself if the protocol was
+//! created with [crate::picker::Picker::new_animated_resize_protocol], so advancing does nothing.
+//!
+//! Other protocols must re-encode each frame that is displayed. The encoded protocol of each
+//! frame is cached, so that looping does not re-encode it, unless the area changes.
+use std::{mem, time::Duration};
+
+use image::Rgb;
+use ratatui::{buffer::Buffer, layout::Rect};
+
+use super::{ImageSource, ResizedImage, StatefulBlock, StatefulProtocol};
+use crate::Resize;
+
+/// A resizing protocol that animates the frames of its [ImageSource].
+///
+/// # Example
+/// ```rust,no_run
+/// use std::time::Instant;
+///
+/// use image::{codecs::gif::GifDecoder, AnimationDecoder};
+/// use ratatui_image::picker::Picker;
+///
+/// let mut picker = Picker::new((7, 14));
+/// let decoder = GifDecoder::new(std::fs::File::open("./assets/Recording.gif")?)?;
+/// let mut protocol = picker.new_animated_protocol(decoder.into_frames())?;
+/// let mut last = Instant::now();
+/// // In the event loop:
+/// if protocol.advance(last.elapsed()) {
+///     // Redraw.
+/// }
+/// last = Instant::now();
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone)]
+pub struct StatefulAnimated {
+    protocol: StatefulBlock,
+    /// The protocols of the other frames that were shown, by frame index.
+    cache: Vec<Option<StatefulBlock>>,
+    frame: usize,
+    /// The time since the current frame was shown.
+    elapsed: Duration,
+}
+
+impl StatefulAnimated {
+    /// Animate `protocol`, whose [ImageSource] has `frame_count` frames, see
+    /// [ImageSource::frame_count]. The protocol must be at its first frame.
+    pub fn new(protocol: StatefulBlock, frame_count: usize) -> StatefulAnimated {
+        StatefulAnimated {
+            protocol,
+            cache: vec![None; frame_count.max(1)],
+            frame: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Advance the animation by the time `elapsed` since the last call, and return whether the
+    /// frame changed, i.e. whether the image should be rendered again.
+    ///
+    /// Frames whose delay has fully elapsed are skipped, but at most one loop of the animation.
+    /// Still images, and animations that the terminal plays by itself, never change.
+    pub fn advance(&mut self, elapsed: Duration) -> bool {
+        self.elapsed += elapsed;
+        let mut advanced = false;
+        for _ in 0..self.cache.len() {
+            match self.protocol.frame_delay() {
+                Some(delay) if delay <= self.elapsed => {
+                    self.elapsed -= delay;
+                    self.next_frame();
+                    advanced = true;
+                }
+                _ => return advanced,
+            }
+        }
+        // Behind by more than a loop, e.g. with zero delays.
+        self.elapsed = Duration::ZERO;
+        advanced
+    }
+
+    /// The index of the current frame.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    fn next_frame(&mut self) {
+        let next = (self.frame + 1) % self.cache.len();
+        // Kitty images are stored by ID in the terminal, so a cached protocol would show whatever
+        // frame was transmitted last.
+        if let StatefulBlock::Kitty(_) = self.protocol {
+            self.protocol.advance_frame();
+        } else if let Some(cached) = self.cache[next].take() {
+            self.cache[self.frame] = Some(mem::replace(&mut self.protocol, cached));
+        } else {
+            self.cache[self.frame] = Some(self.protocol.clone());
+            self.protocol.advance_frame();
+        }
+        self.frame = next;
+    }
+}
+
+impl StatefulProtocol for StatefulAnimated {
+    fn needs_resize(&mut self, resize: &Resize, area: Rect) -> Option<Rect> {
+        self.protocol.needs_resize(resize, area)
+    }
+    fn resize(
+        &self,
+        resize: &Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+    ) -> Option<ResizedImage> {
+        self.protocol.resize(resize, background_color, area)
+    }
+    fn encode(&mut self, resized: ResizedImage) {
+        self.protocol.encode(resized);
+    }
+    fn render(&mut self, area: Rect, buf: &mut Buffer) {
+        self.protocol.render(area, buf);
+    }
+    fn rect(&self) -> Rect {
+        self.protocol.rect()
+    }
+    fn encoded(&self) -> String {
+        self.protocol.encoded()
+    }
+    /// Also clears the cached frames, and starts at the first frame of `source`.
+    fn set_source(&mut self, source: ImageSource) {
+        self.cache = vec![None; source.frame_count()];
+        self.frame = 0;
+        self.elapsed = Duration::ZERO;
+        self.protocol.set_source(source);
+    }
+    /// Advance to the next frame immediately, see [StatefulAnimated::advance].
+    fn advance_frame(&mut self) {
+        self.elapsed = Duration::ZERO;
+        self.next_frame();
+    }
+    fn frame_delay(&self) -> Option<Duration> {
+        self.protocol.frame_delay()
+    }
+    fn clear(&mut self, area: Rect, buf: &mut Buffer) {
+        self.protocol.clear(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use image::{Delay, Frame, Frames, ImageBuffer, Rgba};
+    use ratatui::{buffer::Buffer, layout::Rect, style::Color};
+
+    use super::StatefulAnimated;
+    use crate::{
+        protocol::{halfblocks::StatefulHalfblocks, ImageSource, StatefulProtocol},
+        Resize,
+    };
+
+    /// Frames of one red pixel each, with a delay of 100ms.
+    fn frames(reds: &[u8]) -> Frames<'static> {
+        let frames: Vec<_> = reds
+            .iter()
+            .map(|red| {
+                let buffer = ImageBuffer::from_pixel(1, 2, Rgba([*red, 0, 0, 255]));
+                Ok(Frame::from_parts(
+                    buffer,
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(100, 1),
+                ))
+            })
+            .collect();
+        Frames::new(Box::new(frames.into_iter()))
+    }
+
+    fn animated(reds: &[u8]) -> StatefulAnimated {
+        let source = ImageSource::from_frames(frames(reds), (1, 2)).unwrap();
+        let frame_count = source.frame_count();
+        StatefulAnimated::new(StatefulHalfblocks::new(source).into(), frame_count)
+    }
+
+    fn render(animated: &mut StatefulAnimated) -> Color {
+        let area = Rect::new(0, 0, 1, 1);
+        let mut buf = Buffer::empty(area);
+        animated.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        buf.get(0, 0).fg
+    }
+
+    #[test]
+    fn advance() {
+        let mut animated = animated(&[10, 20, 30]);
+        assert_eq!(Color::Rgb(10, 0, 0), render(&mut animated));
+
+        assert!(!animated.advance(Duration::from_millis(60)));
+        assert!(animated.advance(Duration::from_millis(60)));
+        assert_eq!(1, animated.frame());
+        assert_eq!(Color::Rgb(20, 0, 0), render(&mut animated));
+
+        // 20ms were left over from the last frame.
+        assert!(animated.advance(Duration::from_millis(180)));
+        assert_eq!(0, animated.frame());
+        assert_eq!(Color::Rgb(10, 0, 0), render(&mut animated));
+
+        // Far behind, at most one loop.
+        assert!(animated.advance(Duration::from_secs(10)));
+        assert!(!animated.advance(Duration::ZERO));
+    }
+
+    #[test]
+    fn cached() {
+        let mut animated = animated(&[10, 20]);
+        let area = Rect::new(0, 0, 1, 1);
+        render(&mut animated);
+        animated.advance_frame();
+        render(&mut animated);
+        animated.advance_frame();
+        // The first frame is back, and is still encoded.
+        assert_eq!(None, animated.needs_resize(&Resize::Fit(None), area));
+        assert_eq!(Color::Rgb(10, 0, 0), render(&mut animated));
+        animated.advance_frame();
+        assert_eq!(None, animated.needs_resize(&Resize::Fit(None), area));
+
+        let still = ImageSource::new(
+            ImageBuffer::from_pixel(1, 2, Rgba([0u8, 0, 0, 255])).into(),
+            (1, 2),
+        );
+        animated.set_source(still);
+        assert!(!animated.advance(Duration::from_secs(1)));
+        assert_eq!(Color::Rgb(0, 0, 0), render(&mut animated));
+    }
+}
@@ -0,0 +1,197 @@
+//! Blank protocol implementation.
+//!
+//! Draws no image at all, but fills the cells of the image with a placeholder, by default spaces.
+//! No escape sequences are written, and resizing only computes the size of the image, so it is
+//! useful where graphics are not wanted, e.g. when the output is redirected to a file.
+use std::time::Duration;
+
+use image::Rgb;
+use ratatui::{buffer::Buffer, layout::Rect, style::Style};
+
+use super::{in_buffer, Protocol, ResizedImage, StatefulProtocol};
+use crate::{ImageSource, Resize};
+
+/// Options for the blank protocol.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlankOptions {
+    /// Placeholder character of each cell. Defaults to a space.
+    pub symbol: char,
+    /// Style of the placeholder cells. Defaults to leaving the style of the cells unchanged.
+    pub style: Style,
+}
+
+impl Default for BlankOptions {
+    fn default() -> Self {
+        BlankOptions {
+            symbol: ' ',
+            style: Style::default(),
+        }
+    }
+}
+
+// Fixed Blank protocol
+#[derive(Clone, Default)]
+pub struct Blank {
+    rect: Rect,
+    options: BlankOptions,
+}
+
+impl Blank {
+    /// Create a Blank with the size that the image would have with `resize` in `area`.
+    pub fn from_source(
+        source: &ImageSource,
+        resize: Resize,
+        area: Rect,
+        options: BlankOptions,
+    ) -> Self {
+        let rect = resize
+            .needs_resize(source, Rect::default(), area, false)
+            .unwrap_or(source.desired);
+        Self { rect, options }
+    }
+}
+
+impl Protocol for Blank {
+    fn render(&self, area: Rect, buf: &mut Buffer) {
+        render(self.rect, &self.options, area, buf);
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// One line of placeholder characters per cell row.
+    fn encoded(&self) -> String {
+        let line: String = (0..self.rect.width).map(|_| self.options.symbol).collect();
+        (0..self.rect.height).map(|_| format!("{line}\n")).collect()
+    }
+}
+
+fn render(rect: Rect, options: &BlankOptions, area: Rect, buf: &mut Buffer) {
+    for y in rect.top()..rect.bottom() {
+        for x in rect.left()..rect.right() {
+            if x >= area.width || y >= area.height || !in_buffer(buf, area.x + x, area.y + y) {
+                continue;
+            }
+            buf.get_mut(area.x + x, area.y + y)
+                .set_char(options.symbol)
+                .set_style(options.style);
+        }
+    }
+}
+
+/// A resizing blank protocol.
+#[derive(Clone)]
+pub struct StatefulBlank {
+    source: ImageSource,
+    current: Blank,
+    hash: u64,
+}
+
+impl StatefulBlank {
+    pub fn new(source: ImageSource, options: BlankOptions) -> StatefulBlank {
+        StatefulBlank {
+            source,
+            current: Blank {
+                rect: Rect::default(),
+                options,
+            },
+            hash: u64::default(),
+        }
+    }
+}
+
+impl StatefulProtocol for StatefulBlank {
+    fn needs_resize(&mut self, resize: &Resize, area: Rect) -> Option<Rect> {
+        let force = self.source.resize_hash(resize) != self.hash;
+        resize.needs_resize(&self.source, self.current.rect, area, force)
+    }
+    fn resize(
+        &self,
+        resize: &Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+    ) -> Option<ResizedImage> {
+        ResizedImage::rect_only(
+            &self.source,
+            self.current.rect,
+            self.source.resize_hash(resize) != self.hash,
+            resize,
+            background_color,
+            area,
+        )
+    }
+    fn encode(&mut self, resized: ResizedImage) {
+        self.current.rect = resized.rect;
+        self.hash = resized.hash;
+    }
+    fn render(&mut self, area: Rect, buf: &mut Buffer) {
+        self.current.render(area, buf);
+    }
+    fn rect(&self) -> Rect {
+        self.current.rect
+    }
+    fn encoded(&self) -> String {
+        self.current.encoded()
+    }
+    fn set_source(&mut self, source: ImageSource) {
+        self.source = source;
+    }
+    fn advance_frame(&mut self) {
+        self.source.advance_frame();
+    }
+    fn frame_delay(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, ImageBuffer, Rgb};
+    use ratatui::{
+        buffer::Buffer,
+        layout::Rect,
+        style::{Color, Style},
+    };
+
+    use super::{Blank, BlankOptions, StatefulBlank};
+    use crate::{
+        protocol::{ImageSource, Protocol, StatefulProtocol},
+        Resize,
+    };
+
+    fn source() -> ImageSource {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 4, Rgb([255u8, 0, 0])).into();
+        ImageSource::new(image, (2, 4))
+    }
+
+    #[test]
+    fn placeholder() {
+        let options = BlankOptions {
+            symbol: '░',
+            style: Style::default().fg(Color::Gray),
+        };
+        let area = Rect::new(0, 0, 3, 2);
+        let blank = Blank::from_source(&source(), Resize::Fit(None), area, options);
+        assert_eq!(Rect::new(0, 0, 2, 1), blank.rect());
+        assert_eq!("░░\n", blank.encoded());
+
+        let mut buf = Buffer::empty(area);
+        blank.render(area, &mut buf);
+        assert_eq!("░", buf.get(1, 0).symbol);
+        assert_eq!(Color::Gray, buf.get(1, 0).fg);
+        assert_eq!(" ", buf.get(2, 0).symbol);
+        assert_eq!(" ", buf.get(0, 1).symbol);
+    }
+
+    #[test]
+    fn stateful() {
+        let mut blank = StatefulBlank::new(source(), BlankOptions::default());
+        let (resize, area) = (Resize::Fit(None), Rect::new(0, 0, 1, 1));
+        let mut buf = Buffer::empty(area);
+        blank.resize_encode_render(&resize, None, area, &mut buf);
+        assert_eq!(Rect::new(0, 0, 1, 1), blank.rect());
+        assert_eq!(None, blank.needs_resize(&resize, area));
+        assert_eq!("", blank.encoded().trim());
+    }
+}
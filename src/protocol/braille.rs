@@ -0,0 +1,335 @@
+//! Braille protocol implementations.
+//! Uses the unicode braille patterns (`U+2800..U+28FF`) as a monochrome 2x4 dot grid per cell.
+//! Only needs text and optionally a foreground color, so it works in terminals where even
+//! halfblocks' background colors are not available.
+//!
+//! A dot is drawn for each pixel whose luminance is at least [BrailleOptions::threshold], so that
+//! bright parts of the image show up on dark terminal backgrounds.
+use std::time::Duration;
+
+use image::{
+    imageops::{self, colorops::BiLevel, FilterType},
+    DynamicImage, Luma, Rgb,
+};
+use ratatui::{buffer::Buffer, layout::Rect, style::Color};
+
+use super::{in_buffer, Protocol, ResizedImage, StatefulProtocol};
+use crate::{ImageSource, Resize, Result};
+
+/// Options for the braille protocol.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BrailleOptions {
+    /// Foreground color of the dots. Defaults to the terminal's foreground color.
+    pub color: Option<Rgb<u8>>,
+    /// Color the dots of each cell with their average color instead of [BrailleOptions::color].
+    pub average_color: bool,
+    /// Minimum luminance of a pixel to draw its dot. Defaults to `128`.
+    pub threshold: u8,
+    /// Dither the luminance (Floyd-Steinberg) instead of thresholding each pixel on its own, to
+    /// show shades of gray as dot density. The threshold then shifts the overall brightness.
+    pub dither: bool,
+}
+
+impl Default for BrailleOptions {
+    fn default() -> Self {
+        BrailleOptions {
+            color: None,
+            average_color: false,
+            threshold: 128,
+            dither: false,
+        }
+    }
+}
+
+// Fixed Braille protocol
+#[derive(Clone, Default)]
+pub struct Braille {
+    data: Vec<Dots>,
+    rect: Rect,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Dots {
+    symbol: char,
+    fg: Option<Color>,
+}
+
+impl Braille {
+    /// Create a FixedBraille from an image.
+    ///
+    /// The "resolution" is determined by the font size of the terminal. Smaller fonts will result
+    /// in more braille dots for the same image size.
+    pub fn from_source(
+        source: &ImageSource,
+        resize: Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+        options: BrailleOptions,
+    ) -> Result<Self> {
+        let (image, desired) = resize
+            .resize(source, Rect::default(), area, background_color, false)
+            .unwrap_or_else(|| (source.image.clone(), source.desired));
+        let data = encode(&image, desired, resize.filter_type(), &options, Vec::new());
+        Ok(Self {
+            data,
+            rect: desired,
+        })
+    }
+}
+
+/// Bits of the braille dots, indexed by row and column within the cell.
+static DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// Encode the cells into `data`, reusing its allocation, e.g. from the previous encode.
+fn encode(
+    img: &DynamicImage,
+    rect: Rect,
+    filter_type: Option<FilterType>,
+    options: &BrailleOptions,
+    mut data: Vec<Dots>,
+) -> Vec<Dots> {
+    let img = img.resize_exact(
+        rect.width as u32 * 2,
+        rect.height as u32 * 4,
+        filter_type.unwrap_or(FilterType::Triangle),
+    );
+    // Grayscale images stay single-channel, unless the dots take the average color.
+    let rgb = options.average_color.then(|| img.to_rgb8());
+    // Shift the luminance so that the threshold lands on the middle gray, which is also where
+    // dithering splits.
+    let shift = 128 - options.threshold as i16;
+    let mut luma = img.into_luma8();
+    for Luma([l]) in luma.pixels_mut() {
+        *l = (*l as i16 + shift).clamp(0, 255) as u8;
+    }
+    if options.dither {
+        imageops::dither(&mut luma, &BiLevel);
+    }
+    let color = options.color.map(|Rgb([r, g, b])| Color::Rgb(r, g, b));
+
+    data.clear();
+    data.reserve((rect.width * rect.height) as usize);
+    for y in 0..rect.height as u32 {
+        for x in 0..rect.width as u32 {
+            let mut bits = 0;
+            let mut sum = [0u32; 3];
+            let mut count = 0;
+            for (row, dots) in DOTS.iter().enumerate() {
+                for (column, dot) in dots.iter().enumerate() {
+                    let (px, py) = (x * 2 + column as u32, y * 4 + row as u32);
+                    if luma.get_pixel(px, py)[0] >= 128 {
+                        bits |= dot;
+                        if let Some(rgb) = &rgb {
+                            let Rgb(rgb) = rgb.get_pixel(px, py);
+                            for c in 0..3 {
+                                sum[c] += rgb[c] as u32;
+                            }
+                            count += 1;
+                        }
+                    }
+                }
+            }
+            let fg = if count > 0 {
+                let [r, g, b] = sum.map(|c| (c / count) as u8);
+                Some(Color::Rgb(r, g, b))
+            } else {
+                color
+            };
+            data.push(Dots {
+                symbol: char::from_u32(0x2800 + bits).unwrap_or(' '),
+                fg,
+            });
+        }
+    }
+    data
+}
+
+impl Protocol for Braille {
+    fn render(&self, area: Rect, buf: &mut Buffer) {
+        for (i, dots) in self.data.iter().enumerate() {
+            let x = self.rect.x + i as u16 % self.rect.width;
+            let y = self.rect.y + i as u16 / self.rect.width;
+            if x >= area.width || y >= area.height || !in_buffer(buf, area.x + x, area.y + y) {
+                continue;
+            }
+
+            let cell = buf.get_mut(area.x + x, area.y + y);
+            if let Some(fg) = dots.fg {
+                cell.set_fg(fg);
+            }
+            cell.set_char(dots.symbol);
+        }
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn encoded(&self) -> String {
+        let mut seq = String::new();
+        for row in self.data.chunks(self.rect.width.max(1) as usize) {
+            for dots in row {
+                if let Some(Color::Rgb(r, g, b)) = dots.fg {
+                    seq.push_str(&format!("\x1b[38;2;{r};{g};{b}m"));
+                }
+                seq.push(dots.symbol);
+            }
+            seq.push_str("\x1b[0m\n");
+        }
+        seq
+    }
+}
+
+#[derive(Clone)]
+pub struct StatefulBraille {
+    source: ImageSource,
+    current: Braille,
+    hash: u64,
+    options: BrailleOptions,
+}
+
+impl StatefulBraille {
+    pub fn new(source: ImageSource, options: BrailleOptions) -> StatefulBraille {
+        StatefulBraille {
+            source,
+            current: Braille::default(),
+            hash: u64::default(),
+            options,
+        }
+    }
+}
+
+impl StatefulProtocol for StatefulBraille {
+    fn needs_resize(&mut self, resize: &Resize, area: Rect) -> Option<Rect> {
+        let force = self.source.resize_hash(resize) != self.hash;
+        resize.needs_resize(&self.source, self.current.rect, area, force)
+    }
+    fn resize(
+        &self,
+        resize: &Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+    ) -> Option<ResizedImage> {
+        ResizedImage::new(
+            &self.source,
+            self.current.rect,
+            self.source.resize_hash(resize) != self.hash,
+            resize,
+            background_color,
+            area,
+        )
+    }
+    fn encode(&mut self, resized: ResizedImage) {
+        let data = std::mem::take(&mut self.current.data);
+        let rect = resized.rect;
+        let data = encode(
+            &resized.image,
+            rect,
+            resized.resize.filter_type(),
+            &self.options,
+            data,
+        );
+        self.current = Braille { data, rect };
+        self.hash = resized.hash;
+    }
+    fn render(&mut self, area: Rect, buf: &mut Buffer) {
+        Braille::render(&self.current, area, buf);
+    }
+    fn rect(&self) -> Rect {
+        self.current.rect
+    }
+    fn encoded(&self) -> String {
+        self.current.encoded()
+    }
+    fn set_source(&mut self, source: ImageSource) {
+        self.source = source;
+    }
+    fn advance_frame(&mut self) {
+        self.source.advance_frame();
+    }
+    fn frame_delay(&self) -> Option<Duration> {
+        self.source.frame_delay()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, ImageBuffer, Luma, Rgb};
+    use ratatui::{layout::Rect, style::Color};
+
+    use super::{encode, Braille, BrailleOptions};
+    use crate::{protocol::Protocol, ImageSource, Resize};
+
+    fn symbols(image: &DynamicImage, options: &BrailleOptions) -> Vec<char> {
+        encode(image, Rect::new(0, 0, 1, 1), None, options, Vec::new())
+            .into_iter()
+            .map(|dots| dots.symbol)
+            .collect()
+    }
+
+    #[test]
+    fn dots() {
+        // Left column white, right column black.
+        let image: DynamicImage =
+            ImageBuffer::from_fn(2, 4, |x, _| Luma([if x == 0 { 255u8 } else { 0 }])).into();
+        assert_eq!(vec!['⡇'], symbols(&image, &BrailleOptions::default()));
+
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 4, Luma([255u8])).into();
+        let options = BrailleOptions {
+            dither: true,
+            ..BrailleOptions::default()
+        };
+        assert_eq!(vec!['⣿'], symbols(&image, &options));
+    }
+
+    #[test]
+    fn threshold() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 4, Luma([100u8])).into();
+        assert_eq!(vec!['⠀'], symbols(&image, &BrailleOptions::default()));
+        let options = BrailleOptions {
+            threshold: 100,
+            ..BrailleOptions::default()
+        };
+        assert_eq!(vec!['⣿'], symbols(&image, &options));
+    }
+
+    #[test]
+    fn average_color() {
+        // Top rows red, bottom rows black.
+        let image: DynamicImage = ImageBuffer::from_fn(2, 4, |_, y| {
+            if y < 2 {
+                Rgb([255u8, 0, 0])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        })
+        .into();
+        let options = BrailleOptions {
+            average_color: true,
+            threshold: 50,
+            ..BrailleOptions::default()
+        };
+        let dots = encode(&image, Rect::new(0, 0, 1, 1), None, &options, Vec::new());
+        assert_eq!('⠛', dots[0].symbol);
+        assert_eq!(Some(Color::Rgb(255, 0, 0)), dots[0].fg);
+    }
+
+    #[test]
+    fn encoded() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 4, Rgb([255u8, 255, 255])).into();
+        let source = ImageSource::new(image, (2, 4));
+        let options = BrailleOptions {
+            color: Some(Rgb([0, 255, 0])),
+            ..BrailleOptions::default()
+        };
+        let braille = Braille::from_source(
+            &source,
+            Resize::Fit(None),
+            None,
+            Rect::new(0, 0, 1, 1),
+            options,
+        )
+        .unwrap();
+        assert_eq!("\x1b[38;2;0;255;0m⣿\x1b[0m\n", braille.encoded());
+    }
+}
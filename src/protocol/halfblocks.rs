@@ -1,12 +1,75 @@
 //! Halfblocks protocol implementations.
 //! Uses the unicode character `▀` combined with foreground and background color. Assumes that the
 //! font aspect ratio is roughly 1:2. Should work in all terminals.
-use image::{imageops::FilterType, DynamicImage, Rgb};
+//!
+//! With [Blocks::Quadrants], the quadrant block characters (`▖▗▘▝▚▞▙▛▜▟` etc.) are used instead,
+//! for 2x2 pixels per cell. Each cell can still only show two colors, so the four pixels are
+//! quantized to the two colors that represent them best. [Blocks::Sextants] works the same with
+//! the Unicode 13 sextant characters (`🬀🬁🬂` etc.) for 2x3 pixels per cell, but needs a font that
+//! has them.
+//!
+//! Images with an alpha channel are composited over the background color. Without a background
+//! color, [Blocks::Halves] leaves mostly transparent pixels to the terminal's background, while the
+//! other blocks composite over black. The same goes for the padding below an odd last pixel row, or
+//! any other padding of the image to the cell size.
+//!
+//! With [HalfblocksOptions::dither], the error of picking two colors per cell is diffused into
+//! the neighbouring cells, which smoothens gradients in [Blocks::Quadrants] and [Blocks::Sextants]
+//! at the cost of some noise.
+//!
+//! Terminals without truecolor support can use the 256-color palette, see [HalfblocksColors].
+use std::{borrow::Cow, cmp::min, time::Duration};
+
+use image::{
+    imageops::{self, FilterType},
+    DynamicImage, Rgb, RgbaImage,
+};
 use ratatui::{buffer::Buffer, layout::Rect, style::Color};
 
-use super::{Protocol, StatefulProtocol};
+use super::{clip, composite, Protocol, ResizedImage, StatefulProtocol};
 use crate::{ImageSource, Resize, Result};
 
+/// The block characters used to draw pixels within a cell.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Blocks {
+    /// Upper half block `▀`, 1x2 pixels per cell.
+    #[default]
+    Halves,
+    /// Quadrant blocks, 2x2 pixels per cell but only two colors.
+    Quadrants,
+    /// Sextant blocks, 2x3 pixels per cell but only two colors.
+    Sextants,
+}
+
+/// Options for the halfblocks protocols.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HalfblocksOptions {
+    /// Diffuse the color error of each cell into the pixels of the following cells
+    /// (Floyd-Steinberg). Defaults to `false`.
+    ///
+    /// Gradients look smoother, and flat areas stay flat, but e.g. animations may shimmer. Has no
+    /// effect with [Blocks::Halves], where every pixel is shown in its own color.
+    pub dither: bool,
+    /// The colors that the terminal supports. Defaults to [HalfblocksColors::Truecolor].
+    ///
+    /// Set by [crate::picker::Picker::guess_protocol] from `$COLORTERM` and `$TERM`.
+    pub colors: HalfblocksColors,
+}
+
+/// The colors of the cells, see [HalfblocksOptions::colors].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HalfblocksColors {
+    /// 24-bit RGB colors.
+    #[default]
+    Truecolor,
+    /// The nearest colors of the xterm 256-color palette, for terminals without truecolor
+    /// support, which would show the RGB colors wrong or not at all.
+    ///
+    /// Only the 6x6x6 color cube and the grayscale ramp are used, since the 16 system colors
+    /// depend on the terminal's theme.
+    Indexed,
+}
+
 // Fixed Halfblocks protocol
 #[derive(Clone, Default)]
 pub struct Halfblocks {
@@ -16,8 +79,9 @@ pub struct Halfblocks {
 
 #[derive(Clone, Debug)]
 struct HalfBlock {
-    upper: Color,
-    lower: Color,
+    symbol: char,
+    fg: Color,
+    bg: Color,
 }
 
 impl Halfblocks {
@@ -32,10 +96,50 @@ impl Halfblocks {
         background_color: Option<Rgb<u8>>,
         area: Rect,
     ) -> Result<Self> {
+        Self::from_source_with_blocks(source, resize, background_color, area, Blocks::Halves)
+    }
+
+    /// Create a FixedHalfblocks from an image, drawing with the given [Blocks].
+    pub fn from_source_with_blocks(
+        source: &ImageSource,
+        resize: Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+        blocks: Blocks,
+    ) -> Result<Self> {
+        Self::from_source_with_options(
+            source,
+            resize,
+            background_color,
+            area,
+            blocks,
+            HalfblocksOptions::default(),
+        )
+    }
+
+    /// Create a FixedHalfblocks from an image, drawing with the given [Blocks] and
+    /// [HalfblocksOptions].
+    pub fn from_source_with_options(
+        source: &ImageSource,
+        resize: Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+        blocks: Blocks,
+        options: HalfblocksOptions,
+    ) -> Result<Self> {
+        let padded = transparent_padding(source, background_color, blocks);
         let (image, desired) = resize
-            .resize(source, Rect::default(), area, background_color, false)
+            .resize(&padded, Rect::default(), area, background_color, false)
             .unwrap_or_else(|| (source.image.clone(), source.desired));
-        let data = encode(&image, desired);
+        let data = encode(
+            &image,
+            desired,
+            resize.filter_type(),
+            blocks,
+            background_color,
+            options,
+            Vec::new(),
+        );
         Ok(Self {
             data,
             rect: desired,
@@ -43,96 +147,824 @@ impl Halfblocks {
     }
 }
 
-fn encode(img: &DynamicImage, rect: Rect) -> Vec<HalfBlock> {
-    let img = img.resize_exact(
-        rect.width as u32,
-        (rect.height * 2) as u32,
-        FilterType::Triangle,
-    );
+impl Halfblocks {
+    /// Encode an image that is already resized for `rect` with [Blocks::Halves], e.g. as a
+    /// fallback when another protocol fails to encode it.
+    pub(crate) fn from_resized(
+        image: &DynamicImage,
+        rect: Rect,
+        background_color: Option<Rgb<u8>>,
+    ) -> Halfblocks {
+        let data = encode(
+            image,
+            rect,
+            None,
+            Blocks::Halves,
+            background_color,
+            HalfblocksOptions::default(),
+            Vec::new(),
+        );
+        Halfblocks { data, rect }
+    }
+}
+
+/// Without a background color, give an opaque image an alpha channel, so that the image is padded
+/// to the cell size with transparency instead of black, which [Blocks::Halves] leaves to the
+/// terminal's background.
+fn transparent_padding(
+    source: &ImageSource,
+    background_color: Option<Rgb<u8>>,
+    blocks: Blocks,
+) -> Cow<'_, ImageSource> {
+    if background_color.is_some() || blocks != Blocks::Halves || source.image.color().has_alpha() {
+        return Cow::Borrowed(source);
+    }
+    Cow::Owned(ImageSource {
+        image: source.image.to_rgba8().into(),
+        font_size: source.font_size,
+        desired: source.desired,
+        hash: source.hash,
+        frames: source.frames.clone(),
+        original: None,
+        frame: source.frame,
+        scale: source.scale,
+    })
+}
+
+/// Encode the cells into `data`, reusing its allocation, e.g. from the previous encode.
+fn encode(
+    img: &DynamicImage,
+    rect: Rect,
+    filter_type: Option<FilterType>,
+    blocks: Blocks,
+    background_color: Option<Rgb<u8>>,
+    options: HalfblocksOptions,
+    mut data: Vec<HalfBlock>,
+) -> Vec<HalfBlock> {
+    data.clear();
+    let filter_type = filter_type.unwrap_or(FilterType::Triangle);
+    match blocks {
+        Blocks::Halves => encode_halves(img, rect, filter_type, background_color, &mut data),
+        Blocks::Quadrants => encode_subcells(
+            img,
+            rect,
+            filter_type,
+            background_color,
+            (2, 2),
+            quadrant_symbol,
+            options.dither,
+            &mut data,
+        ),
+        Blocks::Sextants => encode_subcells(
+            img,
+            rect,
+            filter_type,
+            background_color,
+            (2, 3),
+            sextant_symbol,
+            options.dither,
+            &mut data,
+        ),
+    }
+    if options.colors == HalfblocksColors::Indexed {
+        for hb in &mut data {
+            hb.fg = indexed(hb.fg);
+            hb.bg = indexed(hb.bg);
+        }
+    }
+    data
+}
 
-    let mut data = vec![
+fn encode_halves(
+    img: &DynamicImage,
+    rect: Rect,
+    filter_type: FilterType,
+    background_color: Option<Rgb<u8>>,
+    data: &mut Vec<HalfBlock>,
+) {
+    let (width, height) = (rect.width as u32, rect.height as u32 * 2);
+    let img = if background_color.is_none() && img.color().has_alpha() {
+        resize_premultiplied(img, width, height, filter_type)
+    } else {
+        composite(
+            img.resize_exact(width, height, filter_type),
+            background_color,
+        )
+        .into_rgba8()
+    };
+
+    data.resize(
+        (rect.width * rect.height) as usize,
         HalfBlock {
-            upper: Color::Rgb(0, 0, 0),
-            lower: Color::Rgb(0, 0, 0),
-        };
-        (rect.width * rect.height) as usize
-    ];
+            symbol: '▀',
+            fg: Color::Rgb(0, 0, 0),
+            bg: Color::Rgb(0, 0, 0),
+        },
+    );
 
-    for (y, row) in img.to_rgb8().rows().enumerate() {
+    for (y, row) in img.rows().enumerate() {
         for (x, pixel) in row.enumerate() {
             let position = x + (rect.width as usize) * (y / 2);
+            let [r, g, b, a] = pixel.0;
+            // Mostly transparent pixels show the terminal's background.
+            let color = if a < 128 {
+                Color::Reset
+            } else {
+                Color::Rgb(r, g, b)
+            };
             if y % 2 == 0 {
-                data[position].upper = Color::Rgb(pixel[0], pixel[1], pixel[2]);
+                data[position].fg = color;
             } else {
-                data[position].lower = Color::Rgb(pixel[0], pixel[1], pixel[2]);
+                data[position].bg = color;
             }
         }
     }
-    data
+
+    // A transparent upper pixel would show in the terminal's default foreground color under `▀`.
+    for hb in data.iter_mut() {
+        match (hb.fg, hb.bg) {
+            (Color::Reset, Color::Reset) => hb.symbol = ' ',
+            (Color::Reset, bg) => {
+                hb.symbol = '▄';
+                hb.fg = bg;
+                hb.bg = Color::Reset;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Resize with premultiplied alpha, so that the color of transparent pixels does not bleed into
+/// the soft edges of the image, e.g. as a dark halo.
+fn resize_premultiplied(
+    img: &DynamicImage,
+    width: u32,
+    height: u32,
+    filter_type: FilterType,
+) -> RgbaImage {
+    let mut img = img.to_rgba8();
+    for pixel in img.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for c in 0..3 {
+            pixel[c] = (pixel[c] as u32 * alpha / 255) as u8;
+        }
+    }
+    let mut img = imageops::resize(&img, width, height, filter_type);
+    for pixel in img.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for c in 0..3 {
+            if let Some(color) = (pixel[c] as u32 * 255).checked_div(alpha) {
+                pixel[c] = min(color, 255) as u8;
+            }
+        }
+    }
+    img
+}
+
+/// Quadrant characters, indexed by a bitmask of the foreground quadrants: upper left `1`, upper
+/// right `2`, lower left `4`, lower right `8`.
+static QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', // Lower right in the background.
+    '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█', // Lower right in the foreground.
+];
+
+fn quadrant_symbol(mask: usize) -> char {
+    QUADRANTS[mask]
+}
+
+/// Sextant character for a bitmask of the foreground sextants: upper left `1`, upper right `2`,
+/// middle left `4`, middle right `8`, lower left `16`, lower right `32`.
+///
+/// The sextants block (`U+1FB00..U+1FB3B`) is in mask order, but leaves out the masks that
+/// already have a character: empty, full, and the left and right halves.
+fn sextant_symbol(mask: usize) -> char {
+    match mask {
+        0 => ' ',
+        21 => '▌',
+        42 => '▐',
+        63 => '█',
+        _ => {
+            let skipped = (mask > 21) as usize + (mask > 42) as usize;
+            char::from_u32(0x1FB00 + (mask - 1 - skipped) as u32).unwrap_or('█')
+        }
+    }
+}
+
+/// Encode cells of `columns` x `rows` pixels each, picking the two colors that represent each
+/// cell's pixels best.
+///
+/// With `dither`, the cells are encoded in row-major order, and the error of each pixel is
+/// diffused into the neighbouring pixels of the cells that are not encoded yet.
+#[allow(clippy::too_many_arguments)]
+fn encode_subcells(
+    img: &DynamicImage,
+    rect: Rect,
+    filter_type: FilterType,
+    background_color: Option<Rgb<u8>>,
+    (columns, rows): (u32, u32),
+    symbol: fn(usize) -> char,
+    dither: bool,
+    data: &mut Vec<HalfBlock>,
+) {
+    let (width, height) = (rect.width as u32 * columns, rect.height as u32 * rows);
+    let img = img.resize_exact(width, height, filter_type);
+    let img = composite(img, background_color).into_rgb8();
+
+    // The diffused error of each pixel in sixteenths, only allocated when dithering.
+    let mut errors = vec![[0i32; 3]; if dither { (width * height) as usize } else { 0 }];
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+
+    data.reserve((rect.width * rect.height) as usize);
+    let mut pixels = Vec::with_capacity((columns * rows) as usize);
+    for y in 0..rect.height as u32 {
+        for x in 0..rect.width as u32 {
+            pixels.clear();
+            for row in 0..rows {
+                for column in 0..columns {
+                    let (px, py) = (x * columns + column, y * rows + row);
+                    let mut pixel = img.get_pixel(px, py).0;
+                    if let Some(error) = errors.get(index(px, py)) {
+                        for c in 0..3 {
+                            let error = (error[c] + 8).div_euclid(16);
+                            pixel[c] = (pixel[c] as i32 + error).clamp(0, 255) as u8;
+                        }
+                    }
+                    pixels.push(pixel);
+                }
+            }
+            let (mask, fg, bg) = split(&pixels);
+            if dither {
+                for (i, pixel) in pixels.iter().enumerate() {
+                    let color = if mask & (1 << i) != 0 { fg } else { bg };
+                    let (column, row) = (i as u32 % columns, i as u32 / columns);
+                    let (px, py) = (x * columns + column, y * rows + row);
+                    // Floyd-Steinberg weights, in sixteenths.
+                    let neighbours = [(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)];
+                    for (dx, dy, weight) in neighbours {
+                        let (nx, ny) = (px as i64 + dx, py as i64 + dy);
+                        if nx < 0 || nx >= width as i64 || ny >= height as i64 {
+                            continue;
+                        }
+                        let (nx, ny) = (nx as u32, ny as u32);
+                        // The pixels of this cell are already encoded.
+                        if nx / columns == x && ny / rows == y {
+                            continue;
+                        }
+                        let error = &mut errors[index(nx, ny)];
+                        for c in 0..3 {
+                            error[c] += (pixel[c] as i32 - color[c] as i32) * weight;
+                        }
+                    }
+                }
+            }
+            data.push(HalfBlock {
+                symbol: symbol(mask),
+                fg: Color::Rgb(fg[0], fg[1], fg[2]),
+                bg: Color::Rgb(bg[0], bg[1], bg[2]),
+            });
+        }
+    }
+}
+
+/// Pick the bitmask of the foreground pixels and the two colors that represent the pixels best.
+///
+/// Tries every split of the pixels into foreground and background, with the average color of
+/// each group, and keeps the split with the smallest squared error.
+fn split(pixels: &[[u8; 3]]) -> (usize, [u8; 3], [u8; 3]) {
+    let full = (1 << pixels.len()) - 1;
+    let mut best = (u32::MAX, 0, [0; 3], [0; 3]);
+    // Full block first, so that uniform cells don't need a background color.
+    for mask in (1..=full).rev() {
+        let is_fg = |i: usize| mask & (1 << i) != 0;
+        let fg = average(pixels, is_fg);
+        let bg = average(pixels, |i| !is_fg(i));
+        let error = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, pixel)| distance(pixel, if is_fg(i) { &fg } else { &bg }))
+            .sum();
+        if error < best.0 {
+            best = (error, mask, fg, bg);
+        }
+    }
+    let (_, mask, fg, bg) = best;
+    // The full block has no background pixels.
+    let bg = if mask == full { fg } else { bg };
+    (mask, fg, bg)
+}
+
+fn average(pixels: &[[u8; 3]], include: impl Fn(usize) -> bool) -> [u8; 3] {
+    let mut sum = [0u32; 3];
+    let mut count = 0;
+    for (i, pixel) in pixels.iter().enumerate() {
+        if include(i) {
+            for c in 0..3 {
+                sum[c] += pixel[c] as u32;
+            }
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return [0; 3];
+    }
+    sum.map(|c| (c / count) as u8)
+}
+
+fn distance(a: &[u8; 3], b: &[u8; 3]) -> u32 {
+    (0..3)
+        .map(|c| (a[c] as i32 - b[c] as i32).pow(2) as u32)
+        .sum()
+}
+
+/// The levels of each channel in the 6x6x6 color cube of the xterm 256-color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The nearest color of the xterm 256-color palette to an RGB color, from the color cube
+/// (`16..=231`) or the grayscale ramp (`232..=255`). Other colors are left as they are.
+fn indexed(color: Color) -> Color {
+    let Color::Rgb(r, g, b) = color else {
+        return color;
+    };
+    let rgb = [r, g, b];
+    let level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - c as i32).abs())
+            .unwrap_or(0)
+    };
+    let [lr, lg, lb] = rgb.map(level);
+    let cube = [CUBE_LEVELS[lr], CUBE_LEVELS[lg], CUBE_LEVELS[lb]];
+    // The ramp goes from 8 to 238 in steps of 10.
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let step = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray = 8 + step * 10;
+    if distance(&rgb, &[gray; 3]) < distance(&rgb, &cube) {
+        Color::Indexed(232 + step)
+    } else {
+        Color::Indexed(16 + 36 * lr as u8 + 6 * lg as u8 + lb as u8)
+    }
+}
+
+/// The SGR parameters for a foreground (`38`) or background (`48`) color. Any color other than
+/// RGB or indexed is the terminal's default.
+fn sgr(color: Color, base: u8) -> String {
+    match color {
+        Color::Rgb(r, g, b) => format!("{base};2;{r};{g};{b}"),
+        Color::Indexed(i) => format!("{base};5;{i}"),
+        _ => (base + 1).to_string(),
+    }
 }
 
 impl Protocol for Halfblocks {
     fn render(&self, area: Rect, buf: &mut Buffer) {
-        for (i, hb) in self.data.iter().enumerate() {
-            let x = i as u16 % self.rect.width;
-            let y = i as u16 / self.rect.width;
-            if x >= area.width || y >= area.height {
-                continue;
+        let image_area = Rect::new(
+            area.x + self.rect.x,
+            area.y + self.rect.y,
+            min(self.rect.width, area.width.saturating_sub(self.rect.x)),
+            min(self.rect.height, area.height.saturating_sub(self.rect.y)),
+        );
+        // Only copy the cells that are inside both the area and the buffer.
+        let Some(visible) = clip(buf, image_area) else {
+            return;
+        };
+        for y in visible.top()..visible.bottom() {
+            let row = (y - image_area.y) as usize * self.rect.width as usize;
+            for x in visible.left()..visible.right() {
+                if let Some(hb) = self.data.get(row + (x - image_area.x) as usize) {
+                    buf.get_mut(x, y)
+                        .set_fg(hb.fg)
+                        .set_bg(hb.bg)
+                        .set_char(hb.symbol);
+                }
             }
-
-            buf.get_mut(area.x + x, area.y + y)
-                .set_fg(hb.upper)
-                .set_bg(hb.lower)
-                .set_char('▀');
         }
     }
 
     fn rect(&self) -> Rect {
         self.rect
     }
+
+    fn encoded(&self) -> String {
+        let mut seq = String::new();
+        for row in self.data.chunks(self.rect.width.max(1) as usize) {
+            for hb in row {
+                let (fg, bg, symbol) = (sgr(hb.fg, 38), sgr(hb.bg, 48), hb.symbol);
+                seq.push_str(&format!("\x1b[{fg};{bg}m{symbol}"));
+            }
+            seq.push_str("\x1b[0m\n");
+        }
+        seq
+    }
 }
 
+/// A resizing halfblocks protocol.
+///
+/// The cells are only encoded again when the source or the area changes, and are otherwise copied
+/// into the buffer as they are on each render.
 #[derive(Clone)]
 pub struct StatefulHalfblocks {
     source: ImageSource,
     current: Halfblocks,
     hash: u64,
+    blocks: Blocks,
+    options: HalfblocksOptions,
+    /// Whether the options changed since the last encode, see [StatefulHalfblocks::set_options].
+    options_changed: bool,
 }
 
 impl StatefulHalfblocks {
     pub fn new(source: ImageSource) -> StatefulHalfblocks {
+        StatefulHalfblocks::new_with_blocks(source, Blocks::Halves)
+    }
+
+    /// Create a StatefulHalfblocks that draws with the given [Blocks].
+    pub fn new_with_blocks(source: ImageSource, blocks: Blocks) -> StatefulHalfblocks {
         StatefulHalfblocks {
             source,
             current: Halfblocks::default(),
             hash: u64::default(),
+            blocks,
+            options: HalfblocksOptions::default(),
+            options_changed: false,
+        }
+    }
+
+    /// Set the [HalfblocksOptions]. The image is encoded again on the next render.
+    pub fn set_options(&mut self, options: HalfblocksOptions) {
+        if options != self.options {
+            self.options = options;
+            self.options_changed = true;
         }
     }
+
+    /// Whether the image must be resized and encoded again, even for the same area.
+    fn force(&self, resize: &Resize) -> bool {
+        self.options_changed || self.source.resize_hash(resize) != self.hash
+    }
 }
 
 impl StatefulProtocol for StatefulHalfblocks {
     fn needs_resize(&mut self, resize: &Resize, area: Rect) -> Option<Rect> {
-        resize.needs_resize(&self.source, self.current.rect, area, false)
+        resize.needs_resize(&self.source, self.current.rect, area, self.force(resize))
     }
-    fn resize_encode(&mut self, resize: &Resize, background_color: Option<Rgb<u8>>, area: Rect) {
-        if area.width == 0 || area.height == 0 {
-            return;
-        }
-
-        let force = self.source.hash != self.hash;
-        if let Some((img, rect)) = resize.resize(
-            &self.source,
+    fn resize(
+        &self,
+        resize: &Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+    ) -> Option<ResizedImage> {
+        // Only copy the image for the transparent padding if it is actually resized.
+        let force = self.force(resize);
+        resize.needs_resize(&self.source, self.current.rect, area, force)?;
+        ResizedImage::new(
+            &transparent_padding(&self.source, background_color, self.blocks),
             self.current.rect,
-            area,
-            background_color,
             force,
-        ) {
-            let data = encode(&img, rect);
-            let current = Halfblocks { data, rect };
-            self.current = current;
-            self.hash = self.source.hash;
-        }
+            resize,
+            background_color,
+            area,
+        )
+    }
+    fn encode(&mut self, resized: ResizedImage) {
+        let data = std::mem::take(&mut self.current.data);
+        let data = encode(
+            &resized.image,
+            resized.rect,
+            resized.resize.filter_type(),
+            self.blocks,
+            resized.background_color,
+            self.options,
+            data,
+        );
+        self.current = Halfblocks {
+            data,
+            rect: resized.rect,
+        };
+        self.hash = resized.hash;
+        self.options_changed = false;
     }
     fn render(&mut self, area: Rect, buf: &mut Buffer) {
         Halfblocks::render(&self.current, area, buf);
     }
+    fn rect(&self) -> Rect {
+        self.current.rect
+    }
+    fn encoded(&self) -> String {
+        self.current.encoded()
+    }
+    fn set_source(&mut self, source: ImageSource) {
+        self.source = source;
+    }
+    fn advance_frame(&mut self) {
+        self.source.advance_frame();
+    }
+    fn frame_delay(&self) -> Option<Duration> {
+        self.source.frame_delay()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, ImageBuffer, Luma, Rgb, Rgba};
+    use ratatui::{buffer::Buffer, layout::Rect, style::Color};
+
+    use super::{
+        indexed, quadrant_symbol, sextant_symbol, split, Blocks, Halfblocks, HalfblocksColors,
+        HalfblocksOptions, StatefulHalfblocks,
+    };
+    use crate::{
+        protocol::{Protocol, StatefulProtocol},
+        ImageSource, Resize,
+    };
+
+    #[test]
+    fn encoded() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgb([255u8, 0, 0])).into();
+        let source = ImageSource::new(image, (1, 2));
+        let hb = Halfblocks::from_source(&source, Resize::Fit(None), None, Rect::new(0, 0, 2, 1))
+            .unwrap();
+        let cell = "\x1b[38;2;255;0;0;48;2;255;0;0m▀";
+        assert_eq!(format!("{cell}{cell}\x1b[0m\n"), hb.encoded());
+    }
+
+    #[test]
+    fn grayscale() {
+        let image: DynamicImage = ImageBuffer::from_fn(1, 2, |_, y| Luma([y as u8 * 200])).into();
+        let source = ImageSource::new(image, (1, 2));
+        let hb = Halfblocks::from_source(&source, Resize::Fit(None), None, Rect::new(0, 0, 1, 1))
+            .unwrap();
+        assert_eq!(Color::Rgb(0, 0, 0), hb.data[0].fg);
+        assert_eq!(Color::Rgb(200, 200, 200), hb.data[0].bg);
+    }
+
+    #[test]
+    fn transparency() {
+        // Opaque red on top, semi-transparent red, and fully transparent at the bottom.
+        let image: DynamicImage = ImageBuffer::from_fn(1, 4, |_, y| match y {
+            0 => Rgba([255u8, 0, 0, 255]),
+            1 => Rgba([255, 0, 0, 0]),
+            2 => Rgba([255, 0, 0, 200]),
+            _ => Rgba([0, 0, 0, 0]),
+        })
+        .into();
+        let source = ImageSource::new(image, (1, 2));
+        let area = Rect::new(0, 0, 1, 2);
+
+        // Transparent pixels show the terminal's background, soft edges are not darkened.
+        let hb = Halfblocks::from_source(&source, Resize::Fit(None), None, area).unwrap();
+        assert_eq!(Color::Rgb(255, 0, 0), hb.data[0].fg);
+        assert_eq!(Color::Reset, hb.data[0].bg);
+        assert_eq!(Color::Rgb(255, 0, 0), hb.data[1].fg);
+        assert_eq!(Color::Reset, hb.data[1].bg);
+        assert!(hb.encoded().starts_with("\x1b[38;2;255;0;0;49m▀"));
+
+        // With a background color, the image is composited over it.
+        let white = Rgb([255, 255, 255]);
+        let hb = Halfblocks::from_source(&source, Resize::Fit(None), Some(white), area).unwrap();
+        assert_eq!(Color::Rgb(255, 255, 255), hb.data[0].bg);
+        assert_eq!(Color::Rgb(255, 55, 55), hb.data[1].fg);
+        assert_eq!(Color::Rgb(255, 255, 255), hb.data[1].bg);
+    }
+
+    #[test]
+    fn transparent_top() {
+        // Transparent on top of opaque red, and fully transparent.
+        let image: DynamicImage = ImageBuffer::from_fn(1, 4, |_, y| match y {
+            1 => Rgba([255u8, 0, 0, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        })
+        .into();
+        let source = ImageSource::new(image, (1, 2));
+        let area = Rect::new(0, 0, 1, 2);
+
+        let hb = Halfblocks::from_source(&source, Resize::Fit(None), None, area).unwrap();
+        assert_eq!('▄', hb.data[0].symbol);
+        assert_eq!(Color::Rgb(255, 0, 0), hb.data[0].fg);
+        assert_eq!(Color::Reset, hb.data[0].bg);
+        assert_eq!(' ', hb.data[1].symbol);
+        assert_eq!(Color::Reset, hb.data[1].bg);
+        assert!(hb.encoded().starts_with("\x1b[38;2;255;0;0;49m▄"));
+    }
+
+    #[test]
+    fn odd_height() {
+        // Three pixel rows fill two cells, with nothing below the last pixel.
+        let image: DynamicImage = ImageBuffer::from_pixel(1, 3, Rgb([255u8, 0, 0])).into();
+        let source = ImageSource::new(image, (1, 2));
+        let area = Rect::new(0, 0, 1, 2);
+
+        let hb = Halfblocks::from_source(&source, Resize::Fit(None), None, area).unwrap();
+        assert_eq!(area, hb.rect);
+        assert_eq!('▀', hb.data[1].symbol);
+        assert_eq!(Color::Rgb(255, 0, 0), hb.data[1].fg);
+        assert_eq!(Color::Reset, hb.data[1].bg);
+
+        let white = Rgb([255, 255, 255]);
+        let mut hb = StatefulHalfblocks::new(source);
+        hb.resize_encode(&Resize::Fit(None), Some(white), area);
+        assert_eq!(Color::Rgb(255, 0, 0), hb.current.data[1].fg);
+        assert_eq!(Color::Rgb(255, 255, 255), hb.current.data[1].bg);
+    }
+
+    #[test]
+    fn render_clipped() {
+        let image: DynamicImage =
+            ImageBuffer::from_fn(2, 4, |x, y| Rgb([x as u8 * 100, y as u8 * 50, 0])).into();
+        let source = ImageSource::new(image, (1, 2));
+        let area = Rect::new(0, 0, 3, 3);
+        let hb = Halfblocks::from_source(&source, Resize::Fit(None), None, area).unwrap();
+        assert_eq!(Rect::new(0, 0, 2, 2), hb.rect);
+
+        // The buffer only covers the bottom right cell of the image.
+        let mut buf = Buffer::empty(Rect::new(1, 1, 2, 2));
+        hb.render(area, &mut buf);
+        assert_eq!("▀", buf.get(1, 1).symbol);
+        assert_eq!(Color::Rgb(100, 100, 0), buf.get(1, 1).fg);
+        assert_eq!(Color::Rgb(100, 150, 0), buf.get(1, 1).bg);
+        assert_eq!(" ", buf.get(2, 1).symbol);
+        assert_eq!(" ", buf.get(1, 2).symbol);
+    }
+
+    #[test]
+    fn cached_encoding() {
+        let red: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgb([255u8, 0, 0])).into();
+        let blue: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgb([0u8, 0, 255])).into();
+        let (resize, area) = (Resize::Fit(None), Rect::new(0, 0, 2, 1));
+        let mut hb = StatefulHalfblocks::new(ImageSource::new(red.clone(), (1, 2)));
+
+        assert_eq!(Some(area), hb.needs_resize(&resize, area));
+        hb.resize_encode(&resize, None, area);
+        assert_eq!(None, hb.needs_resize(&resize, area));
+
+        // An identical image keeps the encoding, a different one of the same size does not.
+        hb.set_source(ImageSource::new(red, (1, 2)));
+        assert_eq!(None, hb.needs_resize(&resize, area));
+        hb.set_source(ImageSource::new(blue, (1, 2)));
+        assert_eq!(Some(area), hb.needs_resize(&resize, area));
+        hb.resize_encode(&resize, None, area);
+        assert_eq!(None, hb.needs_resize(&resize, area));
+        assert_eq!(Color::Rgb(0, 0, 255), hb.current.data[0].fg);
+        let cell = "\x1b[38;2;0;0;255;48;2;0;0;255m▀";
+        assert_eq!(format!("{cell}{cell}\x1b[0m\n"), hb.encoded());
+    }
+
+    #[test]
+    fn pan() {
+        let image: DynamicImage =
+            ImageBuffer::from_fn(4, 2, |x, _| Rgb([x as u8 * 50, 0, 0])).into();
+        let area = Rect::new(0, 0, 2, 1);
+        let mut hb = StatefulHalfblocks::new(ImageSource::new(image, (1, 2)));
+
+        hb.resize_encode(&Resize::Pan(1, 0), None, area);
+        assert_eq!(None, hb.needs_resize(&Resize::Pan(1, 0), area));
+        assert_eq!(Color::Rgb(50, 0, 0), hb.current.data[0].fg);
+
+        // Same rect, but a different part of the image.
+        assert_eq!(Some(area), hb.needs_resize(&Resize::Pan(2, 0), area));
+        hb.resize_encode(&Resize::Pan(2, 0), None, area);
+        assert_eq!(Color::Rgb(100, 0, 0), hb.current.data[0].fg);
+    }
+
+    #[test]
+    fn resize_then_encode() {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 4, Rgb([255u8, 0, 0])).into();
+        let (resize, area) = (Resize::Fit(None), Rect::new(0, 0, 2, 1));
+        let mut hb = StatefulHalfblocks::new(ImageSource::new(image, (2, 4)));
+
+        let resized = hb.resize(&resize, None, area).unwrap();
+        assert_eq!(area, resized.rect);
+        assert_eq!((4, 4), (resized.image.width(), resized.image.height()));
+        // Nothing is encoded until the resized image is passed back.
+        assert_eq!(Some(area), hb.needs_resize(&resize, area));
+        hb.encode(resized);
+        assert_eq!(None, hb.needs_resize(&resize, area));
+        assert!(hb.resize(&resize, None, area).is_none());
+        assert_eq!(Color::Rgb(255, 0, 0), hb.current.data[0].fg);
+    }
+
+    #[test]
+    fn encoded_quadrants() {
+        let image: DynamicImage = ImageBuffer::from_fn(2, 2, |x, _| {
+            if x == 0 {
+                Rgb([255u8, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        })
+        .into();
+        let source = ImageSource::new(image, (2, 2));
+        let hb = Halfblocks::from_source_with_blocks(
+            &source,
+            Resize::Fit(None),
+            None,
+            Rect::new(0, 0, 1, 1),
+            Blocks::Quadrants,
+        )
+        .unwrap();
+        assert_eq!("\x1b[38;2;0;0;255;48;2;255;0;0m▐\x1b[0m\n", hb.encoded());
+    }
+
+    #[test]
+    fn quadrant_colors() {
+        let (red, blue) = ([255, 0, 0], [0, 0, 255]);
+        let quadrant = |pixels: [[u8; 3]; 4]| quadrant_symbol(split(&pixels).0);
+        assert_eq!('█', quadrant([red, red, red, red]));
+        assert_eq!('▟', quadrant([red, blue, blue, blue]));
+        assert_eq!('▚', quadrant([blue, red, red, blue]));
+        assert_eq!('▙', quadrant([red, blue, red, red]));
+    }
+
+    #[test]
+    fn sextant_symbols() {
+        assert_eq!('🬀', sextant_symbol(1));
+        assert_eq!('🬓', sextant_symbol(20));
+        assert_eq!('🬔', sextant_symbol(22));
+        assert_eq!('🬨', sextant_symbol(43));
+        assert_eq!('🬻', sextant_symbol(62));
+        assert_eq!('▌', sextant_symbol(21));
+        assert_eq!('█', sextant_symbol(63));
+    }
+
+    #[test]
+    fn sextant_colors() {
+        let (red, blue) = ([255, 0, 0], [0, 0, 255]);
+        let (mask, fg, bg) = split(&[red, red, blue, blue, blue, blue]);
+        // Blue is the foreground of the lower four sextants.
+        assert_eq!('🬹', sextant_symbol(mask));
+        assert_eq!(blue, fg);
+        assert_eq!(red, bg);
+    }
+
+    #[test]
+    fn indexed_colors() {
+        assert_eq!(Color::Indexed(16), indexed(Color::Rgb(0, 0, 0)));
+        assert_eq!(Color::Indexed(231), indexed(Color::Rgb(255, 255, 255)));
+        assert_eq!(Color::Indexed(196), indexed(Color::Rgb(250, 10, 0)));
+        assert_eq!(Color::Indexed(244), indexed(Color::Rgb(128, 128, 128)));
+        assert_eq!(Color::Reset, indexed(Color::Reset));
+
+        let image: DynamicImage = ImageBuffer::from_pixel(1, 2, Rgb([250u8, 10, 0])).into();
+        let source = ImageSource::new(image, (1, 2));
+        let options = HalfblocksOptions {
+            colors: HalfblocksColors::Indexed,
+            ..HalfblocksOptions::default()
+        };
+        let hb = Halfblocks::from_source_with_options(
+            &source,
+            Resize::Fit(None),
+            None,
+            Rect::new(0, 0, 1, 1),
+            Blocks::Halves,
+            options,
+        )
+        .unwrap();
+        assert_eq!("\x1b[38;5;196;48;5;196m▀\x1b[0m\n", hb.encoded());
+    }
+
+    #[test]
+    fn dither() {
+        // A diagonal sky gradient, which bands without dithering.
+        let image: DynamicImage =
+            ImageBuffer::from_fn(16, 16, |x, y| Rgb([40, 80 + (x + y) as u8 * 5, 230])).into();
+        let encode_with = |dither| {
+            let mut hb = StatefulHalfblocks::new_with_blocks(
+                ImageSource::new(image.clone(), (2, 2)),
+                Blocks::Quadrants,
+            );
+            hb.set_options(HalfblocksOptions {
+                dither,
+                ..HalfblocksOptions::default()
+            });
+            let area = Rect::new(0, 0, 8, 8);
+            hb.resize_encode(&Resize::Fit(None), None, area);
+            hb.encoded()
+        };
+        assert_ne!(encode_with(false), encode_with(true));
+
+        // Flat areas have no error to diffuse.
+        let flat: DynamicImage = ImageBuffer::from_pixel(4, 4, Rgb([40u8, 80, 230])).into();
+        let options = HalfblocksOptions {
+            dither: true,
+            ..HalfblocksOptions::default()
+        };
+        let area = Rect::new(0, 0, 2, 2);
+        let source = ImageSource::new(flat, (2, 2));
+        let dithered = Halfblocks::from_source_with_options(
+            &source,
+            Resize::Fit(None),
+            None,
+            area,
+            Blocks::Quadrants,
+            options,
+        )
+        .unwrap();
+        let plain = Halfblocks::from_source_with_blocks(
+            &source,
+            Resize::Fit(None),
+            None,
+            area,
+            Blocks::Quadrants,
+        )
+        .unwrap();
+        assert_eq!(plain.encoded(), dithered.encoded());
+    }
 }
@@ -1,18 +1,48 @@
 //! ITerm2 protocol implementation.
+//!
+//! The image is sized in cells (`width=N;height=M`) rather than in pixels, so that it always
+//! covers exactly its [Rect], even if the terminal's actual font size differs from
+//! [crate::picker::Picker::font_size], e.g. on a display with a different DPI.
 use base64::{engine::general_purpose, Engine};
-use image::{codecs::jpeg::JpegEncoder, DynamicImage, Rgb};
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    ColorType, DynamicImage, ImageEncoder, ImageFormat, Rgb,
+};
 use ratatui::{buffer::Buffer, layout::Rect};
-use std::{cmp::min, format};
+use std::{cmp::min, fmt::Write, io::Cursor, time::Duration};
 
-use super::{Protocol, StatefulProtocol};
-use crate::{ImageSource, Resize, Result};
+use super::{clear_erase, clip, Encoded, Protocol, ResizedImage, StatefulProtocol};
+use crate::{cells_to_pixels, pixels_to_cells, FontSize, ImageSource, Resize, Result};
 
-// Fixed sixel protocol
+/// Options for the iTerm2 protocol.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Iterm2Options {
+    /// Keep the aspect ratio of the image within its cells (`preserveAspectRatio`). Defaults to
+    /// `true`.
+    ///
+    /// The image is resized to fill its cells exactly, so this only matters if the font size is
+    /// off. Then the image is letterboxed within its cells, or stretched to them if `false`.
+    pub preserve_aspect_ratio: bool,
+}
+
+impl Default for Iterm2Options {
+    fn default() -> Self {
+        Iterm2Options {
+            preserve_aspect_ratio: true,
+        }
+    }
+}
+
+// Fixed iTerm2 protocol
+///
+/// An iTerm2 image can only be drawn whole, so nothing is drawn if the image is only partially
+/// inside the buffer, e.g. in a scrolled viewport. [Iterm2State] encodes the visible part instead.
 #[derive(Clone, Default)]
 pub struct FixedIterm2 {
     pub data: String,
     pub rect: Rect,
     pub is_tmux: bool,
+    pub options: Iterm2Options,
 }
 
 impl FixedIterm2 {
@@ -22,38 +52,136 @@ impl FixedIterm2 {
         background_color: Option<Rgb<u8>>,
         is_tmux: bool,
         area: Rect,
+    ) -> Result<Self> {
+        Self::from_source_with_options(
+            source,
+            resize,
+            background_color,
+            is_tmux,
+            area,
+            Iterm2Options::default(),
+        )
+    }
+
+    /// Create a FixedIterm2 from an image, with [Iterm2Options].
+    pub fn from_source_with_options(
+        source: &ImageSource,
+        resize: Resize,
+        background_color: Option<Rgb<u8>>,
+        is_tmux: bool,
+        area: Rect,
+        options: Iterm2Options,
     ) -> Result<Self> {
         let (img, rect) = resize
             .resize(source, Rect::default(), area, background_color, false)
             .unwrap_or_else(|| (source.image.clone(), source.desired));
 
-        let data = encode(img, is_tmux)?;
+        let mut data = String::new();
+        let original = source.original_of(&img);
+        encode(&img, original, rect, is_tmux, &options, &mut data)?;
+        Ok(Self {
+            data,
+            rect,
+            is_tmux,
+            options,
+        })
+    }
+
+    /// Create a FixedIterm2 from encoded image data, e.g. the contents of a GIF file, that is
+    /// passed to the terminal as it is.
+    ///
+    /// Unlike [FixedIterm2::from_source], the image is neither decoded nor re-encoded, so that
+    /// terminals like WezTerm or iTerm2 play animated GIFs. Only the dimensions are read, to size
+    /// the image to the cells that cover it with `font_size`, so the format must still be one that
+    /// the [image] crate's enabled features can read, e.g. GIF with `image-defaults`.
+    pub fn from_bytes(bytes: &[u8], font_size: FontSize, is_tmux: bool) -> Result<Self> {
+        let (width, height) = image::io::Reader::new(Cursor::new(bytes))
+            .with_guessed_format()?
+            .into_dimensions()?;
+        let (width, height) = pixels_to_cells(font_size, width, height);
+        let rect = Rect::new(0, 0, width, height);
+        let options = Iterm2Options::default();
+
+        let mut data = String::new();
+        write_escape(bytes, rect, is_tmux, &options, &mut data);
         Ok(Self {
             data,
             rect,
             is_tmux,
+            options,
         })
     }
 }
 
+/// Encode the image into `data`, reusing its allocation. `data` is left untouched on errors.
+///
+/// The image is sized to the width and height of `rect`, in cells. Images with an alpha channel
+/// are sent as PNG to keep the transparency, others as JPEG. If the `original` data of the image is
+/// PNG or JPEG, it is sent as it is.
 // TODO: change E to sixel_rs::status::Error and map when calling
-fn encode(img: DynamicImage, is_tmux: bool) -> Result<String> {
-    let mut jpg = vec![];
-    JpegEncoder::new_with_quality(&mut jpg, 75).encode_image(&img)?;
-    let data = general_purpose::STANDARD.encode(&jpg);
+fn encode(
+    img: &DynamicImage,
+    original: Option<Encoded>,
+    rect: Rect,
+    is_tmux: bool,
+    options: &Iterm2Options,
+    data: &mut String,
+) -> Result<()> {
+    if let Some(original) = original {
+        if matches!(original.format, ImageFormat::Png | ImageFormat::Jpeg) {
+            write_escape(&original.data, rect, is_tmux, options, data);
+            return Ok(());
+        }
+    }
 
+    let mut bytes = vec![];
+    if img.color().has_alpha() {
+        let rgba = img.to_rgba8();
+        PngEncoder::new(&mut bytes).write_image(
+            rgba.as_raw(),
+            rgba.width(),
+            rgba.height(),
+            ColorType::Rgba8,
+        )?;
+    } else {
+        JpegEncoder::new_with_quality(&mut bytes, 75).encode_image(img)?;
+    }
+
+    write_escape(&bytes, rect, is_tmux, options, data);
+    Ok(())
+}
+
+/// Write the inline image escape sequence of encoded image data into `data`, sized to the width
+/// and height of `rect`, in cells.
+fn write_escape(
+    bytes: &[u8],
+    rect: Rect,
+    is_tmux: bool,
+    options: &Iterm2Options,
+    data: &mut String,
+) {
     let (start, end) = if is_tmux {
         ("\x1bPtmux;\x1b\x1b", "\x1b\\")
     } else {
         ("\x1b", "")
     };
-    Ok(format!(
-        "{start}]1337;File=inline=1;size={};width={}px;height={}px;doNotMoveCursor=1:{}\x07{end}",
-        jpg.len(),
-        img.width(),
-        img.height(),
+    data.clear();
+    // Writing to a String cannot fail.
+    let _ = write!(
         data,
-    ))
+        concat!(
+            "{}]1337;File=inline=1;size={};width={};height={};",
+            "preserveAspectRatio={};doNotMoveCursor=1:"
+        ),
+        start,
+        bytes.len(),
+        rect.width,
+        rect.height,
+        options.preserve_aspect_ratio as u8,
+    );
+    general_purpose::STANDARD.encode_string(bytes, data);
+    data.push('\x07');
+    data.push_str(end);
 }
 
 impl Protocol for FixedIterm2 {
@@ -63,6 +191,9 @@ impl Protocol for FixedIterm2 {
     fn rect(&self) -> Rect {
         self.rect
     }
+    fn encoded(&self) -> String {
+        self.data.clone()
+    }
 }
 
 fn render(rect: Rect, data: &str, area: Rect, buf: &mut Buffer, overdraw: bool) {
@@ -77,6 +208,10 @@ fn render(rect: Rect, data: &str, area: Rect, buf: &mut Buffer, overdraw: bool)
         }
         Some(r) => r,
     };
+    // An iTerm2 image can only be drawn whole, see [Iterm2State] for clipping.
+    if clip(buf, render_area) != Some(render_area) {
+        return;
+    }
 
     buf.get_mut(render_area.left(), render_area.top())
         .set_symbol(data);
@@ -95,6 +230,12 @@ fn render(rect: Rect, data: &str, area: Rect, buf: &mut Buffer, overdraw: bool)
 }
 
 fn render_area(rect: Rect, area: Rect, overdraw: bool) -> Option<Rect> {
+    let area = Rect::new(
+        area.x + rect.x,
+        area.y + rect.y,
+        area.width.saturating_sub(rect.x),
+        area.height.saturating_sub(rect.y),
+    );
     if overdraw {
         return Some(Rect::new(
             area.x,
@@ -110,11 +251,21 @@ fn render_area(rect: Rect, area: Rect, overdraw: bool) -> Option<Rect> {
     Some(Rect::new(area.x, area.y, rect.width, rect.height))
 }
 
+/// A resizing iTerm2 protocol.
+///
+/// If the image is only partially inside the buffer, e.g. in a scrolled viewport, only the visible
+/// part of the image is encoded and drawn.
 #[derive(Clone)]
 pub struct Iterm2State {
     source: ImageSource,
     current: FixedIterm2,
     hash: u64,
+    /// Whether the options changed since the last encode, see [Iterm2State::set_options].
+    options_changed: bool,
+    /// The resized image of `current`, to encode visible parts of it.
+    image: Option<DynamicImage>,
+    /// The visible part of `current`, relative to its rect, and its encoding.
+    clipped: Option<(Rect, String)>,
 }
 
 impl Iterm2State {
@@ -126,44 +277,226 @@ impl Iterm2State {
                 ..FixedIterm2::default()
             },
             hash: u64::default(),
+            options_changed: false,
+            image: None,
+            clipped: None,
+        }
+    }
+
+    /// Set the [Iterm2Options]. The image is encoded again on the next render.
+    pub fn set_options(&mut self, options: Iterm2Options) {
+        if options != self.current.options {
+            self.current.options = options;
+            self.options_changed = true;
         }
     }
+
+    /// Whether the image must be resized and encoded again, even for the same area.
+    fn force(&self, resize: &Resize) -> bool {
+        self.options_changed || self.source.resize_hash(resize) != self.hash
+    }
+
+    /// Encode the part of the resized image that is inside `clip`, in cells.
+    fn encode_clip(&self, clip: Rect) -> Option<String> {
+        let image = self.image.as_ref()?;
+        let font_size = self.source.font_size;
+        let (x, y) = cells_to_pixels(font_size, clip.x, clip.y);
+        let (width, height) = cells_to_pixels(font_size, clip.width, clip.height);
+        let mut data = String::new();
+        encode(
+            &image.crop_imm(x, y, width, height),
+            None,
+            clip,
+            self.current.is_tmux,
+            &self.current.options,
+            &mut data,
+        )
+        .ok()?;
+        Some(data)
+    }
 }
 
 impl StatefulProtocol for Iterm2State {
     fn needs_resize(&mut self, resize: &Resize, area: Rect) -> Option<Rect> {
-        resize.needs_resize(&self.source, self.current.rect, area, false)
+        resize.needs_resize(&self.source, self.current.rect, area, self.force(resize))
     }
-    fn resize_encode(&mut self, resize: &Resize, background_color: Option<Rgb<u8>>, area: Rect) {
-        if area.width == 0 || area.height == 0 {
-            return;
-        }
-
-        let force = self.source.hash != self.hash;
-        if let Some((img, rect)) = resize.resize(
+    fn resize(
+        &self,
+        resize: &Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+    ) -> Option<ResizedImage> {
+        ResizedImage::new(
             &self.source,
             self.current.rect,
-            area,
+            self.force(resize),
+            resize,
             background_color,
-            force,
+            area,
+        )
+    }
+    fn encode(&mut self, resized: ResizedImage) {
+        let current = &mut self.current;
+        match encode(
+            &resized.image,
+            resized.original,
+            resized.rect,
+            current.is_tmux,
+            &current.options,
+            &mut current.data,
         ) {
-            let is_tmux = self.current.is_tmux;
-            match encode(img, is_tmux) {
-                Ok(data) => {
-                    self.current = FixedIterm2 {
-                        data,
-                        rect,
-                        is_tmux,
-                    };
-                    self.hash = self.source.hash;
+            Ok(()) => {
+                self.current.rect = resized.rect;
+                self.hash = resized.hash;
+                self.options_changed = false;
+                self.image = Some(resized.image);
+                self.clipped = None;
+            }
+            Err(_err) => {
+                // TODO: save err in struct and expose in trait?
+            }
+        }
+    }
+    fn render(&mut self, area: Rect, buf: &mut Buffer) {
+        let rect = self.current.rect;
+        let (full, visible) = match render_area(rect, area, true) {
+            Some(full) => (full, clip(buf, full)),
+            None => return,
+        };
+        match visible {
+            Some(visible) if visible == full => {
+                render(rect, &self.current.data, area, buf, true);
+            }
+            Some(visible) => {
+                let clip = Rect::new(
+                    visible.x - full.x,
+                    visible.y - full.y,
+                    visible.width,
+                    visible.height,
+                );
+                if self.clipped.as_ref().map(|(c, _)| *c) != Some(clip) {
+                    self.clipped = self.encode_clip(clip).map(|data| (clip, data));
                 }
-                Err(_err) => {
-                    // TODO: save err in struct and expose in trait?
+                if let Some((_, data)) = &self.clipped {
+                    let rect = Rect::new(0, 0, visible.width, visible.height);
+                    render(rect, data, visible, buf, true);
                 }
             }
+            None => {}
         }
     }
-    fn render(&mut self, area: Rect, buf: &mut Buffer) {
-        render(self.current.rect, &self.current.data, area, buf, true);
+    fn rect(&self) -> Rect {
+        self.current.rect
+    }
+    fn encoded(&self) -> String {
+        self.current.encoded()
+    }
+    fn set_source(&mut self, source: ImageSource) {
+        self.source = source;
+    }
+    fn advance_frame(&mut self) {
+        self.source.advance_frame();
+    }
+    fn frame_delay(&self) -> Option<Duration> {
+        self.source.frame_delay()
+    }
+    fn clear(&mut self, area: Rect, buf: &mut Buffer) {
+        clear_erase(self.rect(), area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use base64::{engine::general_purpose, Engine};
+    use image::{DynamicImage, ImageBuffer, ImageOutputFormat, Rgb};
+    use ratatui::layout::Rect;
+
+    use super::{FixedIterm2, Iterm2Options, Iterm2State};
+    use crate::{
+        protocol::{Protocol, StatefulProtocol},
+        ImageSource, Resize,
+    };
+
+    fn source() -> ImageSource {
+        let image: DynamicImage = ImageBuffer::from_pixel(40, 40, Rgb([255u8, 0, 0])).into();
+        ImageSource::new(image, (10, 20))
+    }
+
+    #[test]
+    fn sized_in_cells() {
+        let iterm2 = FixedIterm2::from_source(
+            &source(),
+            Resize::Fit(None),
+            None,
+            false,
+            Rect::new(0, 0, 10, 10),
+        )
+        .unwrap();
+        assert_eq!(Rect::new(0, 0, 4, 2), iterm2.rect());
+        assert!(iterm2
+            .encoded()
+            .contains(";width=4;height=2;preserveAspectRatio=1;"));
+    }
+
+    #[test]
+    fn from_bytes() {
+        let image: DynamicImage = ImageBuffer::from_pixel(25, 30, Rgb([255u8, 0, 0])).into();
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+
+        let iterm2 = FixedIterm2::from_bytes(&png, (10, 20), false).unwrap();
+        assert_eq!(Rect::new(0, 0, 3, 2), iterm2.rect());
+        let encoded = iterm2.encoded();
+        assert!(encoded.contains(&format!("size={};width=3;height=2;", png.len())));
+        assert!(encoded.contains(&general_purpose::STANDARD.encode(&png)));
+
+        assert!(FixedIterm2::from_bytes(b"garbage", (10, 20), false).is_err());
+    }
+
+    #[test]
+    fn original_data() {
+        let image: DynamicImage = ImageBuffer::from_pixel(40, 40, Rgb([255u8, 0, 0])).into();
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+        let source = ImageSource::from_reader(Cursor::new(&png), (10, 20)).unwrap();
+        let original = general_purpose::STANDARD.encode(&png);
+
+        // At the native size, the PNG is sent as it is.
+        let mut iterm2 = Iterm2State::new(source, false);
+        iterm2.resize_encode(&Resize::Fit(None), None, Rect::new(0, 0, 10, 10));
+        assert!(iterm2.encoded().ends_with(&format!(":{original}\x07")));
+
+        iterm2.resize_encode(&Resize::Fit(None), None, Rect::new(0, 0, 2, 2));
+        assert_eq!(Rect::new(0, 0, 2, 1), iterm2.rect());
+        assert!(!iterm2.encoded().contains(&original));
+    }
+
+    #[test]
+    fn options() {
+        let options = Iterm2Options {
+            preserve_aspect_ratio: false,
+        };
+        let mut iterm2 = Iterm2State::new(source(), false);
+        let (resize, area) = (Resize::Fit(None), Rect::new(0, 0, 10, 10));
+        iterm2.set_options(options);
+        iterm2.resize_encode(&resize, None, area);
+        assert!(iterm2.encoded().contains(";preserveAspectRatio=0;"));
+
+        iterm2.set_options(options);
+        assert_eq!(None, iterm2.needs_resize(&resize, area));
+        let rect = iterm2.rect();
+        iterm2.set_options(Iterm2Options::default());
+        // Still drawn in place until it is encoded again.
+        assert_eq!(rect, iterm2.rect());
+        assert!(iterm2.needs_resize(&resize, area).is_some());
+        iterm2.resize_encode(&resize, None, area);
+        assert!(iterm2.encoded().contains(";preserveAspectRatio=1;"));
+        assert_eq!(None, iterm2.needs_resize(&resize, area));
     }
 }
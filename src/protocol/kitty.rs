@@ -1,20 +1,194 @@
-/// https://sw.kovidgoyal.net/kitty/graphics-protocol/#unicode-placeholders
-use std::format;
+//! Kitty graphics protocol implementations.
+//!
+//! Images are transmitted with a virtual placement (`U=1`), and placed with [unicode placeholders]:
+//! each cell of the image area holds the `U+10EEEE` character, with the image id as 24-bit
+//! foreground color, and the row and column as diacritics. The terminal draws the image wherever
+//! the placeholders are, so the image moves along with the cells, e.g. when scrolling, and other
+//! widgets can draw over it like over any other text. The same image can be shown in several
+//! places while transmitting it only once, see [Kitty::placement] and [StatefulKitty::placement].
+//!
+//! Animated sources created with [StatefulKitty::new_animated] transmit all of their frames at
+//! once, and let the terminal play the [animation] in a loop, which can be paused and resumed with
+//! [StatefulKitty::pause] and [StatefulKitty::play].
+//!
+//! With [KittyOptions::compress], the image data is zlib-compressed (`o=z`) before it is base64
+//! encoded, which makes the escape sequences much shorter e.g. over SSH, at the cost of CPU time.
+//!
+//! [unicode placeholders]: https://sw.kovidgoyal.net/kitty/graphics-protocol/#unicode-placeholders
+//! [animation]: https://sw.kovidgoyal.net/kitty/graphics-protocol/#animation
+use std::{
+    fmt::Write as _,
+    format,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, LockResult, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
 
 use base64::{engine::general_purpose, Engine};
-use image::{DynamicImage, Rgb};
-use ratatui::{buffer::Buffer, layout::Rect};
+use flate2::{write::ZlibEncoder, Compression};
+use image::{DynamicImage, ImageFormat, Rgb};
+use ratatui::{buffer::Buffer, layout::Rect, style::Color};
 
-use crate::{ImageSource, Resize, Result};
+use crate::{errors::Errors, ImageSource, Resize, Result};
 
-use super::{Protocol, StatefulProtocol};
+use super::{clear_cells, in_buffer, Encoded, Protocol, ResizedImage, StatefulProtocol};
+
+/// Options for the kitty protocol.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct KittyOptions {
+    /// Compress the image data with zlib (`o=z`). Defaults to `false`.
+    ///
+    /// Large images are transmitted much faster over slow connections, but compressing takes
+    /// some time on every encode.
+    pub compress: bool,
+    /// The z-index of the placement (`z`). Defaults to `0`.
+    ///
+    /// Where images overlap, e.g. a caption over a photo, the one with the higher z-index is
+    /// drawn on top. Negative values draw the image below the text.
+    pub z_index: i32,
+    /// Wrap the escape sequences in tmux's passthrough (`\x1bPtmux;...\x1b\\`), so that they
+    /// reach the terminal from inside tmux. Defaults to `false`.
+    ///
+    /// Protocols created by [crate::picker::Picker] also wrap them if
+    /// [crate::picker::Picker::is_tmux] is set. The unicode placeholders are regular text, which
+    /// tmux draws like any other.
+    pub tmux: bool,
+}
+
+/// The process-wide [PendingDeletes] queue.
+static PENDING_DELETES: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Last allocated image id, see [next_id].
+static LAST_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Kitty rejects images with a width or height larger than this.
+const MAX_IMAGE_SIZE: u32 = 10000;
+
+/// Ids are encoded as 24-bit foreground colors of the unicode placeholders.
+const MAX_ID: u32 = 0xFF_FFFF;
+
+/// Allocate a new image id, unique among all kitty protocols of this process.
+///
+/// Ids wrap around after 2^24 - 1 images, and `0` is never used.
+pub fn next_id() -> u32 {
+    let mut id = 0;
+    while id == 0 {
+        id = LAST_ID.fetch_add(1, Ordering::Relaxed).wrapping_add(1) & MAX_ID;
+    }
+    id
+}
+
+/// Take the delete sequences of all dropped kitty images of the process-wide queue, see
+/// [PendingDeletes].
+///
+/// If no kitty protocol is going to be rendered anymore, e.g. after switching to another
+/// protocol, the returned sequence can be written to the terminal directly.
+pub fn take_pending_deletes() -> String {
+    PendingDeletes::default().take()
+}
+
+/// A queue of kitty image deletes that have not been written to the terminal yet.
+///
+/// Kitty keeps transmitted image data in memory until it is deleted. Dropping a [Kitty] or
+/// [StatefulKitty] (and all of its clones) queues a delete of its image, which is written out with
+/// the next render of any kitty protocol with the same queue.
+///
+/// The default is one process-wide queue, shared by all kitty protocols, see
+/// [take_pending_deletes]. Protocols with a separate queue, see [Kitty::with_pending_deletes] and
+/// [StatefulKitty::with_pending_deletes], only write out the deletes of their own images, e.g. to
+/// keep the images of several terminals apart.
+#[derive(Clone, Default)]
+pub struct PendingDeletes(Option<Arc<Mutex<Vec<u32>>>>);
+
+impl PendingDeletes {
+    /// A new queue, separate from the process-wide [PendingDeletes::default].
+    pub fn new() -> PendingDeletes {
+        PendingDeletes(Some(Arc::default()))
+    }
+
+    /// Take the delete sequences of all queued images.
+    pub fn take(&self) -> String {
+        let ids = match self.ids() {
+            Ok(mut ids) => std::mem::take(&mut *ids),
+            Err(_) => return String::new(),
+        };
+        ids.into_iter()
+            .map(|id| format!("\x1b_Gq=2,a=d,d=I,i={id}\x1b\\"))
+            .collect()
+    }
+
+    fn push(&self, id: u32) {
+        if let Ok(mut ids) = self.ids() {
+            ids.push(id);
+        }
+    }
+
+    fn ids(&self) -> LockResult<MutexGuard<'_, Vec<u32>>> {
+        match &self.0 {
+            Some(ids) => ids.lock(),
+            None => PENDING_DELETES.lock(),
+        }
+    }
+}
+
+/// A transmitted image, shared between clones of a protocol. Queues the image for deletion once
+/// the last clone is dropped, unless it is deleted already.
+struct TransmittedImage {
+    id: u32,
+    /// Whether the image was deleted with [StatefulKitty::delete], and not encoded again since.
+    deleted: AtomicBool,
+    /// The queue of the delete, shared between clones so that they all write out the deletes of
+    /// the same queue.
+    pending_deletes: Mutex<PendingDeletes>,
+}
+
+impl TransmittedImage {
+    fn new(id: u32) -> Arc<TransmittedImage> {
+        Arc::new(TransmittedImage {
+            id,
+            deleted: AtomicBool::new(false),
+            pending_deletes: Mutex::default(),
+        })
+    }
+
+    fn pending_deletes(&self) -> PendingDeletes {
+        self.pending_deletes
+            .lock()
+            .map(|pending_deletes| pending_deletes.clone())
+            .unwrap_or_default()
+    }
+
+    fn set_pending_deletes(&self, pending_deletes: PendingDeletes) {
+        if let Ok(mut current) = self.pending_deletes.lock() {
+            *current = pending_deletes;
+        }
+    }
+
+    /// Queue the image for deletion, unless it is deleted already.
+    fn delete(&self) {
+        if !self.deleted.swap(true, Ordering::Relaxed) {
+            self.pending_deletes().push(self.id);
+        }
+    }
+}
+
+impl Drop for TransmittedImage {
+    fn drop(&mut self) {
+        self.delete();
+    }
+}
 
 // Fixed Kitty protocol (transmits image data on every render!)
 #[derive(Clone, Default)]
 pub struct Kitty {
     transmit_data: String,
-    unique_id: u8,
+    unique_id: u32,
     rect: Rect,
+    is_tmux: bool,
+    image: Option<Arc<TransmittedImage>>,
 }
 
 impl Kitty {
@@ -28,117 +202,485 @@ impl Kitty {
         resize: Resize,
         background_color: Option<Rgb<u8>>,
         area: Rect,
-        id: u8,
+        id: u32,
+    ) -> Result<Self> {
+        Self::from_source_with_options(
+            source,
+            resize,
+            background_color,
+            area,
+            id,
+            KittyOptions::default(),
+        )
+    }
+
+    /// Create a FixedKitty from an image, with [KittyOptions].
+    pub fn from_source_with_options(
+        source: &ImageSource,
+        resize: Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+        id: u32,
+        options: KittyOptions,
     ) -> Result<Self> {
         let (image, desired) = resize
             .resize(source, Rect::default(), area, background_color, false)
             .unwrap_or_else(|| (source.image.clone(), source.desired));
+        if image.width() > MAX_IMAGE_SIZE || image.height() > MAX_IMAGE_SIZE {
+            return Err(Errors::ImageTooLarge {
+                width: image.width(),
+                height: image.height(),
+            });
+        }
 
-        let transmit_data = transmit_virtual(&image, id);
+        let original = source.original_of(&image);
+        let mut transmit_data = String::new();
+        transmit_virtual(&image, original, id, &options, &mut transmit_data);
         Ok(Self {
             transmit_data,
             unique_id: id,
             rect: desired,
+            is_tmux: options.tmux,
+            image: Some(TransmittedImage::new(id)),
         })
     }
+
+    /// Another placement of the same image, e.g. a logo that appears several times in a layout,
+    /// that only renders the unicode placeholders of the image id, without transmitting it again.
+    ///
+    /// This image must be rendered too, at least once, so that its data is transmitted. The
+    /// placement keeps the image alive in the terminal, like a clone.
+    pub fn placement(&self) -> Kitty {
+        Kitty {
+            transmit_data: String::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Queue the delete of the image to a separate [PendingDeletes] queue, also for its clones
+    /// and placements.
+    pub fn with_pending_deletes(self, pending_deletes: PendingDeletes) -> Kitty {
+        if let Some(image) = &self.image {
+            image.set_pending_deletes(pending_deletes);
+        }
+        self
+    }
+
+    fn pending_deletes(&self) -> PendingDeletes {
+        self.image
+            .as_ref()
+            .map_or_else(PendingDeletes::default, |image| image.pending_deletes())
+    }
 }
 
 impl Protocol for Kitty {
     fn render(&self, area: Rect, buf: &mut Buffer) {
-        let mut seq = Some(self.transmit_data.clone());
-        render(area, self.rect, buf, self.unique_id, &mut seq);
+        render(
+            area,
+            self.rect,
+            buf,
+            self.unique_id,
+            &self.pending_deletes(),
+            &[&self.transmit_data],
+            self.is_tmux,
+        );
     }
 
     fn rect(&self) -> Rect {
         self.rect
     }
+
+    fn encoded(&self) -> String {
+        self.transmit_data.clone()
+    }
+
+    /// Write the transmit sequence, and one line of unicode placeholders per cell row.
+    fn write_to(&self, out: &mut dyn Write) -> io::Result<()> {
+        let seq = self.pending_deletes().take() + &self.transmit_data;
+        out.write_all(passthrough(&seq, self.is_tmux).as_bytes())?;
+        let [_, r, g, b] = self.unique_id.to_be_bytes();
+        for y in 0..self.rect.height {
+            let mut line = format!("\x1b[38;2;{r};{g};{b}m");
+            for x in 0..self.rect.width {
+                add_placeholder(&mut line, x, y);
+            }
+            line.push_str("\x1b[0m\n");
+            out.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
 pub struct StatefulKitty {
     source: ImageSource,
-    pub unique_id: u8,
+    pub unique_id: u32,
     rect: Rect,
     hash: u64,
-    proto_state: KittyProtoState,
-}
-
-#[derive(Default, Clone, PartialEq)]
-enum KittyProtoState {
-    #[default]
-    Place,
-    TransmitAndPlace(String),
+    /// The transmit sequence of the last encode.
+    transmit_data: String,
+    /// Whether `transmit_data` is written out with the next render.
+    transmit: bool,
+    /// Control sequences that are written out with the next render, e.g. to change the frame.
+    controls: String,
+    image: Arc<TransmittedImage>,
+    /// Whether all frames of animated sources are transmitted and animated by the terminal.
+    animate: bool,
+    options: KittyOptions,
+    /// Whether the options changed since the last encode, see [StatefulKitty::set_options].
+    options_changed: bool,
+    /// Current frame of a terminal-animated image.
+    frame: usize,
+    /// Whether the terminal animation is stopped, see [StatefulKitty::pause].
+    paused: bool,
+    /// Whether this only renders the placeholders of another protocol's image, see
+    /// [StatefulKitty::placement].
+    placement: bool,
 }
 
 impl StatefulKitty {
-    pub fn new(source: ImageSource, id: u8) -> StatefulKitty {
+    /// Create a StatefulKitty with an image id, e.g. from [next_id].
+    pub fn new(source: ImageSource, id: u32) -> StatefulKitty {
         StatefulKitty {
             source,
             unique_id: id,
             rect: Rect::default(),
             hash: u64::default(),
-            proto_state: KittyProtoState::default(),
+            transmit_data: String::new(),
+            transmit: false,
+            controls: String::new(),
+            image: TransmittedImage::new(id),
+            animate: false,
+            options: KittyOptions::default(),
+            options_changed: false,
+            frame: 0,
+            paused: false,
+            placement: false,
+        }
+    }
+
+    /// Another placement of the same image, e.g. a sprite that repeats in a list, that only
+    /// renders the unicode placeholders of the image id, without transmitting it again.
+    ///
+    /// The placement has the size of the last encode of this protocol, and never resizes or
+    /// encodes by itself, so it should be created after this protocol has been encoded, e.g.
+    /// rendered, and created again after this protocol was resized. This protocol must be rendered
+    /// too, so that its data is transmitted. The placement keeps the image alive in the terminal,
+    /// like a clone.
+    pub fn placement(&self) -> StatefulKitty {
+        StatefulKitty {
+            transmit_data: String::new(),
+            transmit: false,
+            controls: String::new(),
+            placement: true,
+            ..self.clone()
+        }
+    }
+
+    /// Queue the delete of the image to a separate [PendingDeletes] queue, also for its clones
+    /// and placements, and write out the deletes of that queue when rendering.
+    pub fn with_pending_deletes(self, pending_deletes: PendingDeletes) -> StatefulKitty {
+        self.image.set_pending_deletes(pending_deletes);
+        self
+    }
+
+    /// Create a StatefulKitty that transmits all frames of an animated [ImageSource], and lets the
+    /// terminal play the animation in a loop.
+    ///
+    /// The terminal keeps the timing, so [StatefulProtocol::frame_delay] returns `None`.
+    /// [StatefulProtocol::advance_frame] still steps the animation to the next frame, and
+    /// [StatefulKitty::pause] and [StatefulKitty::play] stop and resume it.
+    ///
+    /// The loop count of GIFs is not available from the [image] crate, so animations always loop
+    /// forever.
+    pub fn new_animated(source: ImageSource, id: u32) -> StatefulKitty {
+        StatefulKitty {
+            animate: true,
+            ..StatefulKitty::new(source, id)
+        }
+    }
+
+    /// Set the [KittyOptions]. The image is encoded and transmitted again on the next render.
+    pub fn set_options(&mut self, options: KittyOptions) {
+        if options != self.options {
+            self.options = options;
+            self.options_changed = true;
+        }
+    }
+
+    /// Whether the image must be resized and encoded again, even for the same area.
+    fn force(&self, resize: &Resize) -> bool {
+        self.options_changed || self.source.resize_hash(resize) != self.hash
+    }
+
+    /// Set the [KittyOptions::z_index] of the placement, keeping the other options.
+    pub fn set_z_index(&mut self, z_index: i32) {
+        self.set_options(KittyOptions {
+            z_index,
+            ..self.options
+        });
+    }
+
+    /// Whether the terminal animates the current source.
+    fn animated(&self) -> bool {
+        self.animate && self.source.frames.len() > 1
+    }
+
+    /// Resume the animation of a [StatefulKitty::new_animated] that was paused, from the current
+    /// frame.
+    ///
+    /// The control sequence is written out with the next render. Does nothing for still images and
+    /// for a [StatefulKitty::placement], since the animation belongs to the shared image.
+    pub fn play(&mut self) {
+        if self.placement || !self.animated() || !self.paused {
+            return;
+        }
+        self.paused = false;
+        // Writing to a String cannot fail.
+        let _ = write!(
+            self.controls,
+            "\x1b_Gq=2,a=a,i={},s=3,v=1\x1b\\",
+            self.unique_id
+        );
+    }
+
+    /// Stop the animation of a [StatefulKitty::new_animated] at the current frame, until
+    /// [StatefulKitty::play] is called. [StatefulProtocol::advance_frame] still steps through the
+    /// frames while paused.
+    ///
+    /// The control sequence is written out with the next render. The animation stays paused when
+    /// the image is encoded again, e.g. after a resize. Does nothing for still images and for a
+    /// [StatefulKitty::placement].
+    pub fn pause(&mut self) {
+        if self.placement || !self.animated() || self.paused {
+            return;
         }
+        self.paused = true;
+        // Writing to a String cannot fail.
+        let _ = write!(
+            self.controls,
+            "\x1b_Gq=2,a=a,i={},s=1\x1b\\",
+            self.unique_id
+        );
+    }
+
+    /// Whether the animation is paused, see [StatefulKitty::pause].
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Delete the transmitted image from the terminal.
+    ///
+    /// The delete is written out with the next render of any kitty protocol with the same
+    /// [PendingDeletes] queue. If this protocol is rendered again, the image is transmitted again.
+    /// [StatefulProtocol::clear] also clears the cells of the image.
+    pub fn delete(&mut self) {
+        self.image.delete();
+        self.rect = Rect::default();
+        self.transmit = false;
+        self.controls.clear();
     }
 }
 
 impl StatefulProtocol for StatefulKitty {
     fn needs_resize(&mut self, resize: &Resize, area: Rect) -> Option<Rect> {
-        resize.needs_resize(&self.source, self.rect, area, false)
+        if self.placement {
+            return None;
+        }
+        resize.needs_resize(&self.source, self.rect, area, self.force(resize))
     }
-    fn resize_encode(&mut self, resize: &Resize, background_color: Option<Rgb<u8>>, area: Rect) {
-        if area.width == 0 || area.height == 0 {
-            return;
+    fn resize(
+        &self,
+        resize: &Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+    ) -> Option<ResizedImage> {
+        if self.placement {
+            return None;
         }
-
-        let force = self.source.hash != self.hash;
-        if let Some((img, rect)) =
-            resize.resize(&self.source, self.rect, area, background_color, force)
-        {
-            let data = transmit_virtual(&img, self.unique_id);
-            self.hash = self.source.hash;
-            self.rect = rect;
-            self.proto_state = KittyProtoState::TransmitAndPlace(data);
+        ResizedImage::new(
+            &self.source,
+            self.rect,
+            self.force(resize),
+            resize,
+            background_color,
+            area,
+        )
+    }
+    fn encode(&mut self, resized: ResizedImage) {
+        let compress = self.options.compress;
+        // Reuse the allocation of the previous transmit.
+        let mut data = std::mem::take(&mut self.transmit_data);
+        data.clear();
+        transmit_virtual(
+            &resized.image,
+            resized.original,
+            self.unique_id,
+            &self.options,
+            &mut data,
+        );
+        if self.animated() {
+            transmit_animation(
+                &self.source,
+                &resized.resize,
+                resized.rect,
+                resized.background_color,
+                self.unique_id,
+                compress,
+                &mut data,
+            );
+            if self.paused {
+                // Writing to a String cannot fail.
+                let _ = write!(data, "\x1b_Gq=2,a=a,i={},s=1\x1b\\", self.unique_id);
+            }
+            self.frame = 0;
         }
+        self.hash = resized.hash;
+        self.options_changed = false;
+        self.rect = resized.rect;
+        self.transmit_data = data;
+        // Transmitted again with the next render, so deleted again once dropped.
+        self.image.deleted.store(false, Ordering::Relaxed);
+        self.transmit = true;
+        self.controls.clear();
     }
     fn render(&mut self, area: Rect, buf: &mut Buffer) {
         // Transmit only once
-        let mut seq = match &mut self.proto_state {
-            KittyProtoState::TransmitAndPlace(seq) => {
-                let seq = std::mem::take(seq);
-                self.proto_state = KittyProtoState::Place;
-                Some(seq)
-            }
-            KittyProtoState::Place => None,
+        let transmit = if self.transmit {
+            &self.transmit_data[..]
+        } else {
+            ""
         };
-
-        render(area, self.rect, buf, self.unique_id, &mut seq);
+        if render(
+            area,
+            self.rect,
+            buf,
+            self.unique_id,
+            &self.image.pending_deletes(),
+            &[transmit, &self.controls],
+            self.options.tmux,
+        ) {
+            self.transmit = false;
+            self.controls.clear();
+        }
+    }
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+    fn encoded(&self) -> String {
+        self.transmit_data.clone()
+    }
+    fn set_source(&mut self, source: ImageSource) {
+        self.source = source;
+    }
+    /// Does nothing for a [StatefulKitty::placement], since the frame belongs to the shared image.
+    fn advance_frame(&mut self) {
+        if self.placement {
+            return;
+        }
+        if !self.animated() {
+            self.source.advance_frame();
+            return;
+        }
+        self.frame = (self.frame + 1) % self.source.frames.len();
+        // Frame numbers are 1-based.
+        // Writing to a String cannot fail.
+        let _ = write!(
+            self.controls,
+            "\x1b_Gq=2,a=a,i={},c={}\x1b\\",
+            self.unique_id,
+            self.frame + 1
+        );
+    }
+    fn frame_delay(&self) -> Option<Duration> {
+        if self.animated() {
+            return None;
+        }
+        self.source.frame_delay()
+    }
+    /// Also deletes the image from the terminal, with the escape sequence in the top left cell.
+    /// Like [StatefulKitty::delete], this resets the state, so that rendering again transmits the
+    /// image again.
+    fn clear(&mut self, area: Rect, buf: &mut Buffer) {
+        let rect = self.rect;
+        self.delete();
+        if let Some(visible) = clear_cells(rect, area, buf) {
+            let symbol = format!(
+                "{} ",
+                passthrough(&self.image.pending_deletes().take(), self.options.tmux)
+            );
+            buf.get_mut(visible.x, visible.y).set_symbol(&symbol);
+        }
     }
 }
 
-fn render(area: Rect, rect: Rect, buf: &mut Buffer, id: u8, seq: &mut Option<String>) {
-    // Draw each line of unicode placeholders but all into the first cell.
-    // I couldn't work out actually drawing into each cell of the buffer so
-    // that `.set_skip(true)` would be made unnecessary. Maybe some other escape
-    // sequence gets sneaked in somehow.
-    // It could also be made so that each cell starts and ends its own escape sequence
-    // with the image id, but maybe that's worse.
-    for y in 0..(area.height.min(rect.height)) {
-        let mut symbol = seq.take().unwrap_or_default();
-
-        // Start unicode placeholder sequence
-        symbol.push_str(&format!("\x1b[38;5;{id}m"));
-        add_placeholder(&mut symbol, 0, y);
+/// Render the placeholders, with the `pending_deletes` and the escape sequences `seq` before the
+/// first one, wrapped in tmux's passthrough if `tmux`.
+///
+/// Returns whether `seq` was written, i.e. whether any placeholder was inside the buffer.
+fn render(
+    area: Rect,
+    rect: Rect,
+    buf: &mut Buffer,
+    id: u32,
+    pending_deletes: &PendingDeletes,
+    seq: &[&str],
+    tmux: bool,
+) -> bool {
+    let area = Rect::new(
+        area.x + rect.x,
+        area.y + rect.y,
+        area.width.saturating_sub(rect.x),
+        area.height.saturating_sub(rect.y),
+    );
+    // The image id is the 24-bit foreground color of the placeholders.
+    let [_, r, g, b] = id.to_be_bytes();
+    let fg = Color::Rgb(r, g, b);
 
-        for x in 1..(area.width.min(rect.width)) {
-            // Add entire row with positions
+    // Each cell gets its own placeholder with row and column diacritics, so that ratatui can
+    // manage the cells like any other, and other widgets can draw over single cells. Cells
+    // outside of the buffer are left out, and kitty only draws the visible part of the image.
+    let mut first = true;
+    for y in 0..(area.height.min(rect.height)) {
+        for x in 0..(area.width.min(rect.width)) {
+            if !in_buffer(buf, area.left() + x, area.top() + y) {
+                continue;
+            }
+            let mut symbol = String::new();
+            if first {
+                first = false;
+                // Delete dropped images before possibly transmitting a new one with the same id.
+                symbol.push_str(&pending_deletes.take());
+                // Transmit along with the first visible placeholder. Note that ratatui counts the
+                // transmit data towards the cell's width, and skips diffing the cells after it
+                // for this frame, but invalidates them so that they are drawn on the next one.
+                seq.iter().for_each(|seq| symbol.push_str(seq));
+                symbol = passthrough(&symbol, tmux);
+            }
             add_placeholder(&mut symbol, x, y);
-            // Skip or something may overwrite it
-            buf.get_mut(area.left() + x, area.top() + y).set_skip(true);
+            buf.get_mut(area.left() + x, area.top() + y)
+                .set_symbol(&symbol)
+                .set_fg(fg);
         }
-        symbol.push_str("\x1b[0m"); // Stop messing with styles now.
-        buf.get_mut(area.left(), area.top() + y).set_symbol(&symbol);
     }
+    !first
+}
+
+/// Wrap each escape sequence of `seq` in tmux's passthrough if `tmux`, doubling its escapes.
+///
+/// The sequences are wrapped one by one, so that a large transmit stays in chunks, instead of one
+/// huge passthrough sequence.
+fn passthrough(seq: &str, tmux: bool) -> String {
+    if !tmux {
+        return seq.to_string();
+    }
+    let mut wrapped = String::new();
+    for seq in seq.split_inclusive("\x1b\\") {
+        wrapped.push_str("\x1bPtmux;");
+        wrapped.push_str(&seq.replace('\x1b', "\x1b\x1b"));
+        wrapped.push_str("\x1b\\");
+    }
+    wrapped
 }
 
 /// Create a kitty escape sequence for transmitting and virtual-placement.
@@ -147,37 +689,136 @@ fn render(area: Rect, rect: Rect, buf: &mut Buffer, id: u8, seq: &mut Option<Str
 /// A "virtual placement" (U=1) is created so that we can place it using unicode placeholders.
 /// Removing the placements when the unicode placeholder is no longer there is being handled
 /// automatically by kitty.
-fn transmit_virtual(img: &DynamicImage, id: u8) -> String {
+///
+/// If the `original` data of the image is PNG, it is sent as it is (`f=100`). The sequence is
+/// appended to `data`.
+fn transmit_virtual(
+    img: &DynamicImage,
+    original: Option<Encoded>,
+    id: u32,
+    options: &KittyOptions,
+    data: &mut String,
+) {
     let (w, h) = (img.width(), img.height());
-    let img_rgb8 = img.to_rgb8();
-    let bytes = img_rgb8.as_raw();
+    let mut control = format!("a=T,U=1,t=d,s={w},v={h}");
+    if options.z_index != 0 {
+        // Writing to a String cannot fail.
+        let _ = write!(control, ",z={}", options.z_index);
+    }
+    match original {
+        Some(original) if original.format == ImageFormat::Png => {
+            transmit_bytes(&original.data, 100, id, &control, false, data)
+        }
+        _ => transmit(img, id, &control, options.compress, data),
+    }
+}
+
+/// Append kitty escape sequences for transmitting the remaining frames of an animation, and
+/// starting the animation loop, to `str`.
+///
+/// Every frame is resized to the same `rect` as the root frame that was transmitted with
+/// [transmit_virtual].
+fn transmit_animation(
+    source: &ImageSource,
+    resize: &Resize,
+    rect: Rect,
+    background_color: Option<Rgb<u8>>,
+    id: u32,
+    compress: bool,
+    str: &mut String,
+) {
+    for (frame, delay) in source.frames.iter().skip(1) {
+        let img = resize.resize_to_rect(
+            frame,
+            source.font_size,
+            source.scale,
+            rect,
+            background_color,
+        );
+        let (w, h) = (img.width(), img.height());
+        let gap = gap_millis(delay);
+        let control = format!("a=f,t=d,s={w},v={h},z={gap}");
+        transmit(&img, id, &control, compress, str);
+    }
+    // The root frame's gap can only be set with a control command.
+    let root_gap = source
+        .frames
+        .first()
+        .map_or(0, |(_, delay)| gap_millis(delay));
+    // Writing to a String cannot fail.
+    let _ = write!(str, "\x1b_Gq=2,a=a,i={id},r=1,z={root_gap}\x1b\\");
+    // Run the animation in a loop (s=3), looping forever (v=1).
+    let _ = write!(str, "\x1b_Gq=2,a=a,i={id},s=3,v=1\x1b\\");
+}
+
+fn gap_millis(delay: &Duration) -> u32 {
+    delay.as_millis().min(u32::MAX as u128) as u32
+}
+
+/// Transmit image data in chunks, with `control` keys on the first chunk, appended to `str`.
+///
+/// Images with an alpha channel are transmitted as RGBA8, so that kitty shows them transparent,
+/// and all others as RGB8. With `compress`, the data is zlib-compressed (`o=z`), unless that does
+/// not make it any smaller.
+fn transmit(img: &DynamicImage, id: u32, control: &str, compress: bool, str: &mut String) {
+    let (bytes, format) = if img.color().has_alpha() {
+        (img.to_rgba8().into_raw(), 32)
+    } else {
+        (img.to_rgb8().into_raw(), 24)
+    };
+    transmit_bytes(&bytes, format, id, control, compress, str)
+}
 
-    let mut str = String::new();
+/// Transmit data of the kitty `format` (`f=..`) in chunks, see [transmit].
+fn transmit_bytes(
+    bytes: &[u8],
+    format: u32,
+    id: u32,
+    control: &str,
+    compress: bool,
+    str: &mut String,
+) {
+    let mut compressed = None;
+    let mut compression = "";
+    if compress {
+        // Tiny or noisy images may not get any smaller.
+        compressed = zlib(bytes).filter(|compressed| compressed.len() < bytes.len());
+        if compressed.is_some() {
+            compression = ",o=z";
+        }
+    }
+    let bytes = compressed.as_deref().unwrap_or(bytes);
 
-    let mut payload: String;
     let chunks = bytes.chunks(4000);
     let chunk_count = chunks.len();
+    // Base64 payload plus the escape sequence around each chunk.
+    str.reserve(bytes.len() / 3 * 4 + chunk_count * 32 + control.len());
     for (i, chunk) in chunks.enumerate() {
-        payload = general_purpose::STANDARD.encode(chunk);
-        match i {
+        // Writing to a String cannot fail.
+        let _ = match i {
             0 => {
-                // Transmit and virtual-place but keep sending chunks
+                // Transmit but keep sending chunks
                 let more = if chunk_count > 1 { 1 } else { 0 };
-                str.push_str(&format!(
-                    "\x1b_Gq=2,i={id},a=T,U=1,f=24,t=d,s={w},v={h},m={more};{payload}\x1b\\"
-                ));
-            }
-            n if n + 1 == chunk_count => {
-                // m=0 means over
-                str.push_str(&format!("\x1b_Gq=2,i={id},m=0;{payload}\x1b\\"));
-            }
-            _ => {
-                // Keep adding chunks
-                str.push_str(&format!("\x1b_Gq=2,i={id},m=1;{payload}\x1b\\"));
+                write!(
+                    str,
+                    "\x1b_Gq=2,i={id},{control},f={format}{compression},m={more};"
+                )
             }
-        }
+            // m=0 means over
+            n if n + 1 == chunk_count => write!(str, "\x1b_Gq=2,i={id},m=0;"),
+            // Keep adding chunks
+            _ => write!(str, "\x1b_Gq=2,i={id},m=1;"),
+        };
+        general_purpose::STANDARD.encode_string(chunk, str);
+        str.push_str("\x1b\\");
     }
-    str
+}
+
+/// Compress with zlib (RFC 1950), as expected by kitty for `o=z`.
+fn zlib(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::with_capacity(bytes.len() / 4), Compression::fast());
+    encoder.write_all(bytes).ok()?;
+    encoder.finish().ok()
 }
 
 fn add_placeholder(str: &mut String, x: u16, y: u16) {
@@ -495,3 +1136,391 @@ fn diacritic(y: u16) -> char {
         DIACRITICS[y as usize]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, time::Duration};
+
+    use image::{Delay, DynamicImage, Frame, Frames, ImageBuffer, ImageOutputFormat, Rgb, Rgba};
+    use ratatui::{buffer::Buffer, layout::Rect, style::Color};
+
+    use std::io::Read;
+
+    use base64::{engine::general_purpose, Engine};
+    use flate2::read::ZlibDecoder;
+
+    use super::{
+        next_id, transmit, transmit_virtual, Kitty, KittyOptions, PendingDeletes, StatefulKitty,
+    };
+    use crate::{
+        protocol::{Encoded, FixedBlock, ImageSource, Protocol, StatefulProtocol},
+        Resize,
+    };
+
+    fn transmitted(image: &DynamicImage, compress: bool) -> String {
+        let mut data = String::new();
+        transmit(image, 1, "a=T", compress, &mut data);
+        data
+    }
+
+    fn transmitted_virtual(
+        image: &DynamicImage,
+        original: Option<Encoded>,
+        options: &KittyOptions,
+    ) -> String {
+        let mut data = String::new();
+        transmit_virtual(image, original, 1, options, &mut data);
+        data
+    }
+
+    #[test]
+    fn compressed() {
+        let image: DynamicImage = ImageBuffer::from_pixel(100, 100, Rgb([255u8, 0, 0])).into();
+        let raw = transmitted(&image, false);
+        let compressed = transmitted(&image, true);
+        assert!(!raw.contains("o=z"));
+        assert!(compressed.starts_with("\x1b_Gq=2,i=1,a=T,f=24,o=z,m=0;"));
+        assert!(compressed.len() * 10 < raw.len());
+
+        let payload = &compressed[compressed.find(';').unwrap() + 1..compressed.len() - 2];
+        let zlib = general_purpose::STANDARD.decode(payload).unwrap();
+        let mut data = Vec::new();
+        ZlibDecoder::new(&zlib[..]).read_to_end(&mut data).unwrap();
+        assert_eq!(image.to_rgb8().into_raw(), data);
+
+        // Compression only adds overhead to a single pixel.
+        let pixel: DynamicImage = ImageBuffer::from_pixel(1, 1, Rgb([255u8, 0, 0])).into();
+        assert_eq!(transmitted(&pixel, false), transmitted(&pixel, true));
+    }
+
+    #[test]
+    fn original_png() {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 4, Rgb([255u8, 0, 0])).into();
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+        let source = ImageSource::from_reader(Cursor::new(&png), (1, 1)).unwrap();
+
+        let original = source.original_of(&source.image);
+        let compress = KittyOptions {
+            compress: true,
+            ..KittyOptions::default()
+        };
+        let data = transmitted_virtual(&source.image, original, &compress);
+        assert!(data.starts_with("\x1b_Gq=2,i=1,a=T,U=1,t=d,s=4,v=4,f=100,m=0;"));
+        assert!(data.contains(&general_purpose::STANDARD.encode(&png)));
+
+        let data = transmitted_virtual(&source.image, None, &KittyOptions::default());
+        assert!(data.starts_with("\x1b_Gq=2,i=1,a=T,U=1,t=d,s=4,v=4,f=24,m=0;"));
+    }
+
+    #[test]
+    fn z_index() {
+        let image: DynamicImage = ImageBuffer::from_pixel(1, 1, Rgb([255u8, 0, 0])).into();
+        let options = KittyOptions {
+            z_index: -2,
+            ..KittyOptions::default()
+        };
+        let data = transmitted_virtual(&image, None, &options);
+        assert!(data.starts_with("\x1b_Gq=2,i=1,a=T,U=1,t=d,s=1,v=1,z=-2,f=24,m=0;"));
+    }
+
+    #[test]
+    fn delete_on_drop() {
+        let image: DynamicImage = ImageBuffer::from_pixel(1, 1, Rgb([255u8, 0, 0])).into();
+        let source = ImageSource::new(image, (1, 1));
+        let area = Rect::new(0, 0, 1, 1);
+
+        let deletes = PendingDeletes::new();
+        let kitty = StatefulKitty::new(source.clone(), 42).with_pending_deletes(deletes.clone());
+        let clone = kitty.clone();
+        drop(kitty);
+        let mut other = StatefulKitty::new(source.clone(), 43).with_pending_deletes(deletes);
+        let mut buf = Buffer::empty(area);
+        other.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        assert!(!buf.get(0, 0).symbol.contains("a=d,d=I,i=42"));
+
+        drop(clone);
+        let mut buf = Buffer::empty(area);
+        other.render(area, &mut buf);
+        assert!(buf
+            .get(0, 0)
+            .symbol
+            .starts_with("\x1b_Gq=2,a=d,d=I,i=42\x1b\\"));
+
+        // Not written out by protocols with another queue.
+        let mut another =
+            StatefulKitty::new(source, 47).with_pending_deletes(PendingDeletes::new());
+        drop(other);
+        let mut buf = Buffer::empty(area);
+        another.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        assert!(!buf.get(0, 0).symbol.contains("a=d"));
+    }
+
+    #[test]
+    fn write_to() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 1, Rgb([255u8, 0, 0])).into();
+        let source = ImageSource::new(image, (1, 1));
+        let kitty = Kitty::from_source(&source, Resize::Fit(None), None, source.desired, 0x010203)
+            .unwrap()
+            .with_pending_deletes(PendingDeletes::new());
+
+        let mut out = Vec::new();
+        kitty.write_to(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\x1b_Gq=2,i=66051,a=T,U=1"));
+        assert!(out.ends_with(
+            "\x1b[38;2;1;2;3m\u{10EEEE}\u{0305}\u{0305}\u{10EEEE}\u{0305}\u{030D}\x1b[0m\n"
+        ));
+
+        // Also with the placeholders through FixedBlock.
+        let mut fixed_out = Vec::new();
+        FixedBlock::from(kitty.clone())
+            .write_to(&mut fixed_out)
+            .unwrap();
+        assert_eq!(out, String::from_utf8(fixed_out).unwrap());
+    }
+
+    #[test]
+    fn clipped_to_buffer() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgb([255u8, 0, 0])).into();
+        let source = ImageSource::new(image, (1, 1));
+        let mut kitty = StatefulKitty::new(source, 8).with_pending_deletes(PendingDeletes::new());
+        // The top row of the image is scrolled out of the buffer.
+        let area = Rect::new(0, 1, 2, 2);
+        let mut buf = Buffer::empty(Rect::new(0, 2, 2, 2));
+        kitty.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+
+        let first = &buf.get(0, 2).symbol;
+        assert!(first.contains("a=T,U=1"));
+        assert!(first.contains(&kitty.encoded()));
+        assert!(first.ends_with("\u{10EEEE}\u{030D}\u{0305}"));
+        assert_eq!("\u{10EEEE}\u{030D}\u{030D}", buf.get(1, 2).symbol);
+        assert_eq!(" ", buf.get(0, 3).symbol);
+    }
+
+    #[test]
+    fn placement() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 1, Rgb([255u8, 0, 0])).into();
+        let source = ImageSource::new(image, (1, 1));
+        let area = Rect::new(0, 0, 2, 1);
+        let deletes = PendingDeletes::new();
+        let kitty = Kitty::from_source(&source, Resize::Fit(None), None, area, 0x0A0B0C)
+            .unwrap()
+            .with_pending_deletes(deletes.clone());
+        let placement = kitty.placement();
+        assert_eq!(kitty.rect(), placement.rect());
+        assert_eq!("", placement.encoded());
+
+        let mut buf = Buffer::empty(Rect::new(0, 0, 2, 2));
+        kitty.render(area, &mut buf);
+        placement.render(Rect::new(0, 1, 2, 1), &mut buf);
+        assert!(buf.get(0, 0).symbol.contains("a=T,U=1"));
+        assert!(buf.get(0, 1).symbol.ends_with("\u{10EEEE}\u{305}\u{305}"));
+        assert!(!buf.get(0, 1).symbol.contains("a=T"));
+        assert_eq!(Color::Rgb(10, 11, 12), buf.get(1, 1).fg);
+
+        // The image is only deleted once the placement is dropped too.
+        drop(kitty);
+        assert_eq!("", deletes.take());
+        drop(placement);
+        assert_eq!("\x1b_Gq=2,a=d,d=I,i=658188\x1b\\", deletes.take());
+    }
+
+    #[test]
+    fn stateful_placement() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 1, Rgb([255u8, 0, 0])).into();
+        let area = Rect::new(0, 0, 2, 1);
+        let deletes = PendingDeletes::new();
+        let mut kitty = StatefulKitty::new(ImageSource::new(image, (1, 1)), 0x0A0B0D)
+            .with_pending_deletes(deletes.clone());
+        let mut buf = Buffer::empty(Rect::new(0, 0, 4, 2));
+        kitty.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+
+        let mut placement = kitty.placement();
+        assert_eq!(kitty.rect(), placement.rect());
+        assert_eq!("", placement.encoded());
+        // Never resizes by itself.
+        let other = Rect::new(0, 1, 4, 1);
+        assert_eq!(None, placement.needs_resize(&Resize::Fit(None), other));
+        placement.resize_encode_render(&Resize::Fit(None), None, other, &mut buf);
+        assert!(buf.get(0, 1).symbol.starts_with('\u{10EEEE}'));
+        assert_eq!(Color::Rgb(10, 11, 13), buf.get(1, 1).fg);
+        assert_eq!(" ", buf.get(2, 1).symbol);
+
+        drop(kitty);
+        assert_eq!("", deletes.take());
+        drop(placement);
+        assert_eq!("\x1b_Gq=2,a=d,d=I,i=658189\x1b\\", deletes.take());
+    }
+
+    #[test]
+    fn unicode_placeholders() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgb([255u8, 0, 0])).into();
+        let source = ImageSource::new(image, (1, 1));
+        let area = Rect::new(0, 0, 2, 2);
+        let mut kitty = StatefulKitty::new(source, 7).with_pending_deletes(PendingDeletes::new());
+        let mut buf = Buffer::empty(area);
+        kitty.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+
+        // Each cell holds its own placeholder, the first one also the transmit data.
+        let first = &buf.get(0, 0).symbol;
+        assert!(first.contains("a=T,U=1"));
+        assert!(first.ends_with("\x1b\\\u{10EEEE}\u{305}\u{305}"));
+        assert_eq!("\u{10EEEE}\u{305}\u{30D}", buf.get(1, 0).symbol);
+        assert_eq!("\u{10EEEE}\u{30D}\u{305}", buf.get(0, 1).symbol);
+        assert_eq!("\u{10EEEE}\u{30D}\u{30D}", buf.get(1, 1).symbol);
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let cell = buf.get(x, y);
+            assert_eq!(Color::Rgb(0, 0, 7), cell.fg);
+            assert!(!cell.skip);
+        }
+    }
+
+    #[test]
+    fn animated() {
+        let frames = [Rgba([255u8, 0, 0, 255]), Rgba([0, 0, 255, 255])].map(|color| {
+            Ok(Frame::from_parts(
+                ImageBuffer::from_pixel(1, 1, color),
+                0,
+                0,
+                Delay::from_saturating_duration(Duration::from_millis(100)),
+            ))
+        });
+        let source = ImageSource::from_frames(Frames::new(Box::new(frames.into_iter())), (1, 1))
+            .expect("frames");
+        let area = Rect::new(0, 0, 1, 1);
+        let mut kitty =
+            StatefulKitty::new_animated(source, 9).with_pending_deletes(PendingDeletes::new());
+        assert_eq!(None, kitty.frame_delay());
+
+        let mut buf = Buffer::empty(area);
+        kitty.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        let symbol = &buf.get(0, 0).symbol;
+        assert!(symbol.contains("a=T,U=1"));
+        assert!(symbol.contains("a=f,t=d,s=1,v=1,z=100,f=32"));
+        assert!(symbol.contains("\x1b_Gq=2,a=a,i=9,r=1,z=100\x1b\\"));
+        assert!(symbol.contains("\x1b_Gq=2,a=a,i=9,s=3,v=1\x1b\\"));
+
+        kitty.advance_frame();
+        let mut buf = Buffer::empty(area);
+        kitty.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        assert!(buf
+            .get(0, 0)
+            .symbol
+            .starts_with("\x1b_Gq=2,a=a,i=9,c=2\x1b\\"));
+
+        kitty.pause();
+        assert!(kitty.is_paused());
+        kitty.pause();
+        let mut buf = Buffer::empty(area);
+        kitty.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        assert!(buf
+            .get(0, 0)
+            .symbol
+            .ends_with("\x1b_Gq=2,a=a,i=9,s=1\x1b\\\u{10EEEE}\u{0305}\u{0305}"));
+
+        // Stays paused when transmitted again.
+        kitty.delete();
+        let mut buf = Buffer::empty(area);
+        kitty.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        assert!(buf
+            .get(0, 0)
+            .symbol
+            .contains("\x1b_Gq=2,a=a,i=9,s=3,v=1\x1b\\\x1b_Gq=2,a=a,i=9,s=1\x1b\\"));
+
+        kitty.play();
+        assert!(!kitty.is_paused());
+        let mut buf = Buffer::empty(area);
+        kitty.render(area, &mut buf);
+        assert!(buf
+            .get(0, 0)
+            .symbol
+            .contains("\x1b_Gq=2,a=a,i=9,s=3,v=1\x1b\\"));
+    }
+
+    #[test]
+    fn unique_ids() {
+        let (a, b) = (next_id(), next_id());
+        assert_ne!(a, b);
+        assert_ne!(0, a);
+        assert_ne!(0, b);
+    }
+
+    #[test]
+    fn delete() {
+        let image: DynamicImage = ImageBuffer::from_pixel(1, 1, Rgb([255u8, 0, 0])).into();
+        let source = ImageSource::new(image, (1, 1));
+        let area = Rect::new(0, 0, 1, 1);
+        let deletes = PendingDeletes::new();
+        let mut kitty = StatefulKitty::new(source, 44).with_pending_deletes(deletes.clone());
+        let mut buf = Buffer::empty(area);
+        kitty.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+
+        kitty.delete();
+        let mut buf = Buffer::empty(area);
+        kitty.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        let symbol = &buf.get(0, 0).symbol;
+        let delete = "\x1b_Gq=2,a=d,d=I,i=44\x1b\\";
+        assert!(symbol.starts_with(delete));
+        assert!(symbol.contains("a=T,U=1"));
+
+        // Deleted only once, and not again when dropped.
+        kitty.delete();
+        kitty.delete();
+        drop(kitty);
+        assert_eq!(delete, deletes.take());
+    }
+
+    #[test]
+    fn clear_cells() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 1, Rgb([255u8, 0, 0])).into();
+        let source = ImageSource::new(image, (1, 1));
+        let area = Rect::new(0, 0, 2, 1);
+        let mut kitty = StatefulKitty::new(source, 45).with_pending_deletes(PendingDeletes::new());
+        let mut buf = Buffer::empty(area);
+        kitty.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+
+        kitty.clear(area, &mut buf);
+        assert!(buf
+            .get(0, 0)
+            .symbol
+            .ends_with("\x1b_Gq=2,a=d,d=I,i=45\x1b\\ "));
+        assert_eq!(Color::Reset, buf.get(0, 0).fg);
+        assert_eq!(" ", buf.get(1, 0).symbol);
+        assert_eq!(Rect::default(), kitty.rect());
+    }
+
+    #[test]
+    fn passthrough() {
+        let seq = "\x1b_Gq=2,a=d,d=I,i=1\x1b\\\x1b_Ga=T,m=1;AAAA\x1b\\";
+        assert_eq!(seq, super::passthrough(seq, false));
+        assert_eq!(
+            concat!(
+                "\x1bPtmux;\x1b\x1b_Gq=2,a=d,d=I,i=1\x1b\x1b\\\x1b\\",
+                "\x1bPtmux;\x1b\x1b_Ga=T,m=1;AAAA\x1b\x1b\\\x1b\\",
+            ),
+            super::passthrough(seq, true)
+        );
+    }
+
+    #[test]
+    fn tmux() {
+        let image: DynamicImage = ImageBuffer::from_pixel(1, 1, Rgb([255u8, 0, 0])).into();
+        let area = Rect::new(0, 0, 1, 1);
+        let mut kitty = StatefulKitty::new(ImageSource::new(image, (1, 1)), 46)
+            .with_pending_deletes(PendingDeletes::new());
+        kitty.set_options(KittyOptions {
+            tmux: true,
+            ..KittyOptions::default()
+        });
+        let mut buf = Buffer::empty(area);
+        kitty.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        let symbol = &buf.get(0, 0).symbol;
+        assert!(symbol.starts_with("\x1bPtmux;\x1b\x1b_G"));
+        assert!(symbol.contains("\x1bPtmux;\x1b\x1b_Gq=2,i=46,a=T,U=1"));
+        // The placeholder is not wrapped.
+        assert!(symbol.ends_with("\x1b\\\u{10EEEE}\u{0305}\u{0305}"));
+    }
+}
@@ -1,22 +1,46 @@
 //! Protocol backends for the widgets
+//!
+//! # Cell encoding
+//!
+//! Protocols render into the cells of a ratatui [Buffer] like any other widget, so that a
+//! `TestBackend` buffer can be asserted on:
+//!
+//! * Sixel and iTerm2 put the whole escape sequence into the symbol of the top left cell of the
+//!   image, and mark all other cells of the image as skipped, so that ratatui does not draw over
+//!   the image. The sequence may be prefixed with erasing the cells, see
+//!   [crate::StatefulImage::overwrite].
+//! * Kitty puts a unicode placeholder into the symbol of each cell, with the image id as the
+//!   foreground color. The top left visible cell's symbol is prefixed with the escape sequences
+//!   that delete dropped images and transmit the image, if any.
+//! * Halfblocks, braille, blank, and text write no escape sequences into the cells, only
+//!   characters and their colors.
+//!
+//! [escape_sequences] extracts the escape sequences from a region of a buffer.
 
 use std::{
     collections::hash_map::DefaultHasher,
     hash::{Hash, Hasher},
+    io::{self, Cursor, Read, Seek, Write},
+    sync::Arc,
+    time::Duration,
 };
 
 use dyn_clone::DynClone;
-use image::{DynamicImage, Rgb};
+use image::{imageops, DynamicImage, Frames, ImageBuffer, ImageFormat, Rgb};
 use ratatui::{buffer::Buffer, layout::Rect};
 
-use crate::FontSize;
+use crate::{FontSize, Result};
 
 use super::Resize;
 
+pub mod animated;
+pub mod blank;
+pub mod braille;
 pub mod halfblocks;
 pub mod iterm2;
 pub mod kitty;
 pub mod sixel;
+pub mod text;
 
 /// A fixed image protocol for the [crate::Image] widget.
 pub trait Protocol: Send + Sync {
@@ -24,6 +48,35 @@ pub trait Protocol: Send + Sync {
     fn render(&self, area: Rect, buf: &mut Buffer);
     /// Get the [ratatui::layout::Rect] of the image.
     fn rect(&self) -> Rect;
+    /// Get the encoded escape sequence, without rendering to a buffer.
+    ///
+    /// For kitty, sixel, and iTerm2 this is the graphics protocol sequence. For halfblocks, this
+    /// is one line of colored `▀` characters per cell row.
+    ///
+    /// Useful for logging, or snapshot testing the output across terminals.
+    fn encoded(&self) -> String;
+    /// Write the image to `out`, e.g. stdout, without a ratatui [Buffer] or terminal.
+    ///
+    /// The image is drawn at the cursor position, so the caller is responsible for moving the
+    /// cursor beforehand. Text-based protocols like halfblocks write one line per cell row, see
+    /// [Protocol::encoded].
+    ///
+    /// # Example
+    /// Print a thumbnail in a CLI, without a TUI:
+    /// ```rust,no_run
+    /// use ratatui::layout::Rect;
+    /// use ratatui_image::{picker::Picker, protocol::Protocol, Resize};
+    ///
+    /// let mut picker = Picker::new((8, 16));
+    /// picker.guess_protocol();
+    /// let image = image::open("./assets/Ada.png")?;
+    /// let thumbnail = picker.new_protocol(image, Rect::new(0, 0, 20, 10), Resize::Fit(None))?;
+    /// thumbnail.write_to(&mut std::io::stdout())?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    fn write_to(&self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(self.encoded().as_bytes())
+    }
 }
 
 /// A stateful resizing image protocol for the [crate::StatefulImage] widget.
@@ -47,23 +100,376 @@ pub trait StatefulProtocol: Send + Sync + DynClone {
     /// Check if the current image state would need resizing (grow or shrink) for the given area.
     ///
     /// This can be called by the UI thread to check if this [StatefulProtocol] should be sent off
-    /// toprotoco
-    /// some background thread/task to do the resizing and encoding, instead of rendering. The
-    /// thread should then return the [StatefulProtocol] so that it can be rendered.protoco
+    /// to some background thread/task to do the resizing and encoding, instead of rendering. The
+    /// thread should then return the [StatefulProtocol] so that it can be rendered.
     fn needs_resize(&mut self, resize: &Resize, area: Rect) -> Option<Rect>;
 
+    /// Whether the last encode matches the area, i.e. rendering would not resize and encode again.
+    ///
+    /// Useful to render a loading indicator instead, while a resize and encode is pending, e.g. on
+    /// a [crate::thread::ResizeWorker].
+    fn is_current(&mut self, resize: &Resize, area: Rect) -> bool {
+        self.needs_resize(resize, area).is_none()
+    }
+
+    /// Whether the last encode does not match the area anymore, the opposite of
+    /// [StatefulProtocol::is_current].
+    ///
+    /// There is no `is_encoding`, because the protocol is moved to the thread that encodes it,
+    /// e.g. with [crate::thread::ResizeWorker::request], so the UI thread should render a
+    /// placeholder such as a spinner until it gets the protocol back.
+    fn is_stale(&mut self, resize: &Resize, area: Rect) -> bool {
+        !self.is_current(resize, area)
+    }
+
+    /// Resize and encode ahead of time for an area that is going to be rendered later, e.g. the
+    /// next image of a slideshow while the current one is shown.
+    ///
+    /// Rendering `area` with the same `resize` afterwards does not resize and encode again. Does
+    /// nothing if the last encode already matches `area`.
+    fn warm(&mut self, resize: &Resize, background_color: Option<Rgb<u8>>, area: Rect) {
+        if let Some(rect) = self.needs_resize(resize, area) {
+            self.resize_encode(resize, background_color, rect);
+        }
+    }
+
     /// Resize the image and encode it for rendering. The result should be stored statefully so
     /// that next call for the given area does not need to redo the work.
     ///
     /// This can be done in a background thread, and the result is stored in this [StatefulProtocol].
-    fn resize_encode(&mut self, resize: &Resize, background_color: Option<Rgb<u8>>, area: Rect);
+    /// Same as [StatefulProtocol::resize] followed by [StatefulProtocol::encode].
+    ///
+    /// Implementations override either this, or [StatefulProtocol::resize] and
+    /// [StatefulProtocol::encode].
+    fn resize_encode(&mut self, resize: &Resize, background_color: Option<Rgb<u8>>, area: Rect) {
+        if let Some(resized) = self.resize(resize, background_color, area) {
+            self.encode(resized);
+        }
+    }
+
+    /// Resize the image for the given area, without encoding it, or `None` if the current encode
+    /// already matches the area.
+    ///
+    /// Together with [StatefulProtocol::encode], this splits [StatefulProtocol::resize_encode] for
+    /// finer control over threading, e.g. resize ahead of time on a worker thread once the area is
+    /// known, and only encode when rendering.
+    ///
+    /// The default returns `None`, for implementations that only override
+    /// [StatefulProtocol::resize_encode], which must then be used instead.
+    fn resize(
+        &self,
+        _resize: &Resize,
+        _background_color: Option<Rgb<u8>>,
+        _area: Rect,
+    ) -> Option<ResizedImage> {
+        None
+    }
+
+    /// Encode an image from [StatefulProtocol::resize], and store the result for rendering.
+    ///
+    /// The built-in protocols encode into the allocation of the previous encode. The resized image
+    /// itself is always allocated anew by [StatefulProtocol::resize].
+    ///
+    /// The default does nothing, see [StatefulProtocol::resize].
+    fn encode(&mut self, _resized: ResizedImage) {}
 
     /// Render the currently resized and encoded data to the buffer.
     fn render(&mut self, area: Rect, buf: &mut Buffer);
+
+    /// The rect of the currently encoded image, relative to the render area.
+    ///
+    /// Useful to compute the space left around the image after
+    /// [StatefulProtocol::resize_encode], e.g. to draw a border that tightly wraps a
+    /// [Resize::Fit] image. Note that [crate::StatefulImage] may still move it within the area,
+    /// see [crate::StatefulImage::alignment]. Empty before the first encode.
+    ///
+    /// With [Resize::FitWidth] or [Resize::FitHeight] this may be larger than the render area.
+    ///
+    /// The default is always empty, for implementations that do not track their rect, which
+    /// [StatefulProtocol::clear] then cannot clear either.
+    fn rect(&self) -> Rect {
+        Rect::default()
+    }
+
+    /// Get the escape sequence of the last encode, see [Protocol::encoded].
+    ///
+    /// Empty if nothing was encoded yet. For kitty, this is the transmit sequence, even after it
+    /// has been rendered. The default is always empty.
+    fn encoded(&self) -> String {
+        String::new()
+    }
+
+    /// Replace the image, e.g. with the next image of a slideshow, keeping the protocol state.
+    ///
+    /// The encoded data is only reused if the new image is identical to the current one, as
+    /// determined by [ImageSource::hash]. Otherwise the next
+    /// [StatefulProtocol::resize_encode_render] re-encodes it, even if the size is the same.
+    ///
+    /// The default does nothing, for implementations that cannot replace their image.
+    fn set_source(&mut self, _source: ImageSource) {}
+
+    /// Advance to the next frame of an animated image, wrapping around to the first frame after
+    /// the last one.
+    ///
+    /// The encoded data becomes stale, so that the next [StatefulProtocol::resize_encode_render]
+    /// re-encodes the new frame. Does nothing for still images, and by default.
+    fn advance_frame(&mut self) {}
+
+    /// The delay of the current frame of an animated image, or `None` for still images and images
+    /// that the terminal animates by itself.
+    ///
+    /// The caller is responsible for timing, e.g. call [StatefulProtocol::advance_frame] from the
+    /// event loop once this delay has elapsed. Defaults to `None`.
+    fn frame_delay(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Wipe the image from the terminal, where it was rendered into `area` of `buf`.
+    ///
+    /// Call this in the frame in which the image goes away, before rendering whatever replaces it
+    /// into `area`. The cells of the image are reset, so that ratatui draws them again. Sixel and
+    /// iTerm2 images are not made of the cells, so they are also erased with the escape sequence
+    /// in the top left cell, and kitty images are deleted from the terminal.
+    ///
+    /// Rendering the protocol again shows the image again.
+    fn clear(&mut self, area: Rect, buf: &mut Buffer) {
+        clear_cells(self.rect(), area, buf);
+    }
 }
 
 dyn_clone::clone_trait_object!(StatefulProtocol);
 
+/// An image resized by [StatefulProtocol::resize], to be encoded with [StatefulProtocol::encode].
+#[derive(Clone)]
+pub struct ResizedImage {
+    /// The resized image.
+    pub image: DynamicImage,
+    /// The rect of the image, see [StatefulProtocol::rect].
+    pub rect: Rect,
+    resize: Resize,
+    background_color: Option<Rgb<u8>>,
+    /// The [ImageSource::hash] of the image before resizing, see [ImageSource::resize_hash].
+    hash: u64,
+    /// The original data, if resizing did not change the image, see [ImageSource::original].
+    original: Option<Encoded>,
+}
+
+impl ResizedImage {
+    /// Resize `source` for `area`, unless `current` is the rect of the encoded image and the
+    /// resize is not `force`d, e.g. because the source or the resize changed.
+    fn new(
+        source: &ImageSource,
+        current: Rect,
+        force: bool,
+        resize: &Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+    ) -> Option<ResizedImage> {
+        if area.width == 0 || area.height == 0 {
+            return None;
+        }
+
+        let (image, rect) = resize.resize(source, current, area, background_color, force)?;
+        Some(ResizedImage {
+            original: source.original_of(&image),
+            image,
+            rect,
+            resize: *resize,
+            background_color,
+            hash: source.resize_hash(resize),
+        })
+    }
+
+    /// Like [ResizedImage::new], but only computes the rect and leaves the image empty, for
+    /// protocols that never draw the pixels, like [blank] and [text].
+    pub(super) fn rect_only(
+        source: &ImageSource,
+        current: Rect,
+        force: bool,
+        resize: &Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+    ) -> Option<ResizedImage> {
+        if area.width == 0 || area.height == 0 {
+            return None;
+        }
+
+        let rect = resize.needs_resize(source, current, area, force)?;
+        Some(ResizedImage {
+            image: DynamicImage::new_rgb8(0, 0),
+            rect,
+            resize: *resize,
+            background_color,
+            hash: source.resize_hash(resize),
+            original: None,
+        })
+    }
+}
+
+/// Encoded image data, see [ImageSource::original].
+#[derive(Clone)]
+struct Encoded {
+    data: Arc<[u8]>,
+    format: ImageFormat,
+}
+
+/// The part of `area` that is inside the buffer, or `None` if none of it is.
+///
+/// Images that are partially outside of the buffer, e.g. in a scrolled viewport, are clipped to
+/// the buffer.
+fn clip(buf: &Buffer, area: Rect) -> Option<Rect> {
+    let buf_area = *buf.area();
+    if area.width == 0 || area.height == 0 || !area.intersects(buf_area) {
+        return None;
+    }
+    Some(area.intersection(buf_area))
+}
+
+/// The cells of an image that are inside the render `area` and `buf`.
+///
+/// `rect` is the image's rect relative to the render `area`, see [StatefulProtocol::rect].
+fn visible_area(rect: Rect, area: Rect, buf: &Buffer) -> Option<Rect> {
+    let image = Rect::new(
+        area.x + rect.x,
+        area.y + rect.y,
+        rect.width.min(area.width.saturating_sub(rect.x)),
+        rect.height.min(area.height.saturating_sub(rect.y)),
+    );
+    clip(buf, image)
+}
+
+/// An "erase characters" (`ECH`) for each row of `visible`, restoring the cursor afterwards.
+fn erase_sequence(visible: Rect) -> String {
+    let rows: Vec<String> = (0..visible.height)
+        .map(|_| format!("\x1b[{}X", visible.width))
+        .collect();
+    format!("\x1b7{}\x1b8", rows.join("\x1b[1B"))
+}
+
+/// Erase the cells of an image in the terminal before it is drawn, by prefixing the escape
+/// sequence in its top left visible cell with an "erase characters" (`ECH`) for each row.
+///
+/// `rect` is the image's rect relative to the render `area`, see [StatefulProtocol::rect].
+pub(crate) fn erase(rect: Rect, area: Rect, buf: &mut Buffer) {
+    let Some(visible) = visible_area(rect, area, buf) else {
+        return;
+    };
+    let cell = buf.get_mut(visible.x, visible.y);
+    // Nothing was drawn, e.g. because the image did not fit.
+    if !cell.symbol.starts_with('\x1b') {
+        return;
+    }
+    let symbol = format!("{}{}", erase_sequence(visible), cell.symbol);
+    cell.set_symbol(&symbol);
+}
+
+/// Reset the visible cells of an image, so that ratatui draws them again, and return them.
+///
+/// `rect` is the image's rect relative to the render `area`, see [StatefulProtocol::rect].
+pub(crate) fn clear_cells(rect: Rect, area: Rect, buf: &mut Buffer) -> Option<Rect> {
+    let visible = visible_area(rect, area, buf)?;
+    for y in visible.top()..visible.bottom() {
+        for x in visible.left()..visible.right() {
+            buf.get_mut(x, y).reset();
+        }
+    }
+    Some(visible)
+}
+
+/// Reset the visible cells of an image like [clear_cells], and also erase them in the terminal,
+/// for protocols whose image is not made of the cells themselves, i.e. sixel and iTerm2.
+pub(crate) fn clear_erase(rect: Rect, area: Rect, buf: &mut Buffer) {
+    if let Some(visible) = clear_cells(rect, area, buf) {
+        let symbol = format!("{} ", erase_sequence(visible));
+        buf.get_mut(visible.x, visible.y).set_symbol(&symbol);
+    }
+}
+
+/// The symbols of the cells in `area` of `buf` that start with an escape sequence, in row-major
+/// order, see [cell encoding](self#cell-encoding).
+///
+/// For sixel and iTerm2 this is the whole escape sequence of the image, and for kitty the escape
+/// sequences of the first cell, followed by its placeholder. For the text-based protocols, this is
+/// empty.
+///
+/// # Example
+/// ```rust
+/// use image::{DynamicImage, ImageBuffer, Rgb};
+/// use ratatui::{buffer::Buffer, layout::Rect};
+/// use ratatui_image::{
+///     protocol::{escape_sequences, sixel::Sixel, ImageSource, Protocol},
+///     Resize,
+/// };
+///
+/// let image: DynamicImage = ImageBuffer::from_pixel(8, 16, Rgb([255u8, 0, 0])).into();
+/// let source = ImageSource::new(image, (8, 16));
+/// let area = Rect::new(0, 0, 4, 4);
+/// let sixel = Sixel::from_source(&source, Resize::Fit(None), None, false, area)?;
+/// let mut buf = Buffer::empty(area);
+/// sixel.render(area, &mut buf);
+/// assert_eq!(sixel.encoded(), escape_sequences(&buf, area));
+/// # Ok::<(), ratatui_image::errors::Errors>(())
+/// ```
+pub fn escape_sequences(buf: &Buffer, area: Rect) -> String {
+    let Some(area) = clip(buf, area) else {
+        return String::new();
+    };
+    let mut sequences = String::new();
+    for y in area.top()..area.bottom() {
+        for x in area.left()..area.right() {
+            let cell = buf.get(x, y);
+            if cell.symbol.starts_with('\x1b') {
+                sequences.push_str(&cell.symbol);
+            }
+        }
+    }
+    sequences
+}
+
+/// Whether the cell at `(x, y)` is inside the buffer, see [clip].
+fn in_buffer(buf: &Buffer, x: u16, y: u16) -> bool {
+    clip(buf, Rect::new(x, y, 1, 1)).is_some()
+}
+
+/// Alpha-composite an image over the background color, for protocols that cannot show
+/// transparency. Without a background color, the terminal's background is unknown, and black is
+/// assumed.
+///
+/// Images without an alpha channel are returned as they are.
+fn composite(image: DynamicImage, background_color: Option<Rgb<u8>>) -> DynamicImage {
+    if !image.color().has_alpha() {
+        return image;
+    }
+    let background = background_color.unwrap_or(Rgb([0, 0, 0]));
+    let image = image.into_rgba8();
+    ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let blend =
+            |c: u8, bg: u8| ((c as u32 * a as u32 + bg as u32 * (255 - a as u32)) / 255) as u8;
+        Rgb([
+            blend(r, background[0]),
+            blend(g, background[1]),
+            blend(b, background[2]),
+        ])
+    })
+    .into()
+}
+
+/// Convert an image to 8 bits per channel, keeping the alpha channel if there is one, and keeping
+/// grayscale images single-channel.
+fn to_8bit(image: DynamicImage) -> DynamicImage {
+    match image {
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageRgb8(_)
+        | DynamicImage::ImageRgba8(_) => image,
+        DynamicImage::ImageLuma16(_) => DynamicImage::ImageLuma8(image.into_luma8()),
+        DynamicImage::ImageLumaA16(_) => DynamicImage::ImageLumaA8(image.into_luma_alpha8()),
+        image if image.color().has_alpha() => DynamicImage::ImageRgba8(image.into_rgba8()),
+        image => DynamicImage::ImageRgb8(image.into_rgb8()),
+    }
+}
+
 #[derive(Clone)]
 /// Image source for [crate::protocol::StatefulProtocol]s
 ///
@@ -87,35 +493,412 @@ pub struct ImageSource {
     pub font_size: FontSize,
     /// The area that the [`ImageSource::image`] covers, but not necessarily fills.
     pub desired: Rect,
-    /// TODO: document this; when image changes but it doesn't need a resize, force a render.
+    /// Hash of the image data.
+    ///
+    /// A [StatefulProtocol] stores the hash of the image it last encoded, and re-encodes when it
+    /// differs, even if the image does not need to be resized.
     pub hash: u64,
+    /// The frames of an animated image and their delays, empty for still images.
+    ///
+    /// Shared between clones, e.g. of a [animated::StatefulAnimated] that caches one protocol per
+    /// frame.
+    frames: Arc<[(DynamicImage, Duration)]>,
+    /// The data that the image was decoded from, see [ImageSource::original].
+    original: Option<Encoded>,
+    /// The index of the current frame.
+    frame: usize,
+    /// See [ImageSource::with_scale].
+    pub(crate) scale: f32,
 }
 
 impl ImageSource {
     /// Create a new image source
+    ///
+    /// Images with more than 8 bits per channel, e.g. 16-bit PNGs or 10-bit AVIFs, are converted
+    /// to 8 bits, which is all that the protocols can show, so that resizing is not slower than
+    /// necessary.
+    ///
+    /// Grayscale images ([DynamicImage::ImageLuma8] and [DynamicImage::ImageLumaA8], or their
+    /// 16-bit variants) stay single-channel through resizing, which saves memory with large
+    /// images. They are only expanded to gray RGB when encoding for a protocol that needs it, and
+    /// when padding them with a background color.
     pub fn new(image: DynamicImage, font_size: FontSize) -> ImageSource {
+        let image = to_8bit(image);
         let desired =
-            ImageSource::round_pixel_size_to_cells(image.width(), image.height(), font_size);
+            ImageSource::round_pixel_size_to_cells(image.width(), image.height(), font_size, 1.0);
 
-        let mut state = DefaultHasher::new();
-        image.as_bytes().hash(&mut state);
-        let hash = state.finish();
+        let hash = ImageSource::hash_image(&image);
 
         ImageSource {
             image,
             font_size,
             desired,
             hash,
+            frames: Arc::new([]),
+            frame: 0,
+            original: None,
+            scale: 1.0,
+        }
+    }
+
+    /// Multiply the pixels per cell of the font size by `scale` when computing
+    /// [ImageSource::desired], i.e. the native size of the image in cells.
+    ///
+    /// Scales below `1.0` make the image cover more cells, e.g. `0.5` for twice as many columns and
+    /// rows on HiDPI screens where images look tiny, and scales above `1.0` fewer. The image is
+    /// still encoded with the actual [ImageSource::font_size], so it stays sharp.
+    ///
+    /// The scaled size is what [Resize::Fit], [Resize::Crop] and the other resize modes fit into
+    /// the area, so e.g. [Resize::Fit] upscales an image that is scaled below `1.0`, if it fits.
+    /// [Resize::Crop] and [Resize::Pan] crop cells of the scaled image, i.e. the pixels they crop
+    /// are scaled too. Scales that are not positive or not finite are ignored.
+    ///
+    /// # Example
+    /// ```rust
+    /// use image::{DynamicImage, ImageBuffer, Rgb};
+    /// use ratatui_image::protocol::ImageSource;
+    ///
+    /// let image: DynamicImage = ImageBuffer::from_pixel(80, 160, Rgb([0u8, 0, 0])).into();
+    /// let source = ImageSource::new(image, (8, 16));
+    /// assert_eq!((10, 10), (source.desired.width, source.desired.height));
+    /// let source = source.with_scale(0.5);
+    /// assert_eq!((20, 20), (source.desired.width, source.desired.height));
+    /// ```
+    pub fn with_scale(mut self, scale: f32) -> ImageSource {
+        self.scale = ImageSource::valid_scale(scale);
+        self.desired = ImageSource::round_pixel_size_to_cells(
+            self.image.width(),
+            self.image.height(),
+            self.font_size,
+            self.scale,
+        );
+        self
+    }
+
+    /// Decode an image from any reader, e.g. a file or a [std::io::Cursor] over a network buffer,
+    /// guessing the format from its contents.
+    ///
+    /// Of animated images, e.g. animated WebP, only the first frame is decoded, see
+    /// [ImageSource::from_frames] for animations.
+    ///
+    /// The EXIF orientation of JPEG images is applied, see [Rotation::from_exif]. Otherwise, the
+    /// data is kept, see [ImageSource::original].
+    ///
+    /// Returns an error if the format is unknown or the data is corrupt.
+    pub fn from_reader<R: Read + Seek>(mut reader: R, font_size: FontSize) -> Result<ImageSource> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let reader = image::io::Reader::new(Cursor::new(&data)).with_guessed_format()?;
+        let format = reader.format();
+        let image = reader.decode()?;
+        let source = ImageSource::new(image, font_size);
+        Ok(match Rotation::from_exif(&data) {
+            Some(rotation) => source.with_rotation(rotation),
+            None => ImageSource {
+                original: format.map(|format| Encoded {
+                    data: data.into(),
+                    format,
+                }),
+                ..source
+            },
+        })
+    }
+
+    /// The size of the [ImageSource::image] in pixels, `(width, height)`.
+    pub fn pixel_size(&self) -> (u32, u32) {
+        (self.image.width(), self.image.height())
+    }
+
+    /// The width to height ratio of the image in terminal cells, e.g. for a layout
+    /// [Constraint::Ratio](ratatui::layout::Constraint::Ratio).
+    ///
+    /// Since cells are usually about twice as high as wide, this is not the ratio of the
+    /// [ImageSource::pixel_size], but it is corrected by the [ImageSource::font_size]. Unlike
+    /// [ImageSource::desired], it is not rounded to whole cells. Returns `0.0` for empty images.
+    pub fn aspect_ratio(&self) -> f32 {
+        let (width, height) = self.pixel_size();
+        let (font_width, font_height) = self.font_size;
+        let columns = width as f32 / f32::from(font_width);
+        let rows = height as f32 / f32::from(font_height);
+        if rows > 0.0 {
+            columns / rows
+        } else {
+            0.0
+        }
+    }
+
+    /// The encoded data and format that the image was decoded from, if it was created with
+    /// [ImageSource::from_reader] and not rotated.
+    ///
+    /// When the image is shown at its native size, i.e. resizing does not change it, protocols
+    /// that understand the format send this data as it is, instead of encoding the image again:
+    /// iTerm2 with PNG or JPEG, and kitty with PNG.
+    pub fn original(&self) -> Option<(&[u8], ImageFormat)> {
+        self.original
+            .as_ref()
+            .map(|original| (&original.data[..], original.format))
+    }
+
+    /// The original data, if `image` is this source's image unchanged by resizing.
+    fn original_of(&self, image: &DynamicImage) -> Option<Encoded> {
+        let unchanged = image.width() == self.image.width()
+            && image.height() == self.image.height()
+            && image.color() == self.image.color();
+        self.original.clone().filter(|_| unchanged)
+    }
+
+    /// Rotate the image clockwise, including all frames of an animated image.
+    ///
+    /// [ImageSource::desired] and [ImageSource::hash] are recomputed for the rotated image.
+    pub fn with_rotation(mut self, rotation: Rotation) -> ImageSource {
+        self.image = rotation.apply(&self.image);
+        self.original = None;
+        self.frames = self
+            .frames
+            .iter()
+            .map(|(frame, delay)| (rotation.apply(frame), *delay))
+            .collect();
+        self.desired = ImageSource::round_pixel_size_to_cells(
+            self.image.width(),
+            self.image.height(),
+            self.font_size,
+            self.scale,
+        );
+        self.hash = ImageSource::hash_image(&self.image);
+        self
+    }
+
+    /// Mirror the image left to right, including all frames of an animated image, e.g. for
+    /// mirrored avatars or sprites.
+    ///
+    /// The size stays the same, but [ImageSource::hash] is recomputed, so that stateful protocols
+    /// re-encode.
+    pub fn flip_horizontal(self) -> ImageSource {
+        self.map_images(DynamicImage::fliph)
+    }
+
+    /// Mirror the image top to bottom, see [ImageSource::flip_horizontal].
+    pub fn flip_vertical(self) -> ImageSource {
+        self.map_images(DynamicImage::flipv)
+    }
+
+    /// Adjust the colors of the image, including all frames of an animated image, e.g. to
+    /// desaturate it in the background of a "focus mode".
+    ///
+    /// The adjustment is applied once, and [ImageSource::hash] is recomputed, so that stateful
+    /// protocols re-encode. To toggle it, keep the unfiltered source and pass either to
+    /// [StatefulProtocol::set_source].
+    ///
+    /// # Example
+    /// ```rust
+    /// use image::{DynamicImage, ImageBuffer, Rgb};
+    /// use ratatui_image::protocol::{ImageFilter, ImageSource};
+    ///
+    /// let image: DynamicImage = ImageBuffer::from_pixel(8, 16, Rgb([255u8, 0, 0])).into();
+    /// let source = ImageSource::new(image, (8, 16));
+    /// let dimmed = source
+    ///     .clone()
+    ///     .with_filter(ImageFilter::Grayscale)
+    ///     .with_filter(ImageFilter::Brightness(-40));
+    /// assert_ne!(source.hash, dimmed.hash);
+    /// ```
+    pub fn with_filter(self, filter: ImageFilter) -> ImageSource {
+        self.map_images(|image| filter.apply(image))
+    }
+
+    /// Replace the image and the frames of an animated image with `map` of them, keeping the size.
+    fn map_images(mut self, map: impl Fn(&DynamicImage) -> DynamicImage) -> ImageSource {
+        self.image = map(&self.image);
+        self.original = None;
+        self.frames = self
+            .frames
+            .iter()
+            .map(|(frame, delay)| (map(frame), *delay))
+            .collect();
+        self.hash = ImageSource::hash_image(&self.image);
+        self
+    }
+
+    /// A new image source of a region of the image, in pixels, including all frames of an
+    /// animated image. The region is clipped to the image.
+    ///
+    /// This source is left unchanged, so that it can be cropped again, e.g. to pan a viewport over
+    /// a large map image: crop the region for each position, and pass it to
+    /// [StatefulProtocol::set_source], which re-encodes it on the next render.
+    ///
+    /// # Example
+    /// ```rust
+    /// use image::{DynamicImage, ImageBuffer, Rgb};
+    /// use ratatui_image::protocol::ImageSource;
+    ///
+    /// let map: DynamicImage = ImageBuffer::from_pixel(1000, 1000, Rgb([0u8, 128, 0])).into();
+    /// let map = ImageSource::new(map, (8, 16));
+    /// let viewport = map.with_crop(100, 200, 80, 160);
+    /// assert_eq!((80, 160), (viewport.image.width(), viewport.image.height()));
+    /// assert_eq!((10, 10), (viewport.desired.width, viewport.desired.height));
+    /// ```
+    pub fn with_crop(&self, x: u32, y: u32, width: u32, height: u32) -> ImageSource {
+        let image = self.image.crop_imm(x, y, width, height);
+        let frames = self
+            .frames
+            .iter()
+            .map(|(frame, delay)| (frame.crop_imm(x, y, width, height), *delay))
+            .collect();
+        ImageSource {
+            desired: ImageSource::round_pixel_size_to_cells(
+                image.width(),
+                image.height(),
+                self.font_size,
+                self.scale,
+            ),
+            hash: ImageSource::hash_image(&image),
+            image,
+            font_size: self.font_size,
+            frames,
+            original: None,
+            frame: self.frame,
+            scale: self.scale,
+        }
+    }
+
+    /// Create a new image source from a base image with other images drawn over it, e.g. a badge
+    /// over an avatar, so that they are encoded and placed as a single image.
+    ///
+    /// Each overlay is alpha-blended over the base at its `x` and `y` offset in pixels of the
+    /// base, and is clipped to the base.
+    ///
+    /// # Example
+    /// ```rust
+    /// use image::{DynamicImage, ImageBuffer, Rgb, Rgba};
+    /// use ratatui_image::protocol::ImageSource;
+    ///
+    /// let avatar: DynamicImage = ImageBuffer::from_pixel(64, 64, Rgb([200u8, 200, 200])).into();
+    /// let badge: DynamicImage = ImageBuffer::from_pixel(16, 16, Rgba([0u8, 255, 0, 255])).into();
+    /// let source = ImageSource::composite(avatar, &[(badge, 48, 48)], (8, 16));
+    /// assert_eq!((64, 64), (source.image.width(), source.image.height()));
+    /// ```
+    pub fn composite(
+        base: DynamicImage,
+        overlays: &[(DynamicImage, i64, i64)],
+        font_size: FontSize,
+    ) -> ImageSource {
+        // Grayscale bases would lose the colors of the overlays.
+        let mut image = if base.color().has_alpha() {
+            DynamicImage::ImageRgba8(base.into_rgba8())
+        } else {
+            DynamicImage::ImageRgb8(base.into_rgb8())
+        };
+        for (overlay, x, y) in overlays {
+            imageops::overlay(&mut image, overlay, *x, *y);
+        }
+        ImageSource::new(image, font_size)
+    }
+
+    /// Create a new image source from raw RGBA8 pixel data.
+    ///
+    /// Returns an error if `data` is not exactly `width * height * 4` bytes long.
+    pub fn from_raw(
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+        font_size: FontSize,
+    ) -> Result<ImageSource> {
+        // ImageBuffer::from_raw also accepts data that is longer than necessary.
+        let exact = data.len() as u64 == u64::from(width) * u64::from(height) * 4;
+        let image = match ImageBuffer::from_raw(width, height, data) {
+            Some(buffer) if exact => DynamicImage::ImageRgba8(buffer),
+            _ => return Err("raw RGBA data length does not match width * height * 4".into()),
+        };
+        Ok(ImageSource::new(image, font_size))
+    }
+
+    /// Create a new animated image source from the [Frames] of an [image::AnimationDecoder].
+    ///
+    /// The first frame becomes the [ImageSource::image]. An animation with a single frame behaves
+    /// like a still image.
+    pub fn from_frames(frames: Frames, font_size: FontSize) -> Result<ImageSource> {
+        let frames: Vec<(DynamicImage, Duration)> = frames
+            .collect_frames()?
+            .into_iter()
+            .map(|frame| {
+                let delay = Duration::from(frame.delay());
+                (DynamicImage::ImageRgba8(frame.into_buffer()), delay)
+            })
+            .collect();
+        let image = match frames.first() {
+            Some((image, _)) => image.clone(),
+            None => return Err("animation has no frames".into()),
+        };
+        let mut source = ImageSource::new(image, font_size);
+        source.frames = frames.into();
+        Ok(source)
+    }
+
+    /// Advance to the next frame, wrapping around to the first frame after the last one.
+    ///
+    /// Updates [ImageSource::image] and [ImageSource::hash]. Does nothing for still images.
+    pub fn advance_frame(&mut self) {
+        if self.frames.len() < 2 {
+            return;
+        }
+        self.frame = (self.frame + 1) % self.frames.len();
+        self.image = self.frames[self.frame].0.clone();
+        self.hash = ImageSource::hash_image(&self.image);
+    }
+
+    /// The number of frames, `1` for still images.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len().max(1)
+    }
+
+    /// The delay of the current frame, or `None` for still images, including animations of a
+    /// single frame, which never change.
+    pub fn frame_delay(&self) -> Option<Duration> {
+        if self.frames.len() < 2 {
+            return None;
+        }
+        self.frames.get(self.frame).map(|(_, delay)| *delay)
+    }
+
+    /// The [ImageSource::hash], combined with the offset of [Resize::Pan], so that a stateful
+    /// protocol re-crops the image when the offset changes, even if its rect stays the same.
+    pub(crate) fn resize_hash(&self, resize: &Resize) -> u64 {
+        match resize {
+            Resize::Pan(x, y) => {
+                let mut state = DefaultHasher::new();
+                (self.hash, x, y).hash(&mut state);
+                state.finish()
+            }
+            _ => self.hash,
+        }
+    }
+
+    fn hash_image(image: &DynamicImage) -> u64 {
+        let mut state = DefaultHasher::new();
+        image.as_bytes().hash(&mut state);
+        state.finish()
+    }
+
+    /// The scale, or `1.0` if it is not positive or not finite, see [ImageSource::with_scale].
+    pub(crate) fn valid_scale(scale: f32) -> f32 {
+        if scale.is_finite() && scale > 0.0 {
+            scale
+        } else {
+            1.0
         }
     }
-    /// Round an image pixel size to the nearest matching cell size, given a font size.
-    fn round_pixel_size_to_cells(
+
+    /// Round an image pixel size up to the cell size that covers it, given a font size whose pixels
+    /// per cell are multiplied by `scale`, see [ImageSource::with_scale].
+    pub(crate) fn round_pixel_size_to_cells(
         img_width: u32,
         img_height: u32,
-        (char_width, char_height): FontSize,
+        font_size: FontSize,
+        scale: f32,
     ) -> Rect {
-        let width = (img_width as f32 / char_width as f32).ceil() as u16;
-        let height = (img_height as f32 / char_height as f32).ceil() as u16;
+        let scaled = |pixels: u32| (pixels as f32 / scale).ceil() as u32;
+        let (width, height) =
+            crate::pixels_to_cells(font_size, scaled(img_width), scaled(img_height));
         Rect::new(0, 0, width, height)
     }
 }
@@ -126,6 +909,9 @@ pub enum StatefulBlock {
     Sixel(sixel::StatefulSixel),
     Kitty(kitty::StatefulKitty),
     Iterm2(iterm2::Iterm2State),
+    Braille(braille::StatefulBraille),
+    Blank(blank::StatefulBlank),
+    Text(text::StatefulText),
 }
 
 impl StatefulProtocol for StatefulBlock {
@@ -135,15 +921,38 @@ impl StatefulProtocol for StatefulBlock {
             StatefulBlock::Sixel(sixel) => sixel.needs_resize(resize, area),
             StatefulBlock::Kitty(kitty) => kitty.needs_resize(resize, area),
             StatefulBlock::Iterm2(iterm2) => iterm2.needs_resize(resize, area),
+            StatefulBlock::Braille(braille) => braille.needs_resize(resize, area),
+            StatefulBlock::Blank(blank) => blank.needs_resize(resize, area),
+            StatefulBlock::Text(text) => text.needs_resize(resize, area),
         }
     }
 
-    fn resize_encode(&mut self, resize: &Resize, background_color: Option<Rgb<u8>>, area: Rect) {
+    fn resize(
+        &self,
+        resize: &Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+    ) -> Option<ResizedImage> {
         match self {
-            StatefulBlock::Halfblocks(hb) => hb.resize_encode(resize, background_color, area),
-            StatefulBlock::Sixel(sixel) => sixel.resize_encode(resize, background_color, area),
-            StatefulBlock::Kitty(kitty) => kitty.resize_encode(resize, background_color, area),
-            StatefulBlock::Iterm2(iterm2) => iterm2.resize_encode(resize, background_color, area),
+            StatefulBlock::Halfblocks(hb) => hb.resize(resize, background_color, area),
+            StatefulBlock::Sixel(sixel) => sixel.resize(resize, background_color, area),
+            StatefulBlock::Kitty(kitty) => kitty.resize(resize, background_color, area),
+            StatefulBlock::Iterm2(iterm2) => iterm2.resize(resize, background_color, area),
+            StatefulBlock::Braille(braille) => braille.resize(resize, background_color, area),
+            StatefulBlock::Blank(blank) => blank.resize(resize, background_color, area),
+            StatefulBlock::Text(text) => text.resize(resize, background_color, area),
+        }
+    }
+
+    fn encode(&mut self, resized: ResizedImage) {
+        match self {
+            StatefulBlock::Halfblocks(hb) => hb.encode(resized),
+            StatefulBlock::Sixel(sixel) => sixel.encode(resized),
+            StatefulBlock::Kitty(kitty) => kitty.encode(resized),
+            StatefulBlock::Iterm2(iterm2) => iterm2.encode(resized),
+            StatefulBlock::Braille(braille) => braille.encode(resized),
+            StatefulBlock::Blank(blank) => blank.encode(resized),
+            StatefulBlock::Text(text) => text.encode(resized),
         }
     }
 
@@ -153,6 +962,81 @@ impl StatefulProtocol for StatefulBlock {
             StatefulBlock::Sixel(sixel) => sixel.render(area, buf),
             StatefulBlock::Kitty(kitty) => kitty.render(area, buf),
             StatefulBlock::Iterm2(iterm2) => iterm2.render(area, buf),
+            StatefulBlock::Braille(braille) => braille.render(area, buf),
+            StatefulBlock::Blank(blank) => blank.render(area, buf),
+            StatefulBlock::Text(text) => text.render(area, buf),
+        }
+    }
+
+    fn rect(&self) -> Rect {
+        match self {
+            StatefulBlock::Halfblocks(hb) => hb.rect(),
+            StatefulBlock::Sixel(sixel) => sixel.rect(),
+            StatefulBlock::Kitty(kitty) => kitty.rect(),
+            StatefulBlock::Iterm2(iterm2) => iterm2.rect(),
+            StatefulBlock::Braille(braille) => braille.rect(),
+            StatefulBlock::Blank(blank) => blank.rect(),
+            StatefulBlock::Text(text) => text.rect(),
+        }
+    }
+
+    fn encoded(&self) -> String {
+        match self {
+            StatefulBlock::Halfblocks(hb) => hb.encoded(),
+            StatefulBlock::Sixel(sixel) => sixel.encoded(),
+            StatefulBlock::Kitty(kitty) => kitty.encoded(),
+            StatefulBlock::Iterm2(iterm2) => iterm2.encoded(),
+            StatefulBlock::Braille(braille) => braille.encoded(),
+            StatefulBlock::Blank(blank) => blank.encoded(),
+            StatefulBlock::Text(text) => text.encoded(),
+        }
+    }
+
+    fn set_source(&mut self, source: ImageSource) {
+        match self {
+            StatefulBlock::Halfblocks(hb) => hb.set_source(source),
+            StatefulBlock::Sixel(sixel) => sixel.set_source(source),
+            StatefulBlock::Kitty(kitty) => kitty.set_source(source),
+            StatefulBlock::Iterm2(iterm2) => iterm2.set_source(source),
+            StatefulBlock::Braille(braille) => braille.set_source(source),
+            StatefulBlock::Blank(blank) => blank.set_source(source),
+            StatefulBlock::Text(text) => text.set_source(source),
+        }
+    }
+
+    fn advance_frame(&mut self) {
+        match self {
+            StatefulBlock::Halfblocks(hb) => hb.advance_frame(),
+            StatefulBlock::Sixel(sixel) => sixel.advance_frame(),
+            StatefulBlock::Kitty(kitty) => kitty.advance_frame(),
+            StatefulBlock::Iterm2(iterm2) => iterm2.advance_frame(),
+            StatefulBlock::Braille(braille) => braille.advance_frame(),
+            StatefulBlock::Blank(blank) => blank.advance_frame(),
+            StatefulBlock::Text(text) => text.advance_frame(),
+        }
+    }
+
+    fn frame_delay(&self) -> Option<Duration> {
+        match self {
+            StatefulBlock::Halfblocks(hb) => hb.frame_delay(),
+            StatefulBlock::Sixel(sixel) => sixel.frame_delay(),
+            StatefulBlock::Kitty(kitty) => kitty.frame_delay(),
+            StatefulBlock::Iterm2(iterm2) => iterm2.frame_delay(),
+            StatefulBlock::Braille(braille) => braille.frame_delay(),
+            StatefulBlock::Blank(blank) => blank.frame_delay(),
+            StatefulBlock::Text(text) => text.frame_delay(),
+        }
+    }
+
+    fn clear(&mut self, area: Rect, buf: &mut Buffer) {
+        match self {
+            StatefulBlock::Halfblocks(hb) => hb.clear(area, buf),
+            StatefulBlock::Sixel(sixel) => sixel.clear(area, buf),
+            StatefulBlock::Kitty(kitty) => kitty.clear(area, buf),
+            StatefulBlock::Iterm2(iterm2) => iterm2.clear(area, buf),
+            StatefulBlock::Braille(braille) => braille.clear(area, buf),
+            StatefulBlock::Blank(blank) => blank.clear(area, buf),
+            StatefulBlock::Text(text) => text.clear(area, buf),
         }
     }
 }
@@ -176,12 +1060,30 @@ impl From<iterm2::Iterm2State> for StatefulBlock {
         StatefulBlock::Iterm2(iterm2)
     }
 }
+impl From<braille::StatefulBraille> for StatefulBlock {
+    fn from(braille: braille::StatefulBraille) -> Self {
+        StatefulBlock::Braille(braille)
+    }
+}
+impl From<blank::StatefulBlank> for StatefulBlock {
+    fn from(blank: blank::StatefulBlank) -> Self {
+        StatefulBlock::Blank(blank)
+    }
+}
+impl From<text::StatefulText> for StatefulBlock {
+    fn from(text: text::StatefulText) -> Self {
+        StatefulBlock::Text(text)
+    }
+}
 
 pub enum FixedBlock {
     Halfblocks(halfblocks::Halfblocks),
     Sixel(sixel::Sixel),
     Kitty(kitty::Kitty),
     Iterm2(iterm2::FixedIterm2),
+    Braille(braille::Braille),
+    Blank(blank::Blank),
+    Text(text::Text),
 }
 
 impl Protocol for FixedBlock {
@@ -191,6 +1093,9 @@ impl Protocol for FixedBlock {
             FixedBlock::Sixel(sixel) => sixel.render(area, buf),
             FixedBlock::Kitty(kitty) => kitty.render(area, buf),
             FixedBlock::Iterm2(iterm2) => iterm2.render(area, buf),
+            FixedBlock::Braille(braille) => braille.render(area, buf),
+            FixedBlock::Blank(blank) => blank.render(area, buf),
+            FixedBlock::Text(text) => text.render(area, buf),
         }
     }
 
@@ -200,6 +1105,33 @@ impl Protocol for FixedBlock {
             FixedBlock::Sixel(sixel) => sixel.rect(),
             FixedBlock::Kitty(kitty) => kitty.rect(),
             FixedBlock::Iterm2(iterm2) => iterm2.rect(),
+            FixedBlock::Braille(braille) => braille.rect(),
+            FixedBlock::Blank(blank) => blank.rect(),
+            FixedBlock::Text(text) => text.rect(),
+        }
+    }
+
+    fn encoded(&self) -> String {
+        match self {
+            FixedBlock::Halfblocks(hb) => hb.encoded(),
+            FixedBlock::Sixel(sixel) => sixel.encoded(),
+            FixedBlock::Kitty(kitty) => kitty.encoded(),
+            FixedBlock::Iterm2(iterm2) => iterm2.encoded(),
+            FixedBlock::Braille(braille) => braille.encoded(),
+            FixedBlock::Blank(blank) => blank.encoded(),
+            FixedBlock::Text(text) => text.encoded(),
+        }
+    }
+
+    fn write_to(&self, out: &mut dyn Write) -> io::Result<()> {
+        match self {
+            FixedBlock::Halfblocks(hb) => hb.write_to(out),
+            FixedBlock::Sixel(sixel) => sixel.write_to(out),
+            FixedBlock::Kitty(kitty) => kitty.write_to(out),
+            FixedBlock::Iterm2(iterm2) => iterm2.write_to(out),
+            FixedBlock::Braille(braille) => braille.write_to(out),
+            FixedBlock::Blank(blank) => blank.write_to(out),
+            FixedBlock::Text(text) => text.write_to(out),
         }
     }
 }
@@ -224,3 +1156,478 @@ impl From<iterm2::FixedIterm2> for FixedBlock {
         FixedBlock::Iterm2(iterm2)
     }
 }
+impl From<braille::Braille> for FixedBlock {
+    fn from(braille: braille::Braille) -> Self {
+        FixedBlock::Braille(braille)
+    }
+}
+impl From<blank::Blank> for FixedBlock {
+    fn from(blank: blank::Blank) -> Self {
+        FixedBlock::Blank(blank)
+    }
+}
+impl From<text::Text> for FixedBlock {
+    fn from(text: text::Text) -> Self {
+        FixedBlock::Text(text)
+    }
+}
+
+/// Color adjustment of an [ImageSource], see [ImageSource::with_filter].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFilter {
+    /// Desaturate the image. The image becomes single-channel, see [ImageSource::new].
+    Grayscale,
+    /// Add to each color channel, i.e. brighten if positive, or darken if negative.
+    Brightness(i32),
+    /// Adjust the contrast by a percentage, i.e. increase if positive, or decrease if negative.
+    Contrast(f32),
+}
+
+impl ImageFilter {
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        match self {
+            ImageFilter::Grayscale => image.grayscale(),
+            ImageFilter::Brightness(value) => image.brighten(*value),
+            ImageFilter::Contrast(percent) => image.adjust_contrast(*percent),
+        }
+    }
+}
+
+/// Clockwise rotation of an [ImageSource], see [ImageSource::with_rotation].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Rotation {
+    /// Read the rotation from the EXIF orientation tag of a JPEG file's data.
+    ///
+    /// Returns `None` if there is no EXIF data, or the image is upright. The mirrored
+    /// orientations are not supported and also return `None`.
+    pub fn from_exif(data: &[u8]) -> Option<Rotation> {
+        match exif_orientation(data)? {
+            3 => Some(Rotation::Rotate180),
+            6 => Some(Rotation::Rotate90),
+            8 => Some(Rotation::Rotate270),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        match self {
+            Rotation::Rotate90 => DynamicImage::from(imageops::rotate90(image)),
+            Rotation::Rotate180 => DynamicImage::from(imageops::rotate180(image)),
+            Rotation::Rotate270 => DynamicImage::from(imageops::rotate270(image)),
+        }
+    }
+}
+
+/// Find the orientation tag (`0x0112`) in the first IFD of a JPEG's APP1 EXIF segment.
+fn exif_orientation(data: &[u8]) -> Option<u16> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    // Walk the segments up to the start of the scan.
+    while pos + 4 <= data.len() && data[pos] == 0xFF && data[pos + 1] != 0xDA {
+        let marker = data[pos + 1];
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return tiff_orientation(&segment[6..]);
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(0..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| -> Option<u16> {
+        let bytes = [*tiff.get(pos)?, *tiff.get(pos + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |pos: usize| -> Option<u32> {
+        let (a, b) = (u16_at(pos)? as u32, u16_at(pos + 2)? as u32);
+        Some(if big_endian { a << 16 | b } else { b << 16 | a })
+    };
+    let ifd = u32_at(4)? as usize;
+    let entries = u16_at(ifd)? as usize;
+    (0..entries)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| u16_at(entry) == Some(0x0112))
+        .and_then(|entry| u16_at(entry + 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, time::Duration};
+
+    use base64::{engine::general_purpose, Engine};
+    use image::{
+        ColorType, Delay, DynamicImage, Frame, Frames, ImageBuffer, ImageFormat, ImageOutputFormat,
+        Luma, Rgb, Rgba,
+    };
+    use ratatui::{buffer::Buffer, layout::Rect};
+
+    use super::{
+        composite, halfblocks::Halfblocks, sixel::Sixel, ImageFilter, ImageSource, Protocol,
+        Rotation, StatefulProtocol,
+    };
+    use crate::{errors::Errors, Resize};
+
+    /// A protocol outside of this crate, that only implements the methods that are required.
+    #[derive(Clone, Default)]
+    struct Minimal {
+        rect: Rect,
+    }
+
+    impl StatefulProtocol for Minimal {
+        fn needs_resize(&mut self, _resize: &Resize, area: Rect) -> Option<Rect> {
+            (area != self.rect).then_some(area)
+        }
+        fn resize_encode(&mut self, _resize: &Resize, _bg: Option<Rgb<u8>>, area: Rect) {
+            self.rect = area;
+        }
+        fn render(&mut self, area: Rect, buf: &mut Buffer) {
+            buf.get_mut(area.x, area.y).set_symbol("x");
+        }
+    }
+
+    #[test]
+    fn minimal_stateful_protocol() {
+        let area = Rect::new(0, 0, 2, 2);
+        let mut protocol = Minimal::default();
+        assert!(protocol.resize(&Resize::Fit(None), None, area).is_none());
+        let mut buf = Buffer::empty(area);
+        protocol.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        assert_eq!("x", buf.get(0, 0).symbol);
+        assert!(protocol.is_current(&Resize::Fit(None), area));
+        assert_eq!(Rect::default(), protocol.rect());
+        assert_eq!("", protocol.encoded());
+    }
+
+    fn frames(colors: &[u8]) -> Frames<'static> {
+        let frames: Vec<_> = colors
+            .iter()
+            .map(|c| {
+                let buffer = ImageBuffer::from_pixel(10, 10, Rgba([*c, 0, 0, 255]));
+                let delay = Delay::from_numer_denom_ms(100 * (*c as u32 + 1), 1);
+                Ok(Frame::from_parts(buffer, 0, 0, delay))
+            })
+            .collect();
+        Frames::new(Box::new(frames.into_iter()))
+    }
+
+    #[test]
+    #[cfg(any(feature = "image-defaults", feature = "webp"))]
+    fn webp() {
+        // Lossless and animated 1x1 WebP images.
+        let still = "UklGRhoAAABXRUJQVlA4TA0AAAAvAAAAEAcQERGIiP4HAA==";
+        let animated = concat!(
+            "UklGRlIAAABXRUJQVlA4WAoAAAASAAAAAAAAAAAAQU5JTQYAAAD/////",
+            "AABBTk1GJgAAAAAAAAAAAAAAAAAAAGQAAABWUDhMDQAAAC8AAAAQBxAREYiI/gcA",
+        );
+        for webp in [still, animated] {
+            let data = general_purpose::STANDARD.decode(webp).unwrap();
+            let source = ImageSource::from_reader(Cursor::new(data), (1, 1)).unwrap();
+            assert_eq!((1, 1), (source.image.width(), source.image.height()));
+            assert_eq!(Rect::new(0, 0, 1, 1), source.desired);
+        }
+    }
+
+    #[test]
+    fn high_bit_depth() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgb([65535u16, 0, 32768])).into();
+        let source = ImageSource::new(image, (1, 1));
+        assert_eq!(ColorType::Rgb8, source.image.color());
+        assert_eq!(&Rgb([255, 0, 128]), source.image.to_rgb8().get_pixel(0, 0));
+
+        let image: DynamicImage =
+            ImageBuffer::from_pixel(2, 2, Rgba([1.0f32, 0.0, 0.0, 0.5])).into();
+        let source = ImageSource::new(image, (1, 1));
+        assert_eq!(ColorType::Rgba8, source.image.color());
+
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 2, Luma([32896u16])).into();
+        let source = ImageSource::new(image, (1, 1));
+        assert_eq!(ColorType::L8, source.image.color());
+        assert_eq!(
+            &Rgb([128, 128, 128]),
+            source.image.to_rgb8().get_pixel(0, 0)
+        );
+    }
+
+    #[test]
+    fn composite_overlays() {
+        let base: DynamicImage = ImageBuffer::from_pixel(4, 4, Luma([0u8])).into();
+        let badge: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgba([0u8, 0, 255, 255])).into();
+        let faint: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgba([255u8, 0, 0, 51])).into();
+        let source = ImageSource::composite(base, &[(badge, 3, 3), (faint, -1, -1)], (2, 2));
+        assert_eq!(ColorType::Rgb8, source.image.color());
+        assert_eq!(Rect::new(0, 0, 2, 2), source.desired);
+
+        let image = source.image.to_rgb8();
+        assert_eq!(&Rgb([51, 0, 0]), image.get_pixel(0, 0));
+        assert_eq!(&Rgb([0, 0, 0]), image.get_pixel(1, 1));
+        assert_eq!(&Rgb([0, 0, 0]), image.get_pixel(2, 3));
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(3, 3));
+    }
+
+    #[test]
+    fn composite_alpha() {
+        let image: DynamicImage = ImageBuffer::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([255u8, 0, 0, 255]),
+            1 => Rgba([255, 0, 0, 51]),
+            _ => Rgba([255, 0, 0, 0]),
+        })
+        .into();
+        let image = composite(image, Some(Rgb([0, 0, 255]))).into_rgb8();
+        assert_eq!(&Rgb([255, 0, 0]), image.get_pixel(0, 0));
+        assert_eq!(&Rgb([51, 0, 204]), image.get_pixel(1, 0));
+        assert_eq!(&Rgb([0, 0, 255]), image.get_pixel(2, 0));
+
+        let opaque: DynamicImage = ImageBuffer::from_pixel(1, 1, Rgb([1u8, 2, 3])).into();
+        assert_eq!(opaque, composite(opaque.clone(), None));
+    }
+
+    #[test]
+    fn advance_frame_wraps() {
+        let mut source = ImageSource::from_frames(frames(&[0, 1, 2]), (1, 1)).unwrap();
+        let first = source.hash;
+        assert_eq!(Some(Duration::from_millis(100)), source.frame_delay());
+
+        source.advance_frame();
+        assert_ne!(first, source.hash);
+        assert_eq!(Some(Duration::from_millis(200)), source.frame_delay());
+
+        source.advance_frame();
+        source.advance_frame();
+        assert_eq!(first, source.hash);
+        assert_eq!(Some(Duration::from_millis(100)), source.frame_delay());
+    }
+
+    #[test]
+    fn single_frame_delay() {
+        let mut source = ImageSource::from_frames(frames(&[0]), (1, 1)).unwrap();
+        assert_eq!(1, source.frame_count());
+        assert_eq!(None, source.frame_delay());
+        source.advance_frame();
+        assert_eq!(None, source.frame_delay());
+    }
+
+    #[test]
+    fn from_raw() {
+        let source = ImageSource::from_raw(2, 3, vec![255; 2 * 3 * 4], (1, 1)).unwrap();
+        assert_eq!((2, 3), (source.image.width(), source.image.height()));
+
+        assert!(ImageSource::from_raw(2, 3, vec![255; 2 * 3 * 3], (1, 1)).is_err());
+        assert!(ImageSource::from_raw(2, 3, vec![255; 2 * 3 * 4 + 1], (1, 1)).is_err());
+    }
+
+    #[test]
+    fn advance_frame_single() {
+        let mut source = ImageSource::from_frames(frames(&[0]), (1, 1)).unwrap();
+        let first = source.hash;
+        source.advance_frame();
+        assert_eq!(first, source.hash);
+        assert!(ImageSource::from_frames(frames(&[]), (1, 1)).is_err());
+    }
+
+    #[test]
+    fn flip() {
+        // Left half red, right half blue.
+        let image: DynamicImage = ImageBuffer::from_fn(20, 10, |x, _| {
+            if x < 10 {
+                Rgb([255u8, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        })
+        .into();
+        let source = ImageSource::new(image, (5, 5));
+
+        let flipped = source.clone().flip_horizontal();
+        assert_eq!(&Rgb([0, 0, 255]), flipped.image.to_rgb8().get_pixel(0, 0));
+        assert_eq!(source.desired, flipped.desired);
+        assert_ne!(source.hash, flipped.hash);
+
+        // Symmetric top to bottom.
+        let flipped = source.clone().flip_vertical();
+        assert_eq!(source.hash, flipped.hash);
+    }
+
+    #[test]
+    fn with_crop() {
+        // Left half red, right half blue.
+        let image: DynamicImage = ImageBuffer::from_fn(20, 10, |x, _| {
+            if x < 10 {
+                Rgb([255u8, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        })
+        .into();
+        let source = ImageSource::new(image, (5, 5));
+
+        let right = source.with_crop(10, 0, 10, 10);
+        assert_eq!(&Rgb([0, 0, 255]), right.image.to_rgb8().get_pixel(0, 0));
+        assert_eq!(Rect::new(0, 0, 2, 2), right.desired);
+        assert_ne!(source.hash, right.hash);
+
+        // Clipped to the image.
+        let clipped = source.with_crop(15, 5, 10, 10);
+        assert_eq!((5, 5), clipped.pixel_size());
+
+        let animated = ImageSource::from_frames(frames(&[0, 1]), (5, 5)).unwrap();
+        let mut cropped = animated.with_crop(0, 0, 5, 5);
+        cropped.advance_frame();
+        assert_eq!((5, 5), cropped.pixel_size());
+    }
+
+    #[test]
+    fn aspect_ratio() {
+        let image: DynamicImage = ImageBuffer::from_pixel(30, 20, Rgb([0u8, 0, 0])).into();
+        let source = ImageSource::new(image, (5, 10));
+        assert_eq!((30, 20), source.pixel_size());
+        // 6 columns by 2 rows.
+        assert_eq!(3.0, source.aspect_ratio());
+
+        let source = ImageSource::new(DynamicImage::new_rgb8(0, 0), (5, 10));
+        assert_eq!(0.0, source.aspect_ratio());
+    }
+
+    #[test]
+    fn from_reader() {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 2, Rgba([255u8, 0, 0, 255])).into();
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .unwrap();
+
+        let source = ImageSource::from_reader(Cursor::new(&png), (2, 2)).unwrap();
+        assert_eq!((4, 2), (source.image.width(), source.image.height()));
+        assert_eq!((2, 1), (source.desired.width, source.desired.height));
+        assert_eq!(Some((&png[..], ImageFormat::Png)), source.original());
+        assert!(source.original_of(&source.image).is_some());
+        assert!(source.original_of(&source.image.thumbnail(2, 1)).is_none());
+        assert!(source
+            .with_rotation(Rotation::Rotate180)
+            .original()
+            .is_none());
+
+        png.truncate(png.len() / 2);
+        assert!(matches!(
+            ImageSource::from_reader(Cursor::new(&png), (2, 2)),
+            Err(Errors::ImageError(_))
+        ));
+    }
+
+    /// A minimal JPEG prefix with an APP1 EXIF segment holding only the orientation tag.
+    fn exif_jpeg(orientation: u8, big_endian: bool) -> Vec<u8> {
+        let tiff: Vec<u8> = if big_endian {
+            let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+            tiff.extend([0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, orientation, 0, 0]);
+            tiff
+        } else {
+            let mut tiff = b"II\x2a\0\x08\0\0\0\x01\0".to_vec();
+            tiff.extend([0x12, 0x01, 3, 0, 1, 0, 0, 0, orientation, 0, 0, 0]);
+            tiff
+        };
+        let len = (2 + 6 + tiff.len()) as u16;
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        data.extend(len.to_be_bytes());
+        data.extend(b"Exif\0\0");
+        data.extend(tiff);
+        data.extend([0xFF, 0xDA]);
+        data
+    }
+
+    #[test]
+    fn exif_rotation() {
+        assert_eq!(
+            Some(Rotation::Rotate90),
+            Rotation::from_exif(&exif_jpeg(6, true))
+        );
+        assert_eq!(
+            Some(Rotation::Rotate270),
+            Rotation::from_exif(&exif_jpeg(8, false))
+        );
+        assert_eq!(None, Rotation::from_exif(&exif_jpeg(1, false)));
+        assert_eq!(None, Rotation::from_exif(&[0xFF, 0xD8, 0xFF]));
+    }
+
+    #[test]
+    fn with_rotation() {
+        // Red left column, blue elsewhere.
+        let image: DynamicImage = ImageBuffer::from_fn(4, 2, |x, _| {
+            if x == 0 {
+                Rgba([255u8, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        })
+        .into();
+        let source = ImageSource::new(image, (2, 2));
+        let hash = source.hash;
+
+        let source = source.with_rotation(Rotation::Rotate90);
+        assert_eq!((2, 4), (source.image.width(), source.image.height()));
+        assert_eq!((1, 2), (source.desired.width, source.desired.height));
+        assert_ne!(hash, source.hash);
+        // The left column is now the top row.
+        assert_eq!(Rgba([255, 0, 0, 255]), source.image.to_rgba8()[(1, 0)]);
+        assert_eq!(Rgba([0, 0, 255, 255]), source.image.to_rgba8()[(1, 1)]);
+    }
+
+    #[test]
+    fn escape_sequences() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 4, Rgb([255u8, 0, 0])).into();
+        let source = ImageSource::new(image, (1, 2));
+        let area = Rect::new(1, 1, 2, 2);
+        let mut buf = Buffer::empty(Rect::new(0, 0, 4, 4));
+
+        let sixel = Sixel::from_source(&source, Resize::Fit(None), None, false, area).unwrap();
+        sixel.render(area, &mut buf);
+        assert_eq!(sixel.encoded(), super::escape_sequences(&buf, area));
+        assert!(buf.get(2, 1).skip);
+        assert_eq!("", super::escape_sequences(&buf, Rect::new(2, 1, 2, 3)));
+
+        let mut buf = Buffer::empty(Rect::new(0, 0, 4, 4));
+        Halfblocks::from_source(&source, Resize::Fit(None), None, area)
+            .unwrap()
+            .render(area, &mut buf);
+        assert_eq!("", super::escape_sequences(&buf, area));
+    }
+
+    #[test]
+    fn with_filter() {
+        let image: DynamicImage = ImageBuffer::from_pixel(2, 2, Rgb([200u8, 100, 0])).into();
+        let source = ImageSource::new(image, (1, 1));
+
+        let gray = source.clone().with_filter(ImageFilter::Grayscale);
+        assert_eq!(ColorType::L8, gray.image.color());
+        assert_eq!(source.desired, gray.desired);
+        assert_ne!(source.hash, gray.hash);
+
+        let bright = source.clone().with_filter(ImageFilter::Brightness(100));
+        assert_eq!(
+            &Rgb([255, 200, 100]),
+            bright.image.to_rgb8().get_pixel(0, 0)
+        );
+
+        let animated = ImageSource::from_frames(frames(&[0, 1]), (1, 1))
+            .unwrap()
+            .with_filter(ImageFilter::Contrast(-100.0));
+        assert_eq!(2, animated.frame_count());
+    }
+}
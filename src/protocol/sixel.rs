@@ -2,25 +2,148 @@
 //! Uses [`sixel-bytes`] to draw image pixels, if the terminal [supports] the [Sixel] protocol.
 //! Needs the `sixel` feature.
 //!
+//! Sixel images are opaque: transparent pixels are composited over the background color, or over
+//! black without one, see [crate::picker::Picker::background_color].
+//!
 //! [`sixel-bytes`]: https://github.com/benjajaja/sixel-bytes
 //! [supports]: https://arewesixelyet.com
 //! [Sixel]: https://en.wikipedia.org/wiki/Sixel
 use icy_sixel::{
-    sixel_string, DiffusionMethod, MethodForLargest, MethodForRep, PixelFormat, Quality,
+    dither::sixel_dither, output::sixel_output, DiffusionMethod, EncodePolicy, MethodForLargest,
+    MethodForRep, PixelFormat, Quality,
 };
-use image::{DynamicImage, Rgb};
+use image::{imageops::FilterType, DynamicImage, Rgb};
 use ratatui::{buffer::Buffer, layout::Rect};
-use std::cmp::min;
+use std::{cmp::min, sync::Arc, time::Duration};
+
+use super::{
+    clear_erase, clip, composite, halfblocks::Halfblocks, Protocol, ResizedImage, StatefulProtocol,
+};
+use crate::{cells_to_pixels, errors::Errors, ImageSource, Resize, Result};
+
+/// Options for the sixel encoder.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SixelOptions {
+    /// Maximum number of colors in the palette, from 2 to 256. Defaults to 256.
+    ///
+    /// Larger palettes give better quality, but make the escape sequence substantially longer,
+    /// which matters e.g. over slow SSH connections.
+    pub palette_size: u16,
+    /// How colors that are not in the palette are approximated. Defaults to [SixelDither::Stucki].
+    ///
+    /// Photos look smoothest with error diffusion, while flat UI graphics and line art stay crisp
+    /// with [SixelDither::None].
+    pub dither: SixelDither,
+    /// How the color of each palette entry is picked. Defaults to [SixelQuantization::Auto].
+    pub quantization: SixelQuantization,
+    /// The number of color registers the terminal advertised, if known.
+    ///
+    /// Set by [crate::picker::Picker::guess_protocol] and
+    /// [crate::picker::Picker::from_query_stdio] if the terminal replies to the `XTSMGRAPHICS`
+    /// query. The palette size is clamped to it.
+    pub color_registers: Option<u16>,
+    /// The maximum sixel image size in pixels, `(width, height)`, that the terminal advertised, if
+    /// known.
+    ///
+    /// Set like [SixelOptions::color_registers]. Terminals silently cut off larger images, so
+    /// these are shrunk to fit it, keeping their aspect ratio, see [Sixel::clamped].
+    pub max_size: Option<(u32, u32)>,
+    /// Where the cursor is left after the image. Defaults to [SixelPlacement::Inline].
+    pub placement: SixelPlacement,
+}
+
+impl SixelOptions {
+    /// The palette size that is actually used: [SixelOptions::palette_size] clamped to 2..=256
+    /// and to [SixelOptions::color_registers].
+    pub fn colors(&self) -> u16 {
+        let max = self
+            .color_registers
+            .map_or(256, |registers| registers.min(256));
+        self.palette_size.min(max).max(2)
+    }
+}
 
-use super::{Protocol, StatefulProtocol};
-use crate::{ImageSource, Resize, Result};
+impl Default for SixelOptions {
+    fn default() -> Self {
+        SixelOptions {
+            palette_size: 256,
+            dither: SixelDither::default(),
+            quantization: SixelQuantization::default(),
+            color_registers: None,
+            max_size: None,
+            placement: SixelPlacement::default(),
+        }
+    }
+}
+
+/// Dithering mode of the sixel encoder, see [SixelOptions::dither].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SixelDither {
+    /// No dithering. Faster, and gives flat color areas, at the cost of visible banding in
+    /// gradients.
+    None,
+    /// Stucki error diffusion, which spreads the error over more neighbours than Floyd-Steinberg,
+    /// for smoother gradients.
+    #[default]
+    Stucki,
+    /// Floyd-Steinberg error diffusion.
+    FloydSteinberg,
+    /// Bill Atkinson's error diffusion, which diffuses only part of the error, for more contrast.
+    Atkinson,
+    /// Positionally stable, ordered pattern dithering. Unlike error diffusion, a pixel does not
+    /// depend on its neighbours, so e.g. animations do not shimmer.
+    Ordered,
+}
+
+/// The method that picks the color of each palette entry from the colors it stands for, see
+/// [SixelOptions::quantization].
+///
+/// The palette is built with median cut, which splits the image's colors into as many boxes as
+/// there are palette entries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SixelQuantization {
+    /// Chosen by the encoder.
+    #[default]
+    Auto,
+    /// The center of the box.
+    CenterBox,
+    /// The average of the distinct colors in the box.
+    AverageColors,
+    /// The average of all pixels in the box, weighing colors by how often they appear.
+    AveragePixels,
+}
+
+/// Where the cursor is left after a sixel image, see [SixelOptions::placement].
+///
+/// A sixel image is always drawn at the cursor. Most terminals, e.g. xterm, foot, mlterm, WezTerm
+/// and Konsole, then move the cursor below the image like after a line of text, and scroll the
+/// screen if the image reaches its bottom. Terminals that leave the cursor where it was behave the
+/// same with both placements.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SixelPlacement {
+    /// Leave the cursor where the terminal moves it, e.g. below the image. For images in scrolling
+    /// output, like an inline viewport or [Protocol::write_to], so that the text after the image
+    /// is not drawn over it.
+    #[default]
+    Inline,
+    /// Save the cursor before the image and restore it afterwards (`DECSC`/`DECRC`). For images
+    /// in fixed widgets, so that the moved cursor does not offset or tear what is drawn next.
+    Overlay,
+}
 
 // Fixed sixel protocol
+///
+/// A sixel sequence can only be drawn whole, so nothing is drawn if the image is only partially
+/// inside the buffer, e.g. in a scrolled viewport. [StatefulSixel] encodes the visible part
+/// instead.
 #[derive(Clone, Default)]
 pub struct Sixel {
     pub data: String,
     pub rect: Rect,
     pub is_tmux: bool,
+    /// Whether the image was shrunk to fit [SixelOptions::max_size], so that it does not fill
+    /// `rect`.
+    pub clamped: bool,
 }
 
 impl Sixel {
@@ -30,55 +153,152 @@ impl Sixel {
         background_color: Option<Rgb<u8>>,
         is_tmux: bool,
         area: Rect,
+    ) -> Result<Self> {
+        Self::from_source_with_options(
+            source,
+            resize,
+            background_color,
+            is_tmux,
+            area,
+            SixelOptions::default(),
+        )
+    }
+
+    /// Create a Sixel from an image, with [SixelOptions] for the encoder.
+    pub fn from_source_with_options(
+        source: &ImageSource,
+        resize: Resize,
+        background_color: Option<Rgb<u8>>,
+        is_tmux: bool,
+        area: Rect,
+        options: SixelOptions,
     ) -> Result<Self> {
         let (img, rect) = resize
             .resize(source, Rect::default(), area, background_color, false)
             .unwrap_or_else(|| (source.image.clone(), source.desired));
 
-        let data = encode(img, is_tmux)?;
+        let img = composite(img, background_color);
+        let (img, clamped) = clamp(img, &options, resize.filter_type());
+        let mut data = String::new();
+        encode(&img, is_tmux, &options, &mut data)?;
         Ok(Self {
             data,
             rect,
             is_tmux,
+            clamped,
         })
     }
 }
 
+/// Shrink the image to fit [SixelOptions::max_size] with the filter of the [Resize], and return
+/// whether it had to be shrunk.
+fn clamp(
+    img: DynamicImage,
+    options: &SixelOptions,
+    filter_type: Option<FilterType>,
+) -> (DynamicImage, bool) {
+    match options.max_size {
+        Some((width, height)) if img.width() > width || img.height() > height => {
+            let filter_type = filter_type.unwrap_or(FilterType::Triangle);
+            (img.resize(width, height, filter_type), true)
+        }
+        _ => (img, false),
+    }
+}
+
 static TMUX_START: &str = "\x1bPtmux;";
 
-// TODO: change E to sixel_rs::status::Error and map when calling
-fn encode(img: DynamicImage, is_tmux: bool) -> Result<String> {
-    let (w, h) = (img.width(), img.height());
-    let img_rgba8 = img.to_rgba8();
-    let bytes = img_rgba8.as_raw();
-
-    let data = sixel_string(
-        bytes,
-        w as i32,
-        h as i32,
-        PixelFormat::RGBA8888,
-        DiffusionMethod::Stucki,
-        MethodForLargest::Auto,
-        MethodForRep::Auto,
-        Quality::HIGH,
-    )?;
+/// Encode the image into `data`, reusing its allocation, e.g. from the previous encode. `data` is
+/// left empty on errors.
+fn encode(
+    img: &DynamicImage,
+    is_tmux: bool,
+    options: &SixelOptions,
+    data: &mut String,
+) -> Result<()> {
+    // The sixel sequence is ASCII, so the encoder can write to the bytes of `data` directly.
+    let mut sixel_data = std::mem::take(data).into_bytes();
+    sixel_data.clear();
+    let (w, h) = (img.width() as i32, img.height() as i32);
+    // The encoder needs a mutable buffer, which is dropped right after encoding.
+    let mut bytes = img.to_rgba8().into_raw();
+    let encoder_failed = |err: Box<dyn std::error::Error>| Errors::EncoderFailed(err.to_string());
+
+    // Same as `icy_sixel::sixel_string`, but with a configurable palette size.
+    let mut dither = sixel_dither::new(options.colors() as i32).map_err(encoder_failed)?;
+    dither
+        .initialize(
+            &bytes,
+            w,
+            h,
+            PixelFormat::RGBA8888,
+            MethodForLargest::Auto,
+            match options.quantization {
+                SixelQuantization::Auto => MethodForRep::Auto,
+                SixelQuantization::CenterBox => MethodForRep::CenterBox,
+                SixelQuantization::AverageColors => MethodForRep::AverageColors,
+                SixelQuantization::AveragePixels => MethodForRep::Pixels,
+            },
+            Quality::HIGH,
+        )
+        .map_err(encoder_failed)?;
+    dither.set_pixelformat(PixelFormat::RGBA8888);
+    dither.set_diffusion_type(match options.dither {
+        SixelDither::None => DiffusionMethod::None,
+        SixelDither::Stucki => DiffusionMethod::Stucki,
+        SixelDither::FloydSteinberg => DiffusionMethod::FS,
+        SixelDither::Atkinson => DiffusionMethod::Atkinson,
+        SixelDither::Ordered => DiffusionMethod::ADither,
+    });
+
+    // Save and restore the cursor around the image for [SixelPlacement::Overlay].
+    let overlay = options.placement == SixelPlacement::Overlay;
+    if overlay {
+        sixel_data.extend_from_slice(b"\x1b7");
+    }
+    let start = sixel_data.len();
+    let mut output = sixel_output::new(&mut sixel_data);
+    output.set_encode_policy(EncodePolicy::AUTO);
+    output
+        .encode(&mut bytes, w, h, 0, &mut dither)
+        .map_err(encoder_failed)?;
+    drop(output);
     if is_tmux {
-        if data.strip_prefix('\x1b').is_none() {
+        if sixel_data.get(start) != Some(&b'\x1b') {
             return Err("sixel string did not start with escape".into());
         }
+        wrap_tmux(&mut sixel_data, start);
+    }
+    if overlay {
+        sixel_data.extend_from_slice(b"\x1b8");
+    }
+    *data = String::from_utf8(sixel_data)
+        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned());
+    Ok(())
+}
 
-        let mut data_tmux = TMUX_START.to_string();
-        for ch in data.chars() {
-            if ch == '\x1b' {
-                data_tmux.push('\x1b');
-            }
-            data_tmux.push(ch);
+/// Wrap the sixel sequence in `data[start..]` in a tmux passthrough, doubling every escape.
+///
+/// The sequence is shifted in place from the back, so that every byte is moved only once.
+fn wrap_tmux(data: &mut Vec<u8>, start: usize) {
+    let len = data.len();
+    let escapes = data[start..]
+        .iter()
+        .filter(|&&byte| byte == b'\x1b')
+        .count();
+    data.resize(len + TMUX_START.len() + escapes, 0);
+    let mut to = data.len();
+    for from in (start..len).rev() {
+        let byte = data[from];
+        to -= 1;
+        data[to] = byte;
+        if byte == b'\x1b' {
+            to -= 1;
+            data[to] = byte;
         }
-        data_tmux.push('\x1b');
-        data_tmux.push('\\');
-        return Ok(data_tmux);
     }
-    Ok(data)
+    data[start..to].copy_from_slice(TMUX_START.as_bytes());
+    data.extend_from_slice(b"\x1b\\");
 }
 
 impl Protocol for Sixel {
@@ -88,6 +308,9 @@ impl Protocol for Sixel {
     fn rect(&self) -> Rect {
         self.rect
     }
+    fn encoded(&self) -> String {
+        self.data.clone()
+    }
 }
 
 fn render(rect: Rect, data: &str, area: Rect, buf: &mut Buffer, overdraw: bool) {
@@ -114,6 +337,10 @@ fn render(rect: Rect, data: &str, area: Rect, buf: &mut Buffer, overdraw: bool)
         }
         Some(r) => r,
     };
+    // A sixel sequence can only be drawn whole, see [StatefulSixel] for clipping.
+    if clip(buf, render_area) != Some(render_area) {
+        return;
+    }
 
     buf.get_mut(render_area.left(), render_area.top())
         .set_symbol(data);
@@ -132,6 +359,12 @@ fn render(rect: Rect, data: &str, area: Rect, buf: &mut Buffer, overdraw: bool)
 }
 
 fn render_area(rect: Rect, area: Rect, overdraw: bool) -> Option<Rect> {
+    let area = Rect::new(
+        area.x + rect.x,
+        area.y + rect.y,
+        area.width.saturating_sub(rect.x),
+        area.height.saturating_sub(rect.y),
+    );
     if overdraw {
         return Some(Rect::new(
             area.x,
@@ -147,11 +380,30 @@ fn render_area(rect: Rect, area: Rect, overdraw: bool) -> Option<Rect> {
     Some(Rect::new(area.x, area.y, rect.width, rect.height))
 }
 
+/// A resizing sixel protocol.
+///
+/// Sixel has no transparency, so images with an alpha channel are composited over the background
+/// color passed to [StatefulProtocol::resize_encode], or black if there is none.
+///
+/// If the image is only partially inside the buffer, e.g. in a scrolled viewport, only the visible
+/// part of the image is encoded and drawn.
+///
+/// If the sixel encoder fails, e.g. out of memory on a huge image, the image is drawn with
+/// halfblocks instead, and the error is kept, see [StatefulSixel::last_error].
 #[derive(Clone)]
 pub struct StatefulSixel {
     source: ImageSource,
     current: Sixel,
     hash: u64,
+    options: SixelOptions,
+    /// Whether the options changed since the last encode, see [StatefulSixel::set_options].
+    options_changed: bool,
+    /// The resized image of `current`, to encode visible parts of it.
+    image: Option<DynamicImage>,
+    /// The visible part of `current`, relative to its rect, and its encoding.
+    clipped: Option<(Rect, String)>,
+    /// The error of the last encode, and the halfblocks that are drawn instead.
+    failed: Option<(Arc<Errors>, Halfblocks)>,
 }
 
 impl StatefulSixel {
@@ -163,44 +415,425 @@ impl StatefulSixel {
                 ..Sixel::default()
             },
             hash: u64::default(),
+            options: SixelOptions::default(),
+            options_changed: false,
+            image: None,
+            clipped: None,
+            failed: None,
         }
     }
+
+    /// The error of the last encode, if the sixel encoder failed, e.g. for the app to log it.
+    ///
+    /// The image is drawn with halfblocks meanwhile. Cleared once an encode succeeds again, e.g.
+    /// after [StatefulSixel::set_options] or a new image.
+    pub fn last_error(&self) -> Option<&Errors> {
+        self.failed.as_ref().map(|(err, _)| err.as_ref())
+    }
+
+    /// Encode the part of the resized image that is inside `clip`, in cells, into `data`.
+    ///
+    /// The image may be shrunk to [SixelOptions::max_size], so the pixels of the cells are scaled
+    /// to the pixels of the image.
+    fn encode_clip(&self, clip: Rect, data: &mut String) -> Option<()> {
+        let image = self.image.as_ref()?;
+        let font_size = self.source.font_size;
+        let rect = self.current.rect;
+        let (full_width, full_height) = cells_to_pixels(font_size, rect.width, rect.height);
+        let scale = |pixels: u32, size: u32, full: u32| {
+            (u64::from(pixels) * u64::from(size) / u64::from(full.max(1))) as u32
+        };
+        let (x0, y0) = cells_to_pixels(font_size, clip.x, clip.y);
+        let (x1, y1) = cells_to_pixels(font_size, clip.right(), clip.bottom());
+        let (x0, x1) = (
+            scale(x0, image.width(), full_width),
+            scale(x1, image.width(), full_width),
+        );
+        let (y0, y1) = (
+            scale(y0, image.height(), full_height),
+            scale(y1, image.height(), full_height),
+        );
+        let cropped = image.crop_imm(x0, y0, x1 - x0, y1 - y0);
+        encode(&cropped, self.current.is_tmux, &self.options, data).ok()
+    }
+
+    /// Whether the current image was shrunk to fit [SixelOptions::max_size], see
+    /// [Sixel::clamped].
+    pub fn clamped(&self) -> bool {
+        self.current.clamped
+    }
+
+    /// Set the [SixelOptions] for the encoder. The image is encoded again on the next render.
+    pub fn set_options(&mut self, options: SixelOptions) {
+        if options != self.options {
+            self.options = options;
+            self.options_changed = true;
+        }
+    }
+
+    /// Whether the image must be resized and encoded again, even for the same area.
+    fn force(&self, resize: &Resize) -> bool {
+        self.options_changed || self.source.resize_hash(resize) != self.hash
+    }
 }
 
 impl StatefulProtocol for StatefulSixel {
     fn needs_resize(&mut self, resize: &Resize, area: Rect) -> Option<Rect> {
-        resize.needs_resize(&self.source, self.current.rect, area, false)
+        resize.needs_resize(&self.source, self.current.rect, area, self.force(resize))
     }
-    fn resize_encode(&mut self, resize: &Resize, background_color: Option<Rgb<u8>>, area: Rect) {
-        if area.width == 0 || area.height == 0 {
-            return;
-        }
-
-        let force = self.source.hash != self.hash;
-        if let Some((img, rect)) = resize.resize(
+    fn resize(
+        &self,
+        resize: &Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+    ) -> Option<ResizedImage> {
+        ResizedImage::new(
             &self.source,
             self.current.rect,
-            area,
+            self.force(resize),
+            resize,
             background_color,
-            force,
-        ) {
-            let is_tmux = self.current.is_tmux;
-            match encode(img, is_tmux) {
-                Ok(data) => {
-                    self.current = Sixel {
-                        data,
-                        rect,
-                        is_tmux,
-                    };
-                    self.hash = self.source.hash;
+            area,
+        )
+    }
+    fn encode(&mut self, resized: ResizedImage) {
+        let img = composite(resized.image, resized.background_color);
+        let (img, clamped) = clamp(img, &self.options, resized.resize.filter_type());
+        let is_tmux = self.current.is_tmux;
+        let mut data = std::mem::take(&mut self.current.data);
+        self.options_changed = false;
+        match encode(&img, is_tmux, &self.options, &mut data) {
+            Ok(()) => {
+                self.current = Sixel {
+                    data,
+                    rect: resized.rect,
+                    is_tmux,
+                    clamped,
+                };
+                self.hash = resized.hash;
+                self.image = Some(img);
+                self.clipped = None;
+                self.failed = None;
+            }
+            Err(err) => {
+                let fallback =
+                    Halfblocks::from_resized(&img, resized.rect, resized.background_color);
+                self.current = Sixel {
+                    data,
+                    rect: resized.rect,
+                    is_tmux,
+                    clamped,
+                };
+                // Not retried on every render, only once the image or the area changes.
+                self.hash = resized.hash;
+                self.image = None;
+                self.clipped = None;
+                self.failed = Some((Arc::new(err), fallback));
+            }
+        }
+    }
+    fn render(&mut self, area: Rect, buf: &mut Buffer) {
+        if let Some((_, fallback)) = &self.failed {
+            fallback.render(area, buf);
+            return;
+        }
+        let rect = self.current.rect;
+        let (full, visible) = match render_area(rect, area, true) {
+            Some(full) => (full, clip(buf, full)),
+            None => return,
+        };
+        match visible {
+            Some(visible) if visible == full => {
+                render(rect, &self.current.data, area, buf, true);
+            }
+            Some(visible) => {
+                let clip = Rect::new(
+                    visible.x - full.x,
+                    visible.y - full.y,
+                    visible.width,
+                    visible.height,
+                );
+                if self.clipped.as_ref().map(|(c, _)| *c) != Some(clip) {
+                    let mut data = self
+                        .clipped
+                        .take()
+                        .map(|(_, data)| data)
+                        .unwrap_or_default();
+                    self.clipped = self.encode_clip(clip, &mut data).map(|()| (clip, data));
                 }
-                Err(_err) => {
-                    // TODO: save err in struct and expose in trait?
+                if let Some((_, data)) = &self.clipped {
+                    let rect = Rect::new(0, 0, visible.width, visible.height);
+                    render(rect, data, visible, buf, true);
                 }
             }
+            None => {}
         }
     }
-    fn render(&mut self, area: Rect, buf: &mut Buffer) {
-        render(self.current.rect, &self.current.data, area, buf, true);
+    fn rect(&self) -> Rect {
+        self.current.rect
+    }
+    fn encoded(&self) -> String {
+        self.current.encoded()
+    }
+    fn set_source(&mut self, source: ImageSource) {
+        self.source = source;
+    }
+    fn advance_frame(&mut self) {
+        self.source.advance_frame();
+    }
+    fn frame_delay(&self) -> Option<Duration> {
+        self.source.frame_delay()
+    }
+    fn clear(&mut self, area: Rect, buf: &mut Buffer) {
+        clear_erase(self.rect(), area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, ImageBuffer, Rgb};
+    use ratatui::{buffer::Buffer, layout::Rect};
+
+    use super::{
+        encode, Errors, Sixel, SixelDither, SixelOptions, SixelPlacement, SixelQuantization,
+        StatefulSixel,
+    };
+    use crate::{
+        protocol::{ImageSource, StatefulProtocol},
+        Resize, Result,
+    };
+
+    fn encoded(image: &DynamicImage, is_tmux: bool, options: &SixelOptions) -> Result<String> {
+        let mut data = String::new();
+        encode(image, is_tmux, options, &mut data)?;
+        Ok(data)
+    }
+
+    /// Count the color definitions (`#<n>;2;<r>;<g>;<b>`) in a sixel sequence.
+    fn palette_colors(data: &str) -> usize {
+        data.split('#')
+            .skip(1)
+            .filter(|register| register.split(';').nth(1) == Some("2"))
+            .count()
+    }
+
+    #[test]
+    fn palette_size() {
+        let image: DynamicImage =
+            ImageBuffer::from_fn(64, 64, |x, y| Rgb([(x * 4) as u8, (y * 4) as u8, 128])).into();
+
+        let full = encoded(&image, false, &SixelOptions::default()).unwrap();
+        let small = SixelOptions {
+            palette_size: 16,
+            ..SixelOptions::default()
+        };
+        let small = encoded(&image, false, &small).unwrap();
+        assert!(palette_colors(&small) <= 16);
+        assert!(palette_colors(&full) > 16);
+        assert!(small.len() < full.len());
+    }
+
+    #[test]
+    fn placement() {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 6, Rgb([255u8, 0, 0])).into();
+        let inline = encoded(&image, false, &SixelOptions::default()).unwrap();
+        assert!(inline.starts_with("\x1bP"));
+        let options = SixelOptions {
+            placement: SixelPlacement::Overlay,
+            ..SixelOptions::default()
+        };
+        let overlay = encoded(&image, false, &options).unwrap();
+        assert_eq!(format!("\x1b7{inline}\x1b8"), overlay);
+        let tmux = encoded(&image, true, &options).unwrap();
+        let escaped = inline.replace('\x1b', "\x1b\x1b");
+        assert_eq!(format!("\x1b7\x1bPtmux;{escaped}\x1b\\\x1b8"), tmux);
+
+        // A previous encode is overwritten.
+        let mut data = tmux;
+        encode(&image, false, &SixelOptions::default(), &mut data).unwrap();
+        assert_eq!(inline, data);
+    }
+
+    #[test]
+    fn colors_clamped() {
+        let mut options = SixelOptions::default();
+        assert_eq!(256, options.colors());
+        options.palette_size = 1024;
+        assert_eq!(256, options.colors());
+        options.palette_size = 0;
+        assert_eq!(2, options.colors());
+        options.palette_size = 256;
+        options.color_registers = Some(16);
+        assert_eq!(16, options.colors());
+        options.color_registers = Some(1024);
+        assert_eq!(256, options.colors());
+    }
+
+    #[test]
+    fn dither() {
+        let image: DynamicImage =
+            ImageBuffer::from_fn(64, 64, |x, _| Rgb([(x * 4) as u8, 0, 0])).into();
+        let options = SixelOptions {
+            palette_size: 4,
+            ..SixelOptions::default()
+        };
+        let dithered = encoded(&image, false, &options).unwrap();
+        let stucki = SixelOptions {
+            dither: SixelDither::Stucki,
+            ..options
+        };
+        assert_eq!(dithered, encoded(&image, false, &stucki).unwrap());
+        let flat = SixelOptions {
+            dither: SixelDither::None,
+            ..options
+        };
+        let flat = encoded(&image, false, &flat).unwrap();
+        assert_ne!(dithered, flat);
+        assert!(flat.len() < dithered.len());
+
+        let ordered = SixelOptions {
+            dither: SixelDither::Ordered,
+            ..options
+        };
+        let ordered = encoded(&image, false, &ordered).unwrap();
+        assert_ne!(dithered, ordered);
+        assert_ne!(flat, ordered);
+    }
+
+    #[test]
+    fn quantization() {
+        let image: DynamicImage =
+            ImageBuffer::from_fn(64, 64, |x, y| Rgb([(x * 4) as u8, (y * 4) as u8, 0])).into();
+        let encode_with = |quantization| {
+            let options = SixelOptions {
+                palette_size: 4,
+                dither: SixelDither::None,
+                quantization,
+                ..SixelOptions::default()
+            };
+            encoded(&image, false, &options).unwrap()
+        };
+        assert_ne!(
+            encode_with(SixelQuantization::CenterBox),
+            encode_with(SixelQuantization::AveragePixels)
+        );
+    }
+
+    #[test]
+    fn max_size() {
+        let image: DynamicImage = ImageBuffer::from_pixel(10, 2000, Rgb([255u8, 0, 0])).into();
+        let options = SixelOptions {
+            max_size: Some((1000, 1000)),
+            ..SixelOptions::default()
+        };
+        let source = ImageSource::new(image, (10, 20));
+        let area = Rect::new(0, 0, 1, 100);
+        let sixel =
+            Sixel::from_source_with_options(&source, Resize::Fit(None), None, false, area, options)
+                .unwrap();
+        assert!(sixel.clamped);
+        assert_eq!(Rect::new(0, 0, 1, 100), sixel.rect);
+        // Raster attributes: aspect ratio 1:1, 5x1000 pixels.
+        assert!(sixel.data.contains("\"1;1;5;1000"));
+
+        let mut sixel = StatefulSixel::new(source, false);
+        sixel.resize_encode(&Resize::Fit(None), None, Rect::new(0, 0, 1, 40));
+        assert!(!sixel.clamped());
+        sixel.set_options(options);
+        sixel.resize_encode(&Resize::Fit(None), None, area);
+        assert!(sixel.clamped());
+    }
+
+    #[test]
+    fn clipped_to_buffer() {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 12, Rgb([255u8, 0, 0])).into();
+        let mut sixel = StatefulSixel::new(ImageSource::new(image, (2, 6)), false);
+        let area = Rect::new(0, 0, 2, 2);
+
+        let mut buf = Buffer::empty(area);
+        sixel.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        let full = buf.get(0, 0).symbol.clone();
+        assert!(full.starts_with("\x1bP"));
+        assert_eq!(None, sixel.clipped);
+
+        // Only the bottom row of cells is inside the buffer.
+        let mut buf = Buffer::empty(Rect::new(0, 1, 2, 1));
+        sixel.render(area, &mut buf);
+        let clipped = &buf.get(0, 1).symbol;
+        assert!(clipped.starts_with("\x1bP"));
+        assert_ne!(&full, clipped);
+        assert_eq!(
+            Some(Rect::new(0, 1, 2, 1)),
+            sixel.clipped.map(|(rect, _)| rect)
+        );
+    }
+
+    #[test]
+    fn clipped_max_size() {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 12, Rgb([255u8, 0, 0])).into();
+        let mut sixel = StatefulSixel::new(ImageSource::new(image, (2, 6)), false);
+        // Half the size of the 4x12 pixels of the 2x2 cells.
+        sixel.set_options(SixelOptions {
+            max_size: Some((2, 6)),
+            ..SixelOptions::default()
+        });
+        let area = Rect::new(0, 0, 2, 2);
+        let mut buf = Buffer::empty(area);
+        sixel.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        assert!(sixel.clamped());
+
+        // The bottom row of cells is the bottom half of the shrunk image.
+        let mut buf = Buffer::empty(Rect::new(0, 1, 2, 1));
+        sixel.render(area, &mut buf);
+        // Raster attributes: aspect ratio 1:1, 2x3 pixels.
+        assert!(buf.get(0, 1).symbol.contains("\"1;1;2;3"));
+    }
+
+    #[test]
+    fn clear() {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 12, Rgb([255u8, 0, 0])).into();
+        let mut sixel = StatefulSixel::new(ImageSource::new(image, (2, 6)), false);
+        let area = Rect::new(0, 0, 2, 2);
+        let mut buf = Buffer::empty(area);
+        sixel.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        assert!(buf.get(1, 1).skip);
+
+        sixel.clear(area, &mut buf);
+        assert_eq!("\x1b7\x1b[2X\x1b[1B\x1b[2X\x1b8 ", buf.get(0, 0).symbol);
+        assert!(!buf.get(1, 1).skip);
+        assert_eq!(" ", buf.get(1, 1).symbol);
+    }
+
+    #[test]
+    fn failed() {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 12, Rgb([255u8, 0, 0])).into();
+        let mut sixel = StatefulSixel::new(ImageSource::new(image, (2, 6)), false);
+        let area = Rect::new(0, 0, 2, 2);
+        let mut buf = Buffer::empty(area);
+        sixel.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        assert!(sixel.last_error().is_none());
+
+        // The encoder rejects empty images.
+        let blue: DynamicImage = ImageBuffer::from_pixel(4, 12, Rgb([0u8, 0, 255])).into();
+        sixel.set_source(ImageSource::new(blue, (2, 6)));
+        let mut resized = sixel.resize(&Resize::Fit(None), None, area).unwrap();
+        resized.image = DynamicImage::new_rgb8(0, 0);
+        sixel.encode(resized);
+        assert!(matches!(sixel.last_error(), Some(Errors::EncoderFailed(_))));
+        assert_eq!(area, sixel.rect());
+        let mut buf = Buffer::empty(area);
+        sixel.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        assert_eq!("▀", buf.get(0, 0).symbol);
+        assert_eq!("▀", buf.get(1, 1).symbol);
+
+        // A new encode succeeds again.
+        sixel.set_options(SixelOptions {
+            palette_size: 16,
+            ..SixelOptions::default()
+        });
+        let mut buf = Buffer::empty(area);
+        sixel.resize_encode_render(&Resize::Fit(None), None, area, &mut buf);
+        assert!(sixel.last_error().is_none());
+        assert!(buf.get(0, 0).symbol.starts_with("\x1bP"));
     }
 }
@@ -0,0 +1,243 @@
+//! Text protocol implementation.
+//!
+//! Draws no image, but a box with a label like `[img 640x480]` in the cells of the image, so that
+//! layouts still work where no graphics are available, e.g. in CI or snapshot tests. No escape
+//! sequences are written, and resizing only computes the size of the image.
+use std::time::Duration;
+
+use image::Rgb;
+use ratatui::{buffer::Buffer, layout::Rect, style::Style};
+
+use super::{in_buffer, Protocol, ResizedImage, StatefulProtocol};
+use crate::{ImageSource, Resize};
+
+/// Options for the text protocol.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextOptions {
+    /// Style of the box and label. Defaults to leaving the style of the cells unchanged.
+    pub style: Style,
+}
+
+// Fixed Text protocol
+#[derive(Clone, Default)]
+pub struct Text {
+    rect: Rect,
+    label: String,
+    options: TextOptions,
+}
+
+impl Text {
+    /// Create a Text with the size that the image would have with `resize` in `area`, labeled
+    /// with the size of the image in pixels.
+    pub fn from_source(
+        source: &ImageSource,
+        resize: Resize,
+        area: Rect,
+        options: TextOptions,
+    ) -> Self {
+        let rect = resize
+            .needs_resize(source, Rect::default(), area, false)
+            .unwrap_or(source.desired);
+        Self {
+            rect,
+            label: label(source),
+            options,
+        }
+    }
+}
+
+impl Protocol for Text {
+    fn render(&self, area: Rect, buf: &mut Buffer) {
+        let lines = lines(self.rect, &self.label);
+        for (y, line) in (self.rect.top()..).zip(lines) {
+            for (x, ch) in (self.rect.left()..).zip(line) {
+                if x >= area.width || y >= area.height || !in_buffer(buf, area.x + x, area.y + y) {
+                    continue;
+                }
+                buf.get_mut(area.x + x, area.y + y)
+                    .set_char(ch)
+                    .set_style(self.options.style);
+            }
+        }
+    }
+
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// One line of the box per cell row.
+    fn encoded(&self) -> String {
+        lines(self.rect, &self.label)
+            .into_iter()
+            .map(|line| line.into_iter().chain(['\n']).collect::<String>())
+            .collect()
+    }
+}
+
+fn label(source: &ImageSource) -> String {
+    let (width, height) = source.pixel_size();
+    format!("[img {width}x{height}]")
+}
+
+/// The characters of each row of the box, with the label centered on the middle row and cut off
+/// if it is too long. The box is left out if the rect is less than 3 cells high or wide.
+fn lines(rect: Rect, label: &str) -> Vec<Vec<char>> {
+    let (width, height) = (usize::from(rect.width), usize::from(rect.height));
+    let border = width >= 3 && height >= 3;
+    let mut lines: Vec<Vec<char>> = (0..height)
+        .map(|y| {
+            if !border {
+                return vec![' '; width];
+            }
+            let (left, fill, right) = match y {
+                0 => ('┌', '─', '┐'),
+                y if y == height - 1 => ('└', '─', '┘'),
+                _ => ('│', ' ', '│'),
+            };
+            let mut line = vec![fill; width];
+            line[0] = left;
+            line[width - 1] = right;
+            line
+        })
+        .collect();
+
+    let inner = if border { 1..width - 1 } else { 0..width };
+    let label: Vec<char> = label.chars().take(inner.len()).collect();
+    if let Some(line) = lines.get_mut(height / 2) {
+        let start = inner.start + (inner.len() - label.len()) / 2;
+        line[start..start + label.len()].copy_from_slice(&label);
+    }
+    lines
+}
+
+/// A resizing text protocol.
+#[derive(Clone)]
+pub struct StatefulText {
+    source: ImageSource,
+    current: Text,
+    hash: u64,
+}
+
+impl StatefulText {
+    pub fn new(source: ImageSource, options: TextOptions) -> StatefulText {
+        StatefulText {
+            source,
+            current: Text {
+                options,
+                ..Text::default()
+            },
+            hash: u64::default(),
+        }
+    }
+}
+
+impl StatefulProtocol for StatefulText {
+    fn needs_resize(&mut self, resize: &Resize, area: Rect) -> Option<Rect> {
+        let force = self.source.resize_hash(resize) != self.hash;
+        resize.needs_resize(&self.source, self.current.rect, area, force)
+    }
+    fn resize(
+        &self,
+        resize: &Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+    ) -> Option<ResizedImage> {
+        ResizedImage::rect_only(
+            &self.source,
+            self.current.rect,
+            self.source.resize_hash(resize) != self.hash,
+            resize,
+            background_color,
+            area,
+        )
+    }
+    fn encode(&mut self, resized: ResizedImage) {
+        self.current.rect = resized.rect;
+        self.current.label = label(&self.source);
+        self.hash = resized.hash;
+    }
+    fn render(&mut self, area: Rect, buf: &mut Buffer) {
+        self.current.render(area, buf);
+    }
+    fn rect(&self) -> Rect {
+        self.current.rect
+    }
+    fn encoded(&self) -> String {
+        self.current.encoded()
+    }
+    fn set_source(&mut self, source: ImageSource) {
+        self.source = source;
+    }
+    fn advance_frame(&mut self) {
+        self.source.advance_frame();
+    }
+    fn frame_delay(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, ImageBuffer, Rgb};
+    use ratatui::{buffer::Buffer, layout::Rect};
+
+    use super::{StatefulText, Text, TextOptions};
+    use crate::{
+        protocol::{ImageSource, Protocol, StatefulProtocol},
+        Resize,
+    };
+
+    fn source() -> ImageSource {
+        let image: DynamicImage = ImageBuffer::from_pixel(64, 48, Rgb([255u8, 0, 0])).into();
+        ImageSource::new(image, (4, 8))
+    }
+
+    #[test]
+    fn label() {
+        let area = Rect::new(0, 0, 20, 10);
+        let text = Text::from_source(&source(), Resize::Fit(None), area, TextOptions::default());
+        assert_eq!(Rect::new(0, 0, 16, 6), text.rect());
+        assert_eq!(
+            concat!(
+                "┌──────────────┐\n",
+                "│              │\n",
+                "│              │\n",
+                "│ [img 64x48]  │\n",
+                "│              │\n",
+                "└──────────────┘\n",
+            ),
+            text.encoded()
+        );
+
+        let mut buf = Buffer::empty(area);
+        text.render(area, &mut buf);
+        assert_eq!("┌", buf.get(0, 0).symbol);
+        assert_eq!("[", buf.get(2, 3).symbol);
+        assert_eq!(" ", buf.get(16, 0).symbol);
+    }
+
+    #[test]
+    fn small() {
+        // Too small for the box, and the label is cut off.
+        let text = Text::from_source(
+            &source(),
+            Resize::Fit(None),
+            Rect::new(0, 0, 8, 2),
+            TextOptions::default(),
+        );
+        assert_eq!(Rect::new(0, 0, 5, 2), text.rect());
+        assert_eq!("     \n[img \n", text.encoded());
+    }
+
+    #[test]
+    fn stateful() {
+        let mut text = StatefulText::new(source(), TextOptions::default());
+        let (resize, area) = (Resize::Fit(None), Rect::new(0, 0, 3, 3));
+        let mut buf = Buffer::empty(area);
+        text.resize_encode_render(&resize, None, area, &mut buf);
+        assert_eq!(Rect::new(0, 0, 3, 1), text.rect());
+        assert_eq!(None, text.needs_resize(&resize, area));
+        assert_eq!("[im\n", text.encoded());
+        assert_eq!("m", buf.get(2, 0).symbol);
+    }
+}
@@ -0,0 +1,332 @@
+//! Offload resizing and encoding of a [StatefulProtocol] from the UI thread.
+//!
+//! The resizing and encoding is CPU-bound, so it runs on a separate thread. [ResizeWorker] is a
+//! long-lived worker thread that hands back protocols over a callback, e.g. to an event loop.
+//!
+//! With the `async` feature, [resize_encode_async] returns a future that resolves once it is done.
+//! The work runs on a pool of blocking threads, one per core, like tokio's `spawn_blocking`. The
+//! future is not tied to any async runtime, so it can be awaited e.g. in a tokio app without a
+//! tokio dependency in this crate.
+#[cfg(feature = "async")]
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll, Waker},
+};
+use std::{
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+use image::Rgb;
+use ratatui::layout::Rect;
+
+use crate::{protocol::StatefulProtocol, Resize};
+
+/// A background thread that resizes and encodes [StatefulProtocol]s.
+///
+/// When [StatefulProtocol::needs_resize] returns a rect, send the protocol to the worker with
+/// [ResizeWorker::request] instead of rendering it. The worker calls
+/// [StatefulProtocol::resize_encode] and passes the protocol to the `on_done` callback, e.g. to
+/// send it back to the event loop, which can then render it. See `examples/async.rs`.
+///
+/// Requests that queue up while the worker is busy are coalesced: only the latest one is
+/// processed, and the protocols of the others are dropped. The thread stops when the worker is
+/// dropped.
+///
+/// # Example
+/// ```rust
+/// # fn example(protocol: ratatui_image::protocol::StatefulBlock) {
+/// use std::sync::mpsc;
+/// use ratatui::layout::Rect;
+/// use ratatui_image::{protocol::StatefulProtocol, thread::ResizeWorker, Resize};
+///
+/// let (tx, rx) = mpsc::channel();
+/// let worker = ResizeWorker::new(move |protocol| {
+///     let _ = tx.send(protocol);
+/// });
+///
+/// let area = Rect::new(0, 0, 40, 20);
+/// worker.request(protocol, Resize::Fit(None), None, area).ok();
+/// // Later, e.g. in the event loop:
+/// let protocol = rx.recv().unwrap();
+/// # }
+/// ```
+pub struct ResizeWorker<P> {
+    requests: Sender<Request<P>>,
+}
+
+struct Request<P> {
+    protocol: P,
+    resize: Resize,
+    background_color: Option<Rgb<u8>>,
+    area: Rect,
+}
+
+impl<P> ResizeWorker<P>
+where
+    P: StatefulProtocol + Send + 'static,
+{
+    /// Spawn the worker thread. `on_done` is called on the worker thread with each protocol that
+    /// has been resized and encoded.
+    pub fn new<F>(mut on_done: F) -> ResizeWorker<P>
+    where
+        F: FnMut(P) + Send + 'static,
+    {
+        let (requests, rx) = mpsc::channel::<Request<P>>();
+        thread::spawn(move || {
+            while let Ok(mut request) = rx.recv() {
+                // Skip to the latest request.
+                while let Ok(next) = rx.try_recv() {
+                    request = next;
+                }
+                let Request {
+                    mut protocol,
+                    resize,
+                    background_color,
+                    area,
+                } = request;
+                protocol.resize_encode(&resize, background_color, area);
+                on_done(protocol);
+            }
+        });
+        ResizeWorker { requests }
+    }
+
+    /// Queue `protocol` to be resized and encoded for `area`, usually the rect returned by
+    /// [StatefulProtocol::needs_resize].
+    ///
+    /// Returns the protocol back if the worker thread is not running anymore, e.g. because
+    /// `on_done` panicked.
+    pub fn request(
+        &self,
+        protocol: P,
+        resize: Resize,
+        background_color: Option<Rgb<u8>>,
+        area: Rect,
+    ) -> Result<(), P> {
+        self.requests
+            .send(Request {
+                protocol,
+                resize,
+                background_color,
+                area,
+            })
+            .map_err(|err| err.0.protocol)
+    }
+}
+
+/// Resize and encode `protocol` on a pool of threads. Needs the `async` feature.
+///
+/// The threads of the pool are spawned on first use, one per core, and are reused for all calls,
+/// so that many images resizing at once do not spawn a thread each. Resolves to the updated
+/// protocol, which can then be rendered. The synchronous [StatefulProtocol::resize_encode] is
+/// unchanged. If resizing or encoding panics, the panic is resumed when awaiting the future. See
+/// `examples/tokio.rs` for a tokio event loop.
+///
+/// # Example
+/// ```rust
+/// # async fn example(mut protocol: ratatui_image::protocol::StatefulBlock) {
+/// use ratatui::layout::Rect;
+/// use ratatui_image::{protocol::StatefulProtocol, thread::resize_encode_async, Resize};
+///
+/// let area = Rect::new(0, 0, 40, 20);
+/// if let Some(rect) = protocol.needs_resize(&Resize::Fit(None), area) {
+///     protocol = resize_encode_async(protocol, Resize::Fit(None), None, rect).await;
+/// }
+/// # }
+/// ```
+#[cfg(feature = "async")]
+pub fn resize_encode_async<P>(
+    mut protocol: P,
+    resize: Resize,
+    background_color: Option<Rgb<u8>>,
+    area: Rect,
+) -> ResizeEncode<P>
+where
+    P: StatefulProtocol + Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        result: None,
+        waker: None,
+    }));
+    let thread_shared = shared.clone();
+    spawn_blocking(Box::new(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(move || {
+            protocol.resize_encode(&resize, background_color, area);
+            protocol
+        }));
+        let mut shared = thread_shared.lock().unwrap_or_else(PoisonError::into_inner);
+        shared.result = Some(result);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }));
+    ResizeEncode { shared }
+}
+
+/// A job for the pool of [resize_encode_async].
+#[cfg(feature = "async")]
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The pool of [resize_encode_async], spawned on first use. The threads run as long as the
+/// process, since the sender is never dropped.
+#[cfg(feature = "async")]
+static POOL: Mutex<Option<Sender<Job>>> = Mutex::new(None);
+
+/// Run `job` on the pool. Jobs must not panic, or the pool loses a thread.
+#[cfg(feature = "async")]
+fn spawn_blocking(job: Job) {
+    let mut pool = POOL.lock().unwrap_or_else(PoisonError::into_inner);
+    let sender = pool.get_or_insert_with(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        for _ in 0..threads {
+            let receiver = receiver.clone();
+            thread::spawn(move || loop {
+                // The lock is only held while waiting for a job, not while running it.
+                let job = receiver
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => return,
+                }
+            });
+        }
+        sender
+    });
+    // The receiver lives as long as the threads, which never stop.
+    let _ = sender.send(job);
+}
+
+/// Future returned by [resize_encode_async].
+#[cfg(feature = "async")]
+pub struct ResizeEncode<P> {
+    shared: Arc<Mutex<Shared<P>>>,
+}
+
+#[cfg(feature = "async")]
+struct Shared<P> {
+    result: Option<thread::Result<P>>,
+    waker: Option<Waker>,
+}
+
+#[cfg(feature = "async")]
+impl<P> Future for ResizeEncode<P> {
+    type Output = P;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<P> {
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        match shared.result.take() {
+            Some(Ok(protocol)) => Poll::Ready(protocol),
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "async")]
+    use std::{
+        future::Future,
+        sync::Arc,
+        task::{Context, Poll, Wake},
+        thread::{self, Thread},
+    };
+    use std::{sync::mpsc, time::Duration};
+
+    use image::{DynamicImage, ImageBuffer, Rgb};
+    use ratatui::{buffer::Buffer, layout::Rect};
+
+    #[cfg(feature = "async")]
+    use super::resize_encode_async;
+    use super::ResizeWorker;
+    use crate::{
+        protocol::{halfblocks::StatefulHalfblocks, ImageSource, StatefulProtocol},
+        Resize,
+    };
+
+    fn protocol() -> StatefulHalfblocks {
+        let image: DynamicImage = ImageBuffer::from_pixel(4, 4, Rgb([255u8, 0, 0])).into();
+        StatefulHalfblocks::new(ImageSource::new(image, (1, 2)))
+    }
+
+    #[cfg(feature = "async")]
+    struct ThreadWaker(Thread);
+
+    #[cfg(feature = "async")]
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    #[cfg(feature = "async")]
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn resize_encode() {
+        let area = Rect::new(0, 0, 4, 2);
+        let mut protocol = block_on(resize_encode_async(
+            protocol(),
+            Resize::Fit(None),
+            None,
+            area,
+        ));
+        assert_eq!(None, protocol.needs_resize(&Resize::Fit(None), area));
+        let mut buf = Buffer::empty(area);
+        protocol.render(area, &mut buf);
+        assert_eq!("▀", buf.get(3, 1).symbol);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn resize_encode_many() {
+        let area = Rect::new(0, 0, 4, 2);
+        let futures: Vec<_> = (0..64)
+            .map(|_| resize_encode_async(protocol(), Resize::Fit(None), None, area))
+            .collect();
+        for future in futures {
+            let mut protocol = block_on(future);
+            assert_eq!(None, protocol.needs_resize(&Resize::Fit(None), area));
+        }
+    }
+
+    #[test]
+    fn worker() {
+        let (tx, rx) = mpsc::channel();
+        let worker = ResizeWorker::new(move |protocol| {
+            let _ = tx.send(protocol);
+        });
+        let area = Rect::new(0, 0, 4, 2);
+        assert!(worker
+            .request(protocol(), Resize::Fit(None), None, area)
+            .is_ok());
+
+        let mut protocol = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(None, protocol.needs_resize(&Resize::Fit(None), area));
+        let mut buf = Buffer::empty(area);
+        protocol.render(area, &mut buf);
+        assert_eq!("▀", buf.get(3, 1).symbol);
+    }
+}